@@ -0,0 +1,87 @@
+use std::hint::black_box;
+use std::io::{Read, Write};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use sps30_hdlc::{Command, Frame, Sps30, checksum};
+
+// shared with the tests, which check them; only some are benchmarked
+#[allow(dead_code)]
+#[path = "../tests/fixtures/mod.rs"]
+mod fixtures;
+
+/// Transport that discards writes and answers every transaction with the same
+/// canned response.
+struct InMemoryPort {
+    response: &'static [u8],
+    pos: usize,
+}
+
+impl Read for InMemoryPort {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = (&self.response[self.pos..]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Write for InMemoryPort {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // a new request starts a new transaction
+        self.pos = 0;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn encode_request(c: &mut Criterion) {
+    c.bench_function("encode ReadMeasuredValue", |b| {
+        b.iter(|| {
+            let f = Frame {
+                addr: 0,
+                cmd: Command::ReadMeasuredValue,
+                data: Vec::new(),
+            };
+            black_box(f.encode().unwrap())
+        })
+    });
+}
+
+fn decode_response(c: &mut Criterion) {
+    c.bench_function("decode measurement response", |b| {
+        b.iter(|| Frame::decode(black_box(fixtures::READ_MEASURED_VALUE_RESPONSE)).unwrap())
+    });
+}
+
+fn checksum_payload(c: &mut Criterion) {
+    // unstuffed frame content of the measurement response, without delimiters
+    // or the trailing checksum byte
+    let mut content = fixtures::READ_MEASURED_VALUE_RESPONSE[1..42].to_vec();
+    content.push(0x11);
+    content.extend_from_slice(&fixtures::READ_MEASURED_VALUE_RESPONSE[44..46]);
+
+    c.bench_function("checksum measurement response", |b| {
+        b.iter(|| checksum(black_box(&content)))
+    });
+}
+
+fn transaction(c: &mut Criterion) {
+    let port = InMemoryPort {
+        response: fixtures::READ_MEASURED_VALUE_RESPONSE,
+        pos: 0,
+    };
+    let mut sensor = Sps30::new(port);
+    c.bench_function("read_measurement transaction", |b| {
+        b.iter(|| black_box(sensor.read_measurement().unwrap()))
+    });
+}
+
+criterion_group!(
+    benches,
+    encode_request,
+    decode_response,
+    checksum_payload,
+    transaction
+);
+criterion_main!(benches);
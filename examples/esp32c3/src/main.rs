@@ -0,0 +1,86 @@
+//! Reading an SPS30 from an ESP32-C3 over UART, with the driver built for
+//! no_std.
+//!
+//! Wiring (SPS30 pin: ESP32-C3 DevKitM-1 pin):
+//!
+//! - 1 VDD: 5V
+//! - 2 RX: GPIO21 (UART1 TX)
+//! - 3 TX: GPIO20 (UART1 RX)
+//! - 4 SEL: leave floating for UART
+//! - 5 GND: GND
+//!
+//! The sensor's TX idles at 3.3 V, so it can go straight to the GPIO.
+//!
+//! Build and flash from this directory with `cargo run --release`, which
+//! needs `espflash` installed. Output is logged through defmt.
+#![no_std]
+#![no_main]
+
+use esp_backtrace as _;
+use esp_hal::{
+    delay::Delay,
+    uart::{Config as UartConfig, Uart},
+};
+use esp_println as _;
+use sps30_hdlc::{Sps30, Unit};
+
+/// The driver takes a plain function for the waits some commands need, so it
+/// doesn't have to hold on to a delay peripheral.
+fn delay_ms(ms: u32) {
+    Delay::new().delay_millis(ms);
+}
+
+#[esp_hal::main]
+fn main() -> ! {
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+    // the driver allocates its frames
+    esp_alloc::heap_allocator!(32 * 1024);
+
+    // the SPS30's UART is fixed at 115200 8N1
+    let uart = Uart::new(
+        peripherals.UART1,
+        UartConfig::default().with_baudrate(115200),
+    )
+    .unwrap()
+    .with_rx(peripherals.GPIO20)
+    .with_tx(peripherals.GPIO21);
+
+    // esp-hal's UART implements embedded_io's Read and Write, which is all
+    // the driver needs. Its reads block until at least one byte arrives, so
+    // a sensor that stops answering stalls the loop below.
+    let mut sensor = Sps30::new(uart);
+    sensor.set_delay(Some(delay_ms));
+    let delay = Delay::new();
+
+    if sensor.device_reset().is_err() {
+        defmt::warn!("Reset failed");
+    }
+    // give the sensor time to boot after the reset
+    delay.delay_millis(100);
+
+    match sensor.read_serial_number() {
+        Some(serial) => defmt::info!("SPS30 {}", serial.as_str()),
+        None => defmt::warn!("No answer from the sensor, check the wiring"),
+    }
+
+    while sensor.start_measurement().is_err() {
+        defmt::warn!("Can't start measuring, retrying");
+        delay.delay_millis(1000);
+    }
+
+    loop {
+        delay.delay_millis(1000);
+        match sensor.read_measurement() {
+            Ok(Some(m)) => defmt::info!(
+                "PM1.0 {} PM2.5 {} PM4.0 {} PM10 {} {}",
+                m.mass_1_0,
+                m.mass_2_5,
+                m.mass_4_0,
+                m.mass_10,
+                Unit::MicrogramsPerCubicMeter.ascii_symbol()
+            ),
+            Ok(None) => (),
+            Err(_) => defmt::warn!("Read failed"),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Reading an SPS30 wired straight to a Raspberry Pi's GPIO UART.
+//!
+//! Wiring (SPS30 pin: Pi header pin):
+//!
+//! - 1 VDD: 2 (5 V for the fan; the sensor's TX still idles at 3.3 V)
+//! - 2 RX: 8 (GPIO14, TXD)
+//! - 3 TX: 10 (GPIO15, RXD)
+//! - 4 SEL: leave floating for UART
+//! - 5 GND: 6
+//!
+//! Before running it:
+//!
+//! - Turn off the login shell on the serial port but keep the port enabled:
+//!   `raspi-config` → Interface Options → Serial Port → "No" then "Yes". Left
+//!   on, the console's output is mixed in with the sensor's and frames come
+//!   back garbled.
+//! - On boards with Bluetooth, `/dev/serial0` is the mini UART, whose baud
+//!   rate follows the VPU clock and drifts when it scales. Either add
+//!   `dtoverlay=disable-bt` (or `miniuart-bt`) to `/boot/firmware/config.txt`
+//!   to get the PL011 back, or pin the clock with `core_freq=250`.
+//! - Add yourself to the `dialout` group to open the port without root.
+//!
+//! Run with `cargo run --example raspberry_pi [-- /dev/serial0]`.
+use std::{env, process::exit, thread::sleep, time::Duration};
+
+use serialport::ErrorKind;
+use sps30_hdlc::{DeviceModel, RetryPolicy, Sps30, Unit};
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or("/dev/serial0".to_string());
+
+    // open the port by hand rather than through Sps30::open to tell the
+    // usual failures apart
+    let port = match serialport::new(&path, 115200)
+        .timeout(Duration::from_millis(20))
+        .open()
+    {
+        Ok(p) => p,
+        Err(e) => {
+            match e.kind() {
+                ErrorKind::NoDevice => eprintln!(
+                    "{} doesn't exist. Enable the serial port with raspi-config and reboot.",
+                    path
+                ),
+                ErrorKind::Io(std::io::ErrorKind::PermissionDenied) => eprintln!(
+                    "No permission to open {}. Run `sudo usermod -aG dialout $USER` and log in again.",
+                    path
+                ),
+                _ => eprintln!("Can't open {}: {}", path, e),
+            }
+            exit(1);
+        }
+    };
+    port.clear(serialport::ClearBuffer::Input).ok();
+
+    let mut sensor = Sps30::new(port);
+    if let Err(e) = sensor.set_timeout(Duration::from_secs(1)) {
+        eprintln!("Can't set the timeout on {}: {}", path, e);
+        exit(1);
+    }
+    sensor.set_retry_policy(RetryPolicy {
+        retries: 2,
+        ..Default::default()
+    });
+
+    match sensor.identify() {
+        Ok(DeviceModel::Sps30) => (),
+        Ok(m) => {
+            eprintln!("Unexpected device on {}: {}", path, m);
+            exit(1);
+        }
+        Err(_) => {
+            eprintln!(
+                "No valid answer on {}. If frames are garbled, check the serial console is \
+                 disabled and the mini UART isn't in use (see the notes at the top of \
+                 this example); if there's no answer at all, check TX and RX aren't swapped.",
+                path
+            );
+            exit(1);
+        }
+    }
+    println!(
+        "SPS30 {} firmware {}",
+        sensor.read_serial_number().unwrap_or_default(),
+        sensor
+            .read_version()
+            .map(|v| v.firmware.to_string())
+            .unwrap_or_default()
+    );
+
+    // it may still be measuring if a previous run was interrupted
+    sensor.stop_measurement().ok();
+    if let Err(e) = sensor.start_measurement() {
+        eprintln!("Can't start measuring: {}", e);
+        exit(1);
+    }
+
+    loop {
+        sleep(Duration::from_secs(1));
+        match sensor.read_measurement() {
+            Ok(Some(m)) => println!(
+                "PM1.0 {:6.1}  PM2.5 {:6.1}  PM4.0 {:6.1}  PM10 {:6.1} {}",
+                m.mass_1_0,
+                m.mass_2_5,
+                m.mass_4_0,
+                m.mass_10,
+                Unit::MicrogramsPerCubicMeter
+            ),
+            Ok(None) => (),
+            Err(e) => eprintln!("Read failed: {}", e),
+        }
+    }
+}
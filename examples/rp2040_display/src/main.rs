@@ -0,0 +1,214 @@
+//! Air quality display: a Raspberry Pi Pico reading an SPS30 over UART and
+//! showing PM2.5 and PM10 on an SSD1306 OLED, with the driver built for
+//! no_std.
+//!
+//! Wiring:
+//!
+//! - SPS30 1 VDD: VBUS (5 V)
+//! - SPS30 2 RX: GP0 (UART0 TX)
+//! - SPS30 3 TX: GP1 (UART0 RX)
+//! - SPS30 4 SEL: leave floating for UART
+//! - SPS30 5 GND: GND
+//! - SSD1306 SDA: GP4, SCL: GP5 (I2C0), VCC: 3V3
+//!
+//! A warning triangle is shown while the sensor reports a fan or laser
+//! fault, and the fan is cleaned once a day on top of the sensor's own weekly
+//! auto cleaning.
+//!
+//! Build and flash from this directory with `cargo run --release`, which
+//! needs `probe-rs` and a debug probe. Log output comes through defmt RTT.
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use core::fmt::Write as _;
+
+use defmt_rtt as _;
+use embedded_alloc::LlffHeap as Heap;
+use embedded_graphics::{
+    mono_font::{
+        MonoTextStyle,
+        ascii::{FONT_6X10, FONT_10X20},
+    },
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Triangle},
+    text::Text,
+};
+use embedded_hal::delay::DelayNs;
+use panic_probe as _;
+use rp_pico::{
+    entry,
+    hal::{
+        self, Clock,
+        fugit::RateExtU32,
+        gpio::{FunctionI2C, FunctionUart, PullUp},
+        pac,
+        uart::{DataBits, StopBits, UartConfig, UartPeripheral},
+    },
+};
+use sps30_hdlc::{Sps30, Sps30Measurement, Unit};
+use ssd1306::{I2CDisplayInterface, Ssd1306, prelude::*};
+
+#[global_allocator]
+static HEAP: Heap = Heap::empty();
+
+/// System clock set up by `init_clocks_and_plls`.
+const SYS_HZ: u32 = 125_000_000;
+
+/// Seconds between fan cleanings.
+const CLEAN_INTERVAL: u32 = 24 * 60 * 60;
+
+/// Seconds between checks of the device status register.
+const STATUS_INTERVAL: u32 = 60;
+
+/// The driver takes a plain function for the waits some commands need.
+fn delay_ms(ms: u32) {
+    cortex_m::asm::delay(ms * (SYS_HZ / 1000));
+}
+
+/// Redraw the screen with the latest reading and fault state.
+fn draw<D: DrawTarget<Color = BinaryColor>>(
+    display: &mut D,
+    m: Option<&Sps30Measurement>,
+    fault: bool,
+) -> Result<(), D::Error> {
+    let small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+    let large = MonoTextStyle::new(&FONT_10X20, BinaryColor::On);
+    display.clear(BinaryColor::Off)?;
+
+    Text::new("PM2.5", Point::new(0, 10), small).draw(display)?;
+    Text::new("PM10", Point::new(0, 42), small).draw(display)?;
+    // the font has no micro sign
+    let unit = Unit::MicrogramsPerCubicMeter.ascii_symbol();
+    Text::new(unit, Point::new(98, 30), small).draw(display)?;
+
+    // a reading fits easily, so the writes can't fail
+    let mut pm25: heapless::String<12> = heapless::String::new();
+    let mut pm10: heapless::String<12> = heapless::String::new();
+    match m {
+        Some(m) => {
+            write!(pm25, "{:.1}", m.mass_2_5).ok();
+            write!(pm10, "{:.1}", m.mass_10).ok();
+        }
+        None => {
+            pm25.push_str("--").ok();
+            pm10.push_str("--").ok();
+        }
+    }
+    Text::new(&pm25, Point::new(0, 30), large).draw(display)?;
+    Text::new(&pm10, Point::new(0, 62), large).draw(display)?;
+
+    if fault {
+        Triangle::new(Point::new(116, 0), Point::new(127, 18), Point::new(105, 18))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(display)?;
+        Text::new("!", Point::new(114, 16), small).draw(display)?;
+    }
+    Ok(())
+}
+
+#[entry]
+fn main() -> ! {
+    {
+        use core::mem::MaybeUninit;
+        const HEAP_SIZE: usize = 16 * 1024;
+        static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
+        // SAFETY: called once, before anything allocates
+        unsafe { HEAP.init(&raw mut HEAP_MEM as usize, HEAP_SIZE) }
+    }
+
+    let mut pac = pac::Peripherals::take().unwrap();
+    let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
+    let clocks = hal::clocks::init_clocks_and_plls(
+        rp_pico::XOSC_CRYSTAL_FREQ,
+        pac.XOSC,
+        pac.CLOCKS,
+        pac.PLL_SYS,
+        pac.PLL_USB,
+        &mut pac.RESETS,
+        &mut watchdog,
+    )
+    .unwrap();
+    let mut timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+    let sio = hal::Sio::new(pac.SIO);
+    let pins = rp_pico::Pins::new(
+        pac.IO_BANK0,
+        pac.PADS_BANK0,
+        sio.gpio_bank0,
+        &mut pac.RESETS,
+    );
+
+    let i2c = hal::I2C::i2c0(
+        pac.I2C0,
+        pins.gpio4.reconfigure::<FunctionI2C, PullUp>(),
+        pins.gpio5.reconfigure::<FunctionI2C, PullUp>(),
+        400.kHz(),
+        &mut pac.RESETS,
+        &clocks.system_clock,
+    );
+    let mut display = Ssd1306::new(
+        I2CDisplayInterface::new(i2c),
+        DisplaySize128x64,
+        DisplayRotation::Rotate0,
+    )
+    .into_buffered_graphics_mode();
+    display.init().unwrap();
+
+    // the SPS30's UART is fixed at 115200 8N1
+    let uart = UartPeripheral::new(
+        pac.UART0,
+        (
+            pins.gpio0.into_function::<FunctionUart>(),
+            pins.gpio1.into_function::<FunctionUart>(),
+        ),
+        &mut pac.RESETS,
+    )
+    .enable(
+        UartConfig::new(115200.Hz(), DataBits::Eight, None, StopBits::One),
+        clocks.peripheral_clock.freq(),
+    )
+    .unwrap();
+
+    let mut sensor = Sps30::new(uart);
+    sensor.set_delay(Some(delay_ms));
+    // it may still be measuring if the Pico was reset on its own
+    sensor.stop_measurement().ok();
+    while sensor.start_measurement().is_err() {
+        defmt::warn!("Can't start measuring, retrying");
+        timer.delay_ms(1000);
+    }
+
+    let mut last: Option<Sps30Measurement> = None;
+    let mut fault = false;
+    let mut seconds: u32 = 0;
+    loop {
+        timer.delay_ms(1000);
+        seconds = seconds.wrapping_add(1);
+
+        match sensor.read_measurement() {
+            Ok(Some(m)) => {
+                defmt::info!("{}", defmt::Display2Format(&m));
+                // the measurement is Copy, so keeping the last one is free
+                last = Some(m);
+            }
+            Ok(None) => (),
+            Err(_) => defmt::warn!("Read failed"),
+        }
+        if seconds % STATUS_INTERVAL == 0 {
+            match sensor.read_and_clear_device_status() {
+                Ok(status) => fault = !status.is_healthy(),
+                Err(_) => defmt::warn!("Can't read device status"),
+            }
+        }
+        if seconds % CLEAN_INTERVAL == 0 {
+            defmt::info!("Cleaning fan");
+            sensor.start_fan_cleaning().ok();
+        }
+
+        if draw(&mut display, last.as_ref(), fault).is_ok() {
+            display.flush().ok();
+        }
+    }
+}
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sps30_hdlc::{DeviceStatus, Frame, Sps30Measurement, Sps30Version};
+
+// Run arbitrary bytes through the whole MISO receive path: unstuffing, header
+// and checksum checks, then every payload parser.
+fuzz_target!(|data: &[u8]| {
+    let _ = Frame::decode_request(data);
+    if let Ok((_, f)) = Frame::decode(data) {
+        let _ = Sps30Measurement::from_bytes(&f.data);
+        let _ = Sps30Version::from_bytes(&f.data);
+        let _ = DeviceStatus::from_payload(&f.data, false);
+    }
+});
@@ -0,0 +1,90 @@
+#ifndef SPS30_H
+#define SPS30_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define SPS30_OK 0
+
+// A handle or pointer argument was NULL.
+#define SPS30_ERR_NULL -1
+
+// The port couldn't be opened.
+#define SPS30_ERR_PORT -2
+
+// The sensor didn't answer, answered with a corrupt frame or reported an
+// error.
+#define SPS30_ERR_DEVICE -3
+
+// No new measurement is available yet.
+#define SPS30_ERR_NO_DATA -4
+
+// The driver panicked. The handle should be closed.
+#define SPS30_ERR_PANIC -5
+
+// Opaque driver handle.
+typedef struct sps30_handle sps30_handle;
+
+// Mass concentrations in µg/m³, number concentrations in #/cm³ and the
+// typical particle size in µm.
+typedef struct sps30_measurement {
+  float mass_1_0;
+  float mass_2_5;
+  float mass_4_0;
+  float mass_10;
+  float number_pm0_5;
+  float number_pm1_0;
+  float number_pm2_5;
+  float number_pm4_0;
+  float number_pm10;
+  float typical_particle_size_um;
+} sps30_measurement;
+
+// Open the serial port at `path` and store a new handle in `*out`.
+//
+// # Safety
+//
+// `path` must be a NUL terminated string and `out` a valid pointer.
+int sps30_open(const char *path, uint32_t baud, sps30_handle **out);
+
+// Take ownership of an already configured file descriptor and store a new
+// handle in `*out`. The descriptor is closed by `sps30_close`.
+//
+// # Safety
+//
+// `fd` must be an open descriptor not owned by anything else, and `out` a
+// valid pointer.
+int sps30_open_fd(int fd, sps30_handle **out);
+
+// # Safety
+//
+// `handle` must come from `sps30_open` or `sps30_open_fd`.
+int sps30_start_measurement(sps30_handle *handle);
+
+// # Safety
+//
+// `handle` must come from `sps30_open` or `sps30_open_fd`.
+int sps30_stop_measurement(sps30_handle *handle);
+
+// Read the latest measurement into `*out`. Returns `SPS30_ERR_NO_DATA`,
+// leaving `*out` untouched, if the sensor has nothing new.
+//
+// # Safety
+//
+// `handle` must come from `sps30_open` or `sps30_open_fd`, and `out` must
+// be a valid pointer.
+int sps30_read_measurement(sps30_handle *handle, sps30_measurement *out);
+
+// Free the handle and close its port. NULL is ignored.
+//
+// # Safety
+//
+// `handle` must come from `sps30_open` or `sps30_open_fd` and not be used
+// afterwards.
+void sps30_close(sps30_handle *handle);
+
+#endif /* SPS30_H */
@@ -0,0 +1,496 @@
+//! Air quality indices and WHO guideline checks from PM2.5 and PM10 mass
+//! concentrations.
+//!
+//! These are instantaneous values computed from a single reading or mean,
+//! not the NowCast the EPA uses for its hourly reports, which needs twelve
+//! hours of history. For figures comparable to official ones, feed them the
+//! mean from [`Sps30Stats`](crate::stats::Sps30Stats) over the index's
+//! averaging period.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Sps30Measurement, Unit};
+
+/// One row of an index's breakpoint table: concentrations from `lo` to `hi`
+/// inclusive map linearly onto index values `index_lo` to `index_hi`.
+struct Breakpoint {
+    lo: u32,
+    hi: u32,
+    index_lo: u16,
+    index_hi: u16,
+}
+
+const fn bp(lo: u32, hi: u32, index_lo: u16, index_hi: u16) -> Breakpoint {
+    Breakpoint {
+        lo,
+        hi,
+        index_lo,
+        index_hi,
+    }
+}
+
+/// US EPA PM2.5 breakpoints, as revised in 2024, in tenths of a µg/m³.
+const US_PM2_5: [Breakpoint; 6] = [
+    bp(0, 90, 0, 50),
+    bp(91, 354, 51, 100),
+    bp(355, 554, 101, 150),
+    bp(555, 1254, 151, 200),
+    bp(1255, 2254, 201, 300),
+    bp(2255, 3254, 301, 500),
+];
+
+/// US EPA PM10 breakpoints, in µg/m³.
+const US_PM10: [Breakpoint; 6] = [
+    bp(0, 54, 0, 50),
+    bp(55, 154, 51, 100),
+    bp(155, 254, 101, 150),
+    bp(255, 354, 151, 200),
+    bp(355, 424, 201, 300),
+    bp(425, 604, 301, 500),
+];
+
+/// Truncate a concentration to whole multiples of `1 / scale`, as the EPA
+/// spec requires before looking it up. The small allowance stops values
+/// like 0.7, which f32 holds as 0.69999..., dropping a step.
+fn truncate(c: f32, scale: f32) -> u32 {
+    (c.max(0.0) * scale + 1e-3) as u32
+}
+
+/// Index of the first band whose inclusive upper bound `c` is within, or None
+/// if it's above them all. Shared by every index so they agree on which band
+/// a boundary value falls in.
+fn band<T: PartialOrd>(upper: impl IntoIterator<Item = T>, c: T) -> Option<usize> {
+    upper.into_iter().position(|hi| c <= hi)
+}
+
+/// Sub-index for a truncated concentration, clamped to the top of the table.
+fn sub_index(table: &[Breakpoint], c: u32) -> u16 {
+    match band(table.iter().map(|b| b.hi), c).map(|i| &table[i]) {
+        Some(b) => {
+            let span = (b.index_hi - b.index_lo) as f32 / (b.hi - b.lo) as f32;
+            // round half up by hand, f32::round isn't in core
+            (span * (c - b.lo) as f32 + b.index_lo as f32 + 0.5) as u16
+        }
+        None => table.last().map_or(0, |b| b.index_hi),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pollutant {
+    Pm2_5,
+    Pm10,
+}
+
+impl fmt::Display for Pollutant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Pollutant::Pm2_5 => "PM2.5",
+            Pollutant::Pm10 => "PM10",
+        })
+    }
+}
+
+/// US AQI category, from the index value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AqiCategory {
+    Good,
+    Moderate,
+    UnhealthyForSensitiveGroups,
+    Unhealthy,
+    VeryUnhealthy,
+    Hazardous,
+}
+
+impl AqiCategory {
+    fn from_value(value: u16) -> Self {
+        match value {
+            0..=50 => AqiCategory::Good,
+            51..=100 => AqiCategory::Moderate,
+            101..=150 => AqiCategory::UnhealthyForSensitiveGroups,
+            151..=200 => AqiCategory::Unhealthy,
+            201..=300 => AqiCategory::VeryUnhealthy,
+            _ => AqiCategory::Hazardous,
+        }
+    }
+}
+
+impl fmt::Display for AqiCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AqiCategory::Good => "Good",
+            AqiCategory::Moderate => "Moderate",
+            AqiCategory::UnhealthyForSensitiveGroups => "Unhealthy for Sensitive Groups",
+            AqiCategory::Unhealthy => "Unhealthy",
+            AqiCategory::VeryUnhealthy => "Very Unhealthy",
+            AqiCategory::Hazardous => "Hazardous",
+        })
+    }
+}
+
+/// An instantaneous (not NowCast) US AQI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aqi {
+    /// 0 to 500; concentrations above the top breakpoint give 500.
+    pub value: u16,
+    pub category: AqiCategory,
+    /// The pollutant with the higher sub-index, PM2.5 on a tie.
+    pub dominant: Pollutant,
+}
+
+impl fmt::Display for Aqi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AQI {} ({}, {})",
+            self.value, self.category, self.dominant
+        )
+    }
+}
+
+/// Instantaneous US EPA AQI from a reading's PM2.5 and PM10. PM2.5 is
+/// truncated to 0.1 µg/m³ and PM10 to 1 µg/m³ before lookup, per the EPA's
+/// technical assistance document.
+pub fn us_aqi(m: &Sps30Measurement) -> Aqi {
+    let pm2_5 = sub_index(&US_PM2_5, truncate(m.mass_2_5, 10.0));
+    let pm10 = sub_index(&US_PM10, truncate(m.mass_10, 1.0));
+    let (value, dominant) = if pm10 > pm2_5 {
+        (pm10, Pollutant::Pm10)
+    } else {
+        (pm2_5, Pollutant::Pm2_5)
+    };
+    Aqi {
+        value,
+        category: AqiCategory::from_value(value),
+        dominant,
+    }
+}
+
+/// Upper bounds of the European CAQI hourly background bands, in µg/m³, from
+/// Very Low to High. Anything above the last is Very High.
+const EU_PM2_5: [f32; 4] = [15.0, 30.0, 55.0, 110.0];
+const EU_PM10: [f32; 4] = [25.0, 50.0, 90.0, 180.0];
+
+/// European CAQI band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CaqiCategory {
+    VeryLow,
+    Low,
+    Medium,
+    High,
+    VeryHigh,
+}
+
+impl CaqiCategory {
+    const ALL: [CaqiCategory; 5] = [
+        CaqiCategory::VeryLow,
+        CaqiCategory::Low,
+        CaqiCategory::Medium,
+        CaqiCategory::High,
+        CaqiCategory::VeryHigh,
+    ];
+
+    /// 1 for Very Low to 5 for Very High.
+    pub fn level(&self) -> u8 {
+        *self as u8 + 1
+    }
+
+    fn lookup(upper: &[f32], c: f32) -> Self {
+        Self::ALL[band(upper.iter().copied(), c).unwrap_or(upper.len())]
+    }
+}
+
+impl fmt::Display for CaqiCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CaqiCategory::VeryLow => "Very Low",
+            CaqiCategory::Low => "Low",
+            CaqiCategory::Medium => "Medium",
+            CaqiCategory::High => "High",
+            CaqiCategory::VeryHigh => "Very High",
+        })
+    }
+}
+
+/// A European Common Air Quality Index band, on the hourly background scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caqi {
+    pub category: CaqiCategory,
+    /// The pollutant in the higher band, PM2.5 on a tie.
+    pub dominant: Pollutant,
+}
+
+impl fmt::Display for Caqi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CAQI {} ({}, {})",
+            self.category.level(),
+            self.category,
+            self.dominant
+        )
+    }
+}
+
+/// European CAQI band for a reading's PM2.5 and PM10. A concentration on a
+/// band's upper bound is in that band, so 15 µg/m³ of PM2.5 is Very Low.
+pub fn eu_caqi(m: &Sps30Measurement) -> Caqi {
+    let pm2_5 = CaqiCategory::lookup(&EU_PM2_5, m.mass_2_5);
+    let pm10 = CaqiCategory::lookup(&EU_PM10, m.mass_10);
+    let (category, dominant) = if pm10 > pm2_5 {
+        (pm10, Pollutant::Pm10)
+    } else {
+        (pm2_5, Pollutant::Pm2_5)
+    };
+    Caqi { category, dominant }
+}
+
+/// Which index to compute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AqiStandard {
+    /// US EPA AQI, see [`us_aqi`].
+    Us,
+    /// European CAQI, see [`eu_caqi`].
+    Eu,
+}
+
+impl AqiStandard {
+    pub fn assess(&self, m: &Sps30Measurement) -> AirQuality {
+        match self {
+            AqiStandard::Us => AirQuality::Us(us_aqi(m)),
+            AqiStandard::Eu => AirQuality::Eu(eu_caqi(m)),
+        }
+    }
+}
+
+/// An index computed under one of the [`AqiStandard`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AirQuality {
+    Us(Aqi),
+    Eu(Caqi),
+}
+
+impl fmt::Display for AirQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AirQuality::Us(a) => a.fmt(f),
+            AirQuality::Eu(a) => a.fmt(f),
+        }
+    }
+}
+
+/// Averaging period of a WHO guideline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Daily,
+    Annual,
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Period::Daily => "24-hour",
+            Period::Annual => "annual",
+        })
+    }
+}
+
+/// WHO 2021 air quality guideline level, in µg/m³.
+pub fn who_guideline(pollutant: Pollutant, period: Period) -> f32 {
+    match (pollutant, period) {
+        (Pollutant::Pm2_5, Period::Daily) => 15.0,
+        (Pollutant::Pm2_5, Period::Annual) => 5.0,
+        (Pollutant::Pm10, Period::Daily) => 45.0,
+        (Pollutant::Pm10, Period::Annual) => 15.0,
+    }
+}
+
+/// A mean above a WHO guideline level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GuidelineExceedance {
+    pub pollutant: Pollutant,
+    pub period: Period,
+    /// The guideline level, in µg/m³.
+    pub guideline: f32,
+    /// The mean that exceeded it, in µg/m³.
+    pub value: f32,
+    /// How many times the guideline level the mean is.
+    pub factor: f32,
+}
+
+impl fmt::Display for GuidelineExceedance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:.1} {unit} is {:.1}x the WHO {} guideline of {} {unit}",
+            self.pollutant,
+            self.value,
+            self.factor,
+            self.period,
+            self.guideline,
+            unit = Unit::MicrogramsPerCubicMeter
+        )
+    }
+}
+
+/// Which WHO 2021 guidelines for `period` a mean over that period exceeds,
+/// PM2.5 first. Guidelines apply to means, so pass e.g. the mean of an
+/// [`Sps30Stats`](crate::stats::Sps30Stats) covering a day for
+/// [`Period::Daily`]; a single reading only gives an indication.
+pub fn who_assessment(mean: &Sps30Measurement, period: Period) -> Vec<GuidelineExceedance> {
+    [
+        (Pollutant::Pm2_5, mean.mass_2_5),
+        (Pollutant::Pm10, mean.mass_10),
+    ]
+    .into_iter()
+    .filter_map(|(pollutant, value)| {
+        let guideline = who_guideline(pollutant, period);
+        (value > guideline).then(|| GuidelineExceedance {
+            pollutant,
+            period,
+            guideline,
+            value,
+            factor: value / guideline,
+        })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pm(mass_2_5: f32, mass_10: f32) -> Sps30Measurement {
+        Sps30Measurement {
+            mass_2_5,
+            mass_10,
+            ..Sps30Measurement::zeros()
+        }
+    }
+
+    #[test]
+    fn us_pm2_5_breakpoints() {
+        for (c, value, category) in [
+            (0.0, 0, AqiCategory::Good),
+            (9.0, 50, AqiCategory::Good),
+            (9.1, 51, AqiCategory::Moderate),
+            (35.4, 100, AqiCategory::Moderate),
+            (35.5, 101, AqiCategory::UnhealthyForSensitiveGroups),
+            (55.4, 150, AqiCategory::UnhealthyForSensitiveGroups),
+            (55.5, 151, AqiCategory::Unhealthy),
+            (125.4, 200, AqiCategory::Unhealthy),
+            (125.5, 201, AqiCategory::VeryUnhealthy),
+            (225.4, 300, AqiCategory::VeryUnhealthy),
+            (225.5, 301, AqiCategory::Hazardous),
+            (325.4, 500, AqiCategory::Hazardous),
+        ] {
+            let aqi = us_aqi(&pm(c, 0.0));
+            assert_eq!((aqi.value, aqi.category), (value, category), "{}", c);
+            assert_eq!(aqi.dominant, Pollutant::Pm2_5);
+        }
+    }
+
+    #[test]
+    fn us_pm10_breakpoints() {
+        for (c, value) in [
+            (54.0, 50),
+            (55.0, 51),
+            (154.0, 100),
+            (155.0, 101),
+            (254.0, 150),
+            (255.0, 151),
+            (354.0, 200),
+            (355.0, 201),
+            (424.0, 300),
+            (425.0, 301),
+            (604.0, 500),
+        ] {
+            let aqi = us_aqi(&pm(0.0, c));
+            assert_eq!(aqi.value, value, "{}", c);
+            assert_eq!(aqi.dominant, Pollutant::Pm10);
+        }
+    }
+
+    #[test]
+    fn us_clamps_above_the_table() {
+        for c in [325.5, 500.0, 1000.0] {
+            assert_eq!(us_aqi(&pm(c, 0.0)).value, 500, "{}", c);
+        }
+        assert_eq!(us_aqi(&pm(0.0, 605.0)).value, 500);
+        assert_eq!(us_aqi(&pm(0.0, 1000.0)).value, 500);
+    }
+
+    #[test]
+    fn us_truncates_before_lookup() {
+        // 0.7 is 0.69999... in f32 and must still count as 0.7
+        assert_eq!(us_aqi(&pm(0.7, 0.0)).value, 4);
+        assert_eq!(us_aqi(&pm(9.09, 0.0)).value, 50);
+        assert_eq!(us_aqi(&pm(35.49, 0.0)).value, 100);
+        assert_eq!(us_aqi(&pm(0.0, 54.9)).value, 50);
+        assert_eq!(us_aqi(&pm(-0.3, -1.0)).value, 0);
+    }
+
+    #[test]
+    fn us_dominant_is_the_higher_sub_index() {
+        let aqi = us_aqi(&pm(9.0, 55.0));
+        assert_eq!((aqi.value, aqi.dominant), (51, Pollutant::Pm10));
+        let aqi = us_aqi(&pm(9.0, 54.0));
+        assert_eq!((aqi.value, aqi.dominant), (50, Pollutant::Pm2_5));
+    }
+
+    #[test]
+    fn eu_band_upper_bounds_are_inclusive() {
+        use CaqiCategory::*;
+        for (c, category) in [
+            (0.0, VeryLow),
+            (15.0, VeryLow),
+            (15.01, Low),
+            (30.0, Low),
+            (30.01, Medium),
+            (55.0, Medium),
+            (55.01, High),
+            (110.0, High),
+            (110.01, VeryHigh),
+            (1000.0, VeryHigh),
+        ] {
+            let caqi = eu_caqi(&pm(c, 0.0));
+            assert_eq!(caqi.category, category, "PM2.5 {}", c);
+            assert_eq!(caqi.dominant, Pollutant::Pm2_5);
+        }
+        for (c, category) in [
+            (25.0, VeryLow),
+            (25.01, Low),
+            (50.0, Low),
+            (50.01, Medium),
+            (90.0, Medium),
+            (90.01, High),
+            (180.0, High),
+            (180.01, VeryHigh),
+        ] {
+            let caqi = eu_caqi(&pm(0.0, c));
+            assert_eq!(caqi.category, category, "PM10 {}", c);
+            let dominant = if category == VeryLow {
+                Pollutant::Pm2_5
+            } else {
+                Pollutant::Pm10
+            };
+            assert_eq!(caqi.dominant, dominant, "PM10 {}", c);
+        }
+    }
+
+    #[test]
+    fn eu_levels_and_dominant() {
+        let levels: Vec<u8> = CaqiCategory::ALL.iter().map(CaqiCategory::level).collect();
+        assert_eq!(levels, [1, 2, 3, 4, 5]);
+        let caqi = eu_caqi(&pm(15.0, 25.01));
+        assert_eq!(
+            (caqi.category, caqi.dominant),
+            (CaqiCategory::Low, Pollutant::Pm10)
+        );
+        let caqi = eu_caqi(&pm(15.01, 25.01));
+        assert_eq!(
+            (caqi.category, caqi.dominant),
+            (CaqiCategory::Low, Pollutant::Pm2_5)
+        );
+    }
+}
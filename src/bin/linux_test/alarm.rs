@@ -0,0 +1,147 @@
+//! Warning and alert thresholds on the mass concentrations.
+use std::process::Command;
+
+use serde_json::{Map, Value, json};
+use sps30_hdlc::Sps30Measurement;
+
+/// A raised level only clears once the reading drops below this fraction of
+/// its threshold, so a value hovering around a threshold doesn't flap.
+const HYSTERESIS: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Normal,
+    Warning,
+    Alert,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Normal => "ok",
+            Level::Warning => "warning",
+            Level::Alert => "alert",
+        }
+    }
+}
+
+struct Alarm {
+    field: &'static str,
+    value: fn(&Sps30Measurement) -> f32,
+    warn: Option<f32>,
+    alert: Option<f32>,
+    level: Level,
+}
+
+impl Alarm {
+    fn update(&mut self, v: f32) -> Level {
+        let above = |t: Option<f32>, held: bool| match t {
+            Some(t) if held => v >= t * HYSTERESIS,
+            Some(t) => v > t,
+            None => false,
+        };
+        self.level = if above(self.alert, self.level == Level::Alert) {
+            Level::Alert
+        } else if above(self.warn, self.level >= Level::Warning) {
+            Level::Warning
+        } else {
+            Level::Normal
+        };
+        self.level
+    }
+}
+
+pub struct Alarms {
+    alarms: Vec<Alarm>,
+    on_alert: Option<String>,
+}
+
+impl Alarms {
+    pub fn new(
+        pm25: (Option<f32>, Option<f32>),
+        pm10: (Option<f32>, Option<f32>),
+        on_alert: Option<String>,
+    ) -> Self {
+        let alarms = [
+            (
+                "pm2_5",
+                pm25,
+                (|m: &Sps30Measurement| m.mass_2_5) as fn(&_) -> f32,
+            ),
+            ("pm10", pm10, |m: &Sps30Measurement| m.mass_10),
+        ]
+        .into_iter()
+        .filter(|(_, (warn, alert), _)| warn.is_some() || alert.is_some())
+        .map(|(field, (warn, alert), value)| Alarm {
+            field,
+            value,
+            warn,
+            alert,
+            level: Level::Normal,
+        })
+        .collect();
+        Self { alarms, on_alert }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alarms.is_empty()
+    }
+
+    /// Check a reading against the thresholds, returning the fields whose
+    /// level changed along with their new level. The alert command runs for
+    /// every field that has just gone into alert.
+    pub fn update(&mut self, m: &Sps30Measurement) -> Vec<(&'static str, f32, Level)> {
+        let mut changed = Vec::new();
+        for a in &mut self.alarms {
+            let before = a.level;
+            let v = (a.value)(m);
+            if a.update(v) != before {
+                changed.push((a.field, v, a.level));
+            }
+        }
+        for &(field, v, level) in &changed {
+            if level == Level::Alert {
+                self.run_on_alert(field, v);
+            }
+        }
+        changed
+    }
+
+    /// The highest level of any field.
+    pub fn level(&self) -> Level {
+        self.alarms
+            .iter()
+            .map(|a| a.level)
+            .fold(Level::Normal, |a, b| if b > a { b } else { a })
+    }
+
+    pub fn json(&self) -> Value {
+        let levels: Map<String, Value> = self
+            .alarms
+            .iter()
+            .map(|a| (a.field.to_string(), json!(a.level.name())))
+            .collect();
+        Value::Object(levels)
+    }
+
+    /// Run the user's command through the shell without waiting for it, with
+    /// the field and value in the environment.
+    fn run_on_alert(&self, field: &str, value: f32) {
+        let Some(cmd) = &self.on_alert else {
+            return;
+        };
+        match Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .env("SPS30_FIELD", field)
+            .env("SPS30_VALUE", value.to_string())
+            .spawn()
+        {
+            Ok(mut child) => {
+                // reap it in the background so it doesn't linger as a zombie
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => eprintln!("Can't run alert command: {}", e),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Averaging readings over a window of samples.
+use serde_json::{Value, json};
+use sps30_hdlc::Sps30Measurement;
+use sps30_hdlc::stats::{Sps30Stats, Sps30StatsReport, percentile};
+
+/// One window's worth of aggregates.
+pub struct Aggregate {
+    pub stats: Sps30StatsReport,
+    /// The percentile asked for, and each field's value at it.
+    pub percentile: Option<(f32, Sps30Measurement)>,
+}
+
+impl Aggregate {
+    /// `samples`, plus `min` and `max` when asked for and the percentile as
+    /// e.g. `p95`.
+    pub fn json(&self, min_max: bool) -> Value {
+        let mut v = json!({ "samples": self.stats.samples });
+        if min_max {
+            v["min"] = serde_json::to_value(self.stats.min).unwrap();
+            v["max"] = serde_json::to_value(self.stats.max).unwrap();
+        }
+        if let Some((p, m)) = &self.percentile {
+            v[format!("p{}", p)] = serde_json::to_value(m).unwrap();
+        }
+        v
+    }
+}
+
+pub struct Window {
+    size: u32,
+    stats: Sps30Stats,
+    percentile: Option<f32>,
+    /// Kept only when a percentile is wanted, to compute it exactly.
+    samples: Vec<[f32; 10]>,
+}
+
+impl Window {
+    pub fn new(size: u32, percentile: Option<f32>) -> Self {
+        Self {
+            size: size.max(1),
+            stats: Sps30Stats::new(),
+            percentile,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Add a sample, returning the aggregates once the window is full.
+    pub fn push(&mut self, m: &Sps30Measurement) -> Option<Aggregate> {
+        self.stats.push(m);
+        if self.percentile.is_some() {
+            self.samples.push(m.as_array());
+        }
+        if self.stats.count() >= self.size {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Aggregate whatever has been collected so far and start a new window.
+    pub fn flush(&mut self) -> Option<Aggregate> {
+        let stats = self.stats.snapshot()?;
+        self.stats.reset();
+        let percentile = self.percentile.map(|p| {
+            let mut out = [0f32; 10];
+            let mut column = Vec::with_capacity(self.samples.len());
+            for (i, o) in out.iter_mut().enumerate() {
+                column.clear();
+                column.extend(self.samples.iter().map(|s| s[i]));
+                *o = percentile(&mut column, p).unwrap_or_default();
+            }
+            (p, Sps30Measurement::from(out))
+        });
+        self.samples.clear();
+        Some(Aggregate { stats, percentile })
+    }
+}
@@ -0,0 +1,80 @@
+//! Configuration file. Every setting is optional, and flags given on the
+//! command line take precedence over the file.
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Format;
+use crate::csv_log::Rotate;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub port: Option<String>,
+    pub baud: Option<usize>,
+    pub format: Option<Format>,
+    /// Measurement interval, e.g. "1s"
+    pub interval: Option<String>,
+    /// CSV file readings are appended to
+    pub output: Option<PathBuf>,
+    pub rotate: Option<Rotate>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Interval(PathBuf, humantime::DurationError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(p, e) => write!(f, "Can't read {}: {}", p.display(), e),
+            ConfigError::Parse(p, e) => write!(f, "Invalid config file {}: {}", p.display(), e),
+            ConfigError::Interval(p, e) => {
+                write!(f, "Invalid interval in {}: {}", p.display(), e)
+            }
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/sps30/config.toml`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(d) if !d.is_empty() => PathBuf::from(d),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("sps30").join("config.toml"))
+}
+
+fn load_from(path: &Path) -> Result<Config, ConfigError> {
+    let s = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+    let config: Config = toml::from_str(&s).map_err(|e| ConfigError::Parse(path.into(), e))?;
+    if let Some(i) = &config.interval {
+        humantime::parse_duration(i).map_err(|e| ConfigError::Interval(path.into(), e))?;
+    }
+    Ok(config)
+}
+
+/// Load the given config file, or the default one if it exists.
+pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+    match path {
+        Some(p) => load_from(p),
+        None => match default_path() {
+            Some(p) if p.exists() => load_from(&p),
+            _ => Ok(Config::default()),
+        },
+    }
+}
+
+impl Config {
+    pub fn interval(&self) -> Option<std::time::Duration> {
+        self.interval
+            .as_deref()
+            .and_then(|i| humantime::parse_duration(i).ok())
+    }
+}
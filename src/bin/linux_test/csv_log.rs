@@ -0,0 +1,188 @@
+//! Appending CSV log of readings, optionally rotated into one file per day.
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use clap::ValueEnum;
+use sps30_hdlc::{DeviceStatus, Sps30Fault, Sps30Measurement};
+
+fn header() -> String {
+    let fields: Vec<_> = Sps30Measurement::zeros()
+        .fields()
+        .map(|(name, _, _)| name)
+        .collect();
+    format!(
+        "timestamp,{},fan_error,laser_error,fan_speed_warning",
+        fields.join(",")
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotate {
+    Never,
+    Daily,
+}
+
+pub struct CsvLog {
+    path: PathBuf,
+    rotate: Rotate,
+    file: Option<(NaiveDate, BufWriter<File>)>,
+}
+
+/// `readings.csv` becomes `readings-2024-05-01.csv`.
+fn dated_path(path: &Path, date: NaiveDate) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, date, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, date),
+    };
+    path.with_file_name(name)
+}
+
+impl CsvLog {
+    pub fn new(path: PathBuf, rotate: Rotate) -> Self {
+        Self {
+            path,
+            rotate,
+            file: None,
+        }
+    }
+
+    fn writer(&mut self, date: NaiveDate) -> io::Result<&mut BufWriter<File>> {
+        let stale = match &self.file {
+            None => true,
+            Some((d, _)) => self.rotate == Rotate::Daily && *d != date,
+        };
+        if stale {
+            let path = match self.rotate {
+                Rotate::Never => self.path.clone(),
+                Rotate::Daily => dated_path(&self.path, date),
+            };
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let new = file.metadata()?.len() == 0;
+            let mut w = BufWriter::new(file);
+            if new {
+                writeln!(w, "{}", header())?;
+            }
+            self.file = Some((date, w));
+        }
+        Ok(&mut self.file.as_mut().unwrap().1)
+    }
+
+    /// Append one row and make sure it reaches the disk.
+    pub fn write(
+        &mut self,
+        time: DateTime<Local>,
+        m: &Sps30Measurement,
+        status: &Option<DeviceStatus>,
+    ) -> io::Result<()> {
+        let has = |f: fn(&Sps30Fault) -> bool| -> u8 {
+            status.iter().flat_map(DeviceStatus::faults).any(|x| f(&x)) as u8
+        };
+        let fan = has(|f| matches!(f, Sps30Fault::FanFailure));
+        let laser = has(|f| matches!(f, Sps30Fault::LaserFailure));
+        let speed = has(|f| matches!(f, Sps30Fault::FanSpeedOutOfRange));
+
+        let values: Vec<_> = m.fields().map(|(_, v, _)| v.to_string()).collect();
+
+        let w = self.writer(time.date_naive())?;
+        writeln!(
+            w,
+            "{},{},{},{},{}",
+            time.to_rfc3339(),
+            values.join(","),
+            fan,
+            laser,
+            speed
+        )?;
+        w.flush()?;
+        w.get_ref().sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::fs;
+
+    /// A fresh directory for one test's files.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sps30-csv-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap()
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn dated_paths() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert_eq!(
+            dated_path(Path::new("/var/log/readings.csv"), date),
+            Path::new("/var/log/readings-2024-05-01.csv")
+        );
+        assert_eq!(
+            dated_path(Path::new("readings"), date),
+            Path::new("readings-2024-05-01")
+        );
+    }
+
+    #[test]
+    fn header_is_written_once_and_rows_appended() {
+        let path = temp_dir("append").join("readings.csv");
+        let m = Sps30Measurement::zeros();
+        let mut log = CsvLog::new(path.clone(), Rotate::Never);
+        log.write(at(1, 10), &m, &None).unwrap();
+        log.write(at(2, 10), &m, &None).unwrap();
+        // reopening appends without a second header
+        let mut log = CsvLog::new(path.clone(), Rotate::Never);
+        log.write(at(3, 10), &m, &Some(DeviceStatus::new(1 << 4, true)))
+            .unwrap();
+
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], header());
+        assert!(lines[1].starts_with("2024-05-01T10:00:00"));
+        assert!(lines[1].ends_with(",0,0,0"));
+        assert!(lines[3].ends_with(",1,0,0"));
+        let columns = lines[0].split(',').count();
+        assert!(lines[1..].iter().all(|l| l.split(',').count() == columns));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn daily_rotation_starts_a_dated_file() {
+        let dir = temp_dir("daily");
+        let path = dir.join("readings.csv");
+        let m = Sps30Measurement::zeros();
+        let mut log = CsvLog::new(path.clone(), Rotate::Daily);
+        log.write(at(1, 10), &m, &None).unwrap();
+        log.write(at(1, 23), &m, &None).unwrap();
+        log.write(at(2, 0), &m, &None).unwrap();
+
+        assert!(!path.exists());
+        let first = lines(&dated_path(&path, at(1, 0).date_naive()));
+        let second = lines(&dated_path(&path, at(2, 0).date_naive()));
+        assert_eq!(first.len(), 3);
+        assert_eq!(second.len(), 2);
+        assert_eq!(first[0], header());
+        assert_eq!(second[0], header());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
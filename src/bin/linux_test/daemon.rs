@@ -0,0 +1,83 @@
+//! Long running measurement service.
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use serde_json::json;
+
+use crate::{
+    MeasureArgs, Output, SHUTDOWN, describe_error, measure, new_sensor, notify, open_port,
+};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// First signal asks the measurement loop to wind down, a second one exits
+/// straight away.
+fn install_signal_handler() {
+    static SIGNALLED: AtomicBool = AtomicBool::new(false);
+    ctrlc::set_handler(|| {
+        if SIGNALLED.swap(true, Ordering::SeqCst) {
+            warn!("Second signal, exiting immediately");
+            std::process::exit(130);
+        }
+        info!("Shutdown requested");
+        notify::stopping();
+        SHUTDOWN.store(true, Ordering::SeqCst);
+    })
+    .expect("Can't install signal handler");
+}
+
+/// Sleep for `d`, returning early if a shutdown is requested.
+fn sleep_unless_shutdown(d: Duration) {
+    let end = Instant::now() + d;
+    while !SHUTDOWN.load(Ordering::SeqCst) && Instant::now() < end {
+        sleep(Duration::from_millis(100));
+    }
+}
+
+pub fn run(port: &str, baud: usize, args: &MeasureArgs, out: &Output) -> i32 {
+    install_signal_handler();
+    info!("Starting on {}@{}", port, baud);
+
+    let mut backoff = Duration::from_secs(1);
+    while !SHUTDOWN.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        match open_port(port, baud) {
+            Ok(p) => {
+                let mut sensor = new_sensor(p, out.link);
+                // the sensor may still be measuring from before a reconnect
+                sensor.stop_measurement().ok();
+                if let Some(http) = &out.http {
+                    let version = sensor.read_version().ok();
+                    http.info(json!({
+                        "product_type": sensor.get_device_info(),
+                        "serial": sensor.read_serial_number(),
+                        "firmware": version.map(|v| v.firmware.to_string()),
+                        "hardware": version.map(|v| v.hardware.to_string()),
+                        "shdlc": version.map(|v| v.shdlc.to_string()),
+                    }));
+                }
+                info!("Measurement started");
+                match measure(&mut sensor, args, out) {
+                    Ok(0) if SHUTDOWN.load(Ordering::SeqCst) => break,
+                    Ok(code) => warn!("Measurement loop ended with code {}", code),
+                    Err(e) => warn!("Sensor error: {}", describe_error(&e, &sensor)),
+                }
+            }
+            Err(e) => warn!("Can't open {}: {}", port, e),
+        }
+
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = Duration::from_secs(1);
+        }
+        info!("Reconnecting in {}", humantime::format_duration(backoff));
+        sleep_unless_shutdown(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    info!("Measurement stopped, exiting");
+    0
+}
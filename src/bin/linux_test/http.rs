@@ -0,0 +1,94 @@
+//! Minimal HTTP endpoint serving the latest reading, status and device info.
+//!
+//! Requests are answered from what the measurement loop last saw, so they
+//! never cause extra traffic to the sensor.
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+use log::{info, warn};
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Response, Server};
+
+#[derive(Default)]
+struct Latest {
+    reading: Option<(Instant, Value)>,
+    status: Option<Value>,
+    info: Option<Value>,
+}
+
+pub struct HttpServer {
+    latest: Arc<Mutex<Latest>>,
+}
+
+fn json_response(code: u16, v: &Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(v.to_string())
+        .with_status_code(code)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn unavailable(what: &str) -> (u16, Value) {
+    (
+        503,
+        json!({ "error": format!("no {} from the sensor yet", what) }),
+    )
+}
+
+fn respond(latest: &Latest, method: &Method, url: &str) -> (u16, Value) {
+    if *method != Method::Get {
+        return (405, json!({ "error": "only GET is supported" }));
+    }
+    match url {
+        "/measurement" => match &latest.reading {
+            Some((at, v)) => {
+                let mut v = v.clone();
+                v["age_seconds"] = json!(at.elapsed().as_secs_f64());
+                (200, v)
+            }
+            None => unavailable("reading"),
+        },
+        "/status" => match &latest.status {
+            Some(v) => (200, v.clone()),
+            None => unavailable("status"),
+        },
+        "/info" => match &latest.info {
+            Some(v) => (200, v.clone()),
+            None => unavailable("device info"),
+        },
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
+
+impl HttpServer {
+    pub fn start(addr: SocketAddr) -> Result<Self, String> {
+        let server = Server::http(addr).map_err(|e| e.to_string())?;
+        info!("Serving HTTP on {}", addr);
+
+        let latest = Arc::new(Mutex::new(Latest::default()));
+        let shared = latest.clone();
+        thread::spawn(move || {
+            for req in server.incoming_requests() {
+                let (code, body) = respond(&shared.lock().unwrap(), req.method(), req.url());
+                if let Err(e) = req.respond(json_response(code, &body)) {
+                    warn!("HTTP response failed: {}", e);
+                }
+            }
+        });
+        Ok(Self { latest })
+    }
+
+    pub fn reading(&self, v: &Value) {
+        self.latest.lock().unwrap().reading = Some((Instant::now(), v.clone()));
+    }
+
+    pub fn status(&self, v: Value) {
+        self.latest.lock().unwrap().status = Some(v);
+    }
+
+    pub fn info(&self, v: Value) {
+        self.latest.lock().unwrap().info = Some(v);
+    }
+}
@@ -0,0 +1,122 @@
+//! InfluxDB line protocol output.
+use chrono::{DateTime, Local};
+use sps30_hdlc::Sps30Measurement;
+
+/// Escape a measurement name: commas and spaces.
+fn escape_measurement(s: &str) -> String {
+    s.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// Escape a tag key or value: commas, equals signs and spaces.
+fn escape_tag(s: &str) -> String {
+    s.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+pub struct Influx {
+    pub measurement: String,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Influx {
+    pub fn line(
+        &self,
+        time: DateTime<Local>,
+        m: &Sps30Measurement,
+        serial: Option<&str>,
+    ) -> String {
+        let mut line = escape_measurement(&self.measurement);
+        if let Some(serial) = serial {
+            line.push_str(&format!(",serial={}", escape_tag(serial)));
+        }
+        for (k, v) in &self.tags {
+            line.push_str(&format!(",{}={}", escape_tag(k), escape_tag(v)));
+        }
+        let fields: Vec<_> = m
+            .fields()
+            .map(|(name, v, _)| format!("{}={}", name, v))
+            .collect();
+        line.push_str(&format!(
+            " {} {}",
+            fields.join(","),
+            time.timestamp_nanos_opt().unwrap_or_default()
+        ));
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const FIELDS: &str = "mass_1_0=1,mass_2_5=2.5,mass_4_0=4,mass_10=10,\
+        number_pm0_5=0.5,number_pm1_0=1.5,number_pm2_5=2,number_pm4_0=3,\
+        number_pm10=4.5,typical_particle_size_um=0.6";
+
+    fn reading() -> Sps30Measurement {
+        Sps30Measurement {
+            mass_1_0: 1.0,
+            mass_2_5: 2.5,
+            mass_4_0: 4.0,
+            mass_10: 10.0,
+            number_pm0_5: 0.5,
+            number_pm1_0: 1.5,
+            number_pm2_5: 2.0,
+            number_pm4_0: 3.0,
+            number_pm10: 4.5,
+            typical_particle_size_um: 0.6,
+        }
+    }
+
+    fn time() -> DateTime<Local> {
+        Local.timestamp_nanos(1_714_557_600_123_456_789)
+    }
+
+    #[test]
+    fn plain_line() {
+        let influx = Influx {
+            measurement: "sps30".into(),
+            tags: Vec::new(),
+        };
+        assert_eq!(
+            influx.line(time(), &reading(), None),
+            format!("sps30 {} 1714557600123456789", FIELDS)
+        );
+    }
+
+    #[test]
+    fn serial_then_extra_tags() {
+        let influx = Influx {
+            measurement: "sps30".into(),
+            tags: vec![
+                ("room".into(), "kitchen".into()),
+                ("site".into(), "home".into()),
+            ],
+        };
+        assert_eq!(
+            influx.line(time(), &reading(), Some("ABCD1234")),
+            format!(
+                "sps30,serial=ABCD1234,room=kitchen,site=home {} 1714557600123456789",
+                FIELDS
+            )
+        );
+    }
+
+    #[test]
+    fn escapes_names_and_tags() {
+        let influx = Influx {
+            measurement: "air quality,indoor".into(),
+            tags: vec![("the room".into(), "a=b, c".into())],
+        };
+        let line = influx.line(time(), &reading(), Some("AB CD"));
+        assert!(
+            line.starts_with(
+                "air\\ quality\\,indoor,serial=AB\\ CD,the\\ room=a\\=b\\,\\ c mass_1_0=1,"
+            ),
+            "{}",
+            line
+        );
+    }
+}
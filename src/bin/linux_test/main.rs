@@ -0,0 +1,1215 @@
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+    process::exit,
+    sync::atomic::{AtomicBool, Ordering},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+mod alarm;
+mod average;
+mod config;
+mod csv_log;
+mod daemon;
+mod http;
+mod influx;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod notify;
+mod ports;
+mod scan;
+mod socket;
+mod tui;
+
+use alarm::{Alarms, Level};
+use average::{Aggregate, Window};
+use clap::{self, Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use config::Config;
+use csv_log::{CsvLog, Rotate};
+use influx::Influx;
+use ports::PortFilter;
+use serde_json::json;
+use serialport::{self, SerialPort};
+use sps30_hdlc::{
+    DeviceError, DeviceModel, DeviceStatus, Direction, Frame, RetryPolicy, Severity, Sps30,
+    Sps30Builder, Sps30Config, Sps30Measurement, Transport,
+    aqi::{AirQuality, AqiStandard, Period, Pollutant, who_guideline},
+    capture::ReplayPort,
+};
+
+#[derive(clap::Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Only print data on stdout and errors on stderr
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log more on stderr, repeat for more detail. RUST_LOG overrides this
+    #[arg(long, short, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Dump every frame sent and received on stderr
+    #[arg(long, global = true)]
+    trace_frames: bool,
+    /// How long to wait for the sensor to answer a command [default: 500ms,
+    /// longer for reset, wake and clean]
+    #[arg(long, global = true, value_parser = humantime::parse_duration)]
+    response_timeout: Option<Duration>,
+    /// How many times to resend a command that wasn't answered
+    #[arg(long, global = true, default_value_t = 2)]
+    retries: u8,
+    /// Report negative values from the sensor as 0
+    #[arg(long, global = true)]
+    clamp_negative: bool,
+    /// Config file, defaults to ~/.config/sps30/config.toml
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[arg(short, long, global = true)]
+    port: Option<String>,
+    /// Baud rate [default: 115200]
+    #[arg(long, short, global = true)]
+    baud: Option<usize>,
+    /// Output format [default: text]
+    #[arg(long, global = true, value_enum)]
+    format: Option<Format>,
+    /// Only list or scan USB serial ports
+    #[arg(long, global = true)]
+    usb_only: bool,
+    /// Only list or scan USB ports with this vendor ID (hex)
+    #[arg(long, global = true, value_parser = ports::parse_usb_id)]
+    vid: Option<u16>,
+    /// Only list or scan USB ports with this product ID (hex)
+    #[arg(long, global = true, value_parser = ports::parse_usb_id)]
+    pid: Option<u16>,
+    /// Measurement name for --format influx
+    #[arg(long, global = true, default_value = "sps30")]
+    influx_measurement: String,
+    /// Extra tag for --format influx, as key=value. May be repeated
+    #[arg(long = "influx-tag", global = true, value_parser = parse_tag)]
+    influx_tags: Vec<(String, String)>,
+    #[command(subcommand)]
+    command: Option<Cmd>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    Text,
+    /// One JSON object per line
+    Json,
+    /// InfluxDB line protocol, readings only
+    Influx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum AqiArg {
+    /// US EPA AQI
+    Us,
+    /// European CAQI
+    Eu,
+}
+
+impl From<AqiArg> for AqiStandard {
+    fn from(a: AqiArg) -> Self {
+        match a {
+            AqiArg::Us => AqiStandard::Us,
+            AqiArg::Eu => AqiStandard::Eu,
+        }
+    }
+}
+
+fn parse_percentile(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Ok(p),
+        _ => Err("must be a number from 0 to 100".into()),
+    }
+}
+
+fn parse_hex_byte(s: &str) -> Result<u8, String> {
+    let digits = s.trim_start_matches("0x");
+    u8::from_str_radix(digits, 16).map_err(|_| format!("expected a hex byte, got {:?}", s))
+}
+
+fn parse_tag(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.into(), v.into())),
+        _ => Err(format!("expected key=value, got {:?}", s)),
+    }
+}
+
+struct Output {
+    format: Format,
+    /// Leave out everything but the readings in text output
+    quiet: bool,
+    link: Link,
+    influx: Influx,
+    /// Publish readings and status here too
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<mqtt::Publisher>,
+    /// Serve the latest reading and status over HTTP
+    http: Option<http::HttpServer>,
+    /// Stream readings to clients of a Unix socket
+    socket: Option<socket::SocketServer>,
+}
+
+/// Driver settings for every sensor the CLI talks to.
+#[derive(Clone, Copy)]
+struct Link {
+    /// Dump frames on stderr
+    trace: bool,
+    response_timeout: Duration,
+    retries: u8,
+    clamp_negative: bool,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Print the serial number, product type, versions and auto cleaning
+    /// interval. Exits 8 if the device isn't an SPS30
+    Info,
+    /// Print firmware, hardware and SHDLC versions
+    Version,
+    /// Read the device status register. Exits 0 when healthy, 1 with
+    /// warnings, 2 with errors and 3 if the sensor couldn't be read
+    Status {
+        /// Clear the register after reading it
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Start measuring and print readings until interrupted
+    Measure(MeasureArgs),
+    /// Measure continuously as a service, reconnecting on errors and stopping
+    /// measurement cleanly on SIGINT/SIGTERM
+    Daemon(DaemonArgs),
+    /// Measure and publish readings to an MQTT broker
+    #[cfg(feature = "mqtt")]
+    Mqtt(MqttCmd),
+    /// Live dashboard with readings, history and fault indicators
+    Tui,
+    /// Reset the sensor
+    Reset,
+    /// Put the sensor to sleep
+    Sleep {
+        /// Stop measurement first if the sensor is measuring
+        #[arg(long)]
+        force: bool,
+    },
+    /// Wake the sensor up from sleep
+    Wake,
+    /// Show or set the automatic fan cleaning interval
+    Autoclean {
+        /// New interval in seconds, 0 disables automatic cleaning
+        seconds: Option<u32>,
+        /// Required to disable automatic cleaning
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Run fan cleaning and check the fan and laser afterwards
+    Clean {
+        /// Trigger cleaning and exit without waiting for it to finish
+        #[arg(long)]
+        no_wait: bool,
+    },
+    /// Probe the available serial ports for an SPS30
+    Scan {
+        /// Print only the first matching port
+        #[arg(long)]
+        first: bool,
+        /// How long to wait for each port to answer
+        #[arg(long, default_value = "500ms", value_parser = humantime::parse_duration)]
+        timeout: Duration,
+    },
+    /// Send a command by number and print the state byte and response, for
+    /// commands the driver doesn't implement. Exits 1 if the state byte
+    /// carries an error code
+    Raw {
+        /// Command byte in hex, e.g. `d2`
+        #[arg(value_parser = parse_hex_byte)]
+        cmd: u8,
+        /// Data bytes in hex, e.g. `01`
+        #[arg(value_parser = parse_hex_byte)]
+        data: Vec<u8>,
+    },
+    /// Run against a captured session file instead of a serial port. Exits 1
+    /// if the session stops before the end of the capture
+    Replay { file: PathBuf },
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCmd),
+}
+
+impl Cmd {
+    /// Response timeout when none is given on the command line.
+    fn response_timeout(&self) -> Duration {
+        match self {
+            // the sensor takes a while to come back from a reset or sleep
+            Cmd::Reset | Cmd::Wake => Duration::from_secs(2),
+            Cmd::Clean { .. } => CLEANING_TIME,
+            _ => Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigCmd {
+    /// Print the effective configuration, after applying command line flags
+    Show,
+}
+
+#[cfg(feature = "mqtt")]
+#[derive(ClapArgs)]
+struct MqttCmd {
+    #[command(flatten)]
+    measure: MeasureArgs,
+    #[command(flatten)]
+    mqtt: mqtt::MqttArgs,
+}
+
+#[derive(ClapArgs)]
+struct DaemonArgs {
+    #[command(flatten)]
+    measure: MeasureArgs,
+    /// Serve the latest reading on this address, e.g. `127.0.0.1:8080`, at
+    /// /measurement, /status and /info
+    #[arg(long)]
+    http: Option<std::net::SocketAddr>,
+    /// Stream readings as JSON lines to clients of this Unix socket
+    #[arg(long)]
+    socket: Option<PathBuf>,
+}
+
+#[derive(ClapArgs)]
+struct MeasureArgs {
+    /// Take a single reading, then stop measuring and exit
+    #[arg(long, conflicts_with = "count")]
+    once: bool,
+    /// Take this many readings, then stop measuring and exit
+    #[arg(long)]
+    count: Option<usize>,
+    /// Time between readings, e.g. `1s`, `500ms`, `2m` [default: 1s]
+    #[arg(long, value_parser = humantime::parse_duration)]
+    interval: Option<Duration>,
+    /// Give up if no reading arrives within this time
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+    /// Also append readings to this CSV file
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// Start a new, dated, CSV file every day [default: never]
+    #[arg(long, value_enum)]
+    rotate: Option<Rotate>,
+    /// Highlight PM2.5 mass concentrations above this, in µg/m³
+    #[arg(long)]
+    warn_pm25: Option<f32>,
+    /// Raise an alert for PM2.5 mass concentrations above this, in µg/m³
+    #[arg(long)]
+    alert_pm25: Option<f32>,
+    /// Highlight PM10 mass concentrations above this, in µg/m³
+    #[arg(long)]
+    warn_pm10: Option<f32>,
+    /// Raise an alert for PM10 mass concentrations above this, in µg/m³
+    #[arg(long)]
+    alert_pm10: Option<f32>,
+    /// Use the WHO 2021 levels for any thresholds not given: warn above the
+    /// 24-hour guidelines and alert above the first interim targets
+    #[arg(long)]
+    who: bool,
+    /// Output the mean of this many readings instead of every reading
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    average: Option<u32>,
+    /// Include the minimum and maximum of each averaged field
+    #[arg(long, requires = "average")]
+    min_max: bool,
+    /// Also output this percentile (0 to 100) of each averaged field, e.g. 95
+    #[arg(long, requires = "average", value_parser = parse_percentile)]
+    percentile: Option<f32>,
+    /// Shell command to run when an alert is raised. SPS30_FIELD and
+    /// SPS30_VALUE are set in its environment
+    #[arg(long)]
+    on_alert: Option<String>,
+    /// Show an air quality index, computed from each reading (or mean), alongside it
+    #[arg(long, value_enum)]
+    aqi: Option<AqiArg>,
+}
+
+impl Default for MeasureArgs {
+    fn default() -> Self {
+        Self {
+            once: false,
+            count: None,
+            interval: None,
+            timeout: Duration::from_secs(10),
+            output: None,
+            rotate: None,
+            warn_pm25: None,
+            alert_pm25: None,
+            warn_pm10: None,
+            alert_pm10: None,
+            who: false,
+            average: None,
+            min_max: false,
+            percentile: None,
+            on_alert: None,
+            aqi: None,
+        }
+    }
+}
+
+impl MeasureArgs {
+    fn limit(&self) -> Option<usize> {
+        if self.once { Some(1) } else { self.count }
+    }
+    fn alarms(&self) -> Alarms {
+        let who = |p| self.who.then(|| who_guideline(p, Period::Daily));
+        let interim = |t| self.who.then_some(t);
+        Alarms::new(
+            (
+                self.warn_pm25.or(who(Pollutant::Pm2_5)),
+                self.alert_pm25.or(interim(WHO_INTERIM_PM25)),
+            ),
+            (
+                self.warn_pm10.or(who(Pollutant::Pm10)),
+                self.alert_pm10.or(interim(WHO_INTERIM_PM10)),
+            ),
+            self.on_alert.clone(),
+        )
+    }
+    fn interval(&self) -> Duration {
+        self.interval.unwrap_or(Duration::from_secs(1))
+    }
+    /// Fill in anything not given on the command line from the config file.
+    fn apply_config(&mut self, config: &Config) {
+        self.interval = self.interval.or(config.interval());
+        self.output = self.output.take().or(config.output.clone());
+        self.rotate = self.rotate.or(config.rotate);
+    }
+}
+
+/// Set when the measurement loop should stop measurement and return.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// WHO 2021 24-hour interim target 1, the loosest step towards the
+/// guidelines, in µg/m³. Used as the `--who` alert thresholds.
+const WHO_INTERIM_PM25: f32 = 75.0;
+const WHO_INTERIM_PM10: f32 = 150.0;
+
+/// The sensor only produces a new reading once a second.
+const SENSOR_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// exit codes
+const EXIT_DEVICE: i32 = 1;
+const EXIT_PORT: i32 = 2;
+const EXIT_FAULT: i32 = 3;
+const EXIT_NO_DATA: i32 = 4;
+const EXIT_IO: i32 = 5;
+const EXIT_CONFIG: i32 = 6;
+const EXIT_ALERT: i32 = 7;
+const EXIT_NOT_SPS30: i32 = 8;
+
+fn list_ports(filter: &PortFilter) {
+    println!("Serial Ports:");
+    for port in ports::available(filter) {
+        println!("\t{} ({})", port.port_name, ports::describe(&port));
+    }
+}
+
+fn open_port(port: &str, baud: usize) -> Result<Box<dyn SerialPort>, serialport::Error> {
+    log::info!("Serialport: {}@{}", port, baud);
+    let mut p = serialport::new(port, baud as u32).open()?;
+
+    log::debug!("Port: {}", p.name().unwrap_or_default());
+    p.set_timeout(Duration::from_millis(20))?;
+
+    log::debug!("Clear existing input");
+    let mut buf = Vec::new();
+    match p.try_clone()?.read_to_end(&mut buf) {
+        Ok(d) => log::debug!("Read {} bytes", d),
+        Err(e) => log::debug!("Error: {}", e),
+    }
+
+    Ok(p)
+}
+
+/// Timestamped hex dump of a frame, followed by its decoded contents.
+fn trace_frame(direction: Direction, raw: &[u8]) {
+    let hex: Vec<String> = raw.iter().map(|b| format!("{:02x}", b)).collect();
+    let time = chrono::Local::now().format("%H:%M:%S%.6f");
+    eprintln!("{} {} {}", time, direction.as_str(), hex.join(" "));
+    let frame = match direction {
+        Direction::Tx => Frame::decode_request(raw).ok(),
+        Direction::Rx => Frame::decode(raw).ok().map(|(_, f)| f),
+    };
+    match frame {
+        Some(f) => eprintln!(
+            "{:>15}    addr {:#04x} {:?} data [{}]",
+            "",
+            f.addr,
+            f.cmd,
+            f.data
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        None => eprintln!("{:>15}    undecodable", ""),
+    }
+}
+
+/// Wrap a port in the driver, with the timeout and retries from the command
+/// line.
+fn new_sensor<P: Read + Write>(port: P, link: Link) -> Sps30<P> {
+    let config = Sps30Config {
+        response_timeout: Some(link.response_timeout),
+        retry: RetryPolicy {
+            retries: link.retries,
+            ..Default::default()
+        },
+        clamp_negative: link.clamp_negative,
+        ..Default::default()
+    };
+    let mut builder = Sps30Builder::new().config(config);
+    if link.trace {
+        builder = builder.trace(trace_frame);
+    }
+    builder.build(port)
+}
+
+/// Describe a driver error, with the number of attempts if it was retried.
+fn describe_error<P: Transport>(e: &DeviceError, sensor: &Sps30<P>) -> String {
+    match sensor.last_attempts() {
+        n if n > 1 => format!("{} after {} attempts", e, n),
+        _ => e.to_string(),
+    }
+}
+
+fn faults_json(status: &Option<DeviceStatus>) -> serde_json::Value {
+    let faults: Vec<&str> = status
+        .iter()
+        .flat_map(DeviceStatus::faults)
+        .map(|f| f.name())
+        .collect();
+    json!({ "ok": faults.is_empty(), "faults": faults })
+}
+
+fn aqi_json(a: &AirQuality) -> serde_json::Value {
+    match a {
+        AirQuality::Us(a) => json!({
+            "standard": "us",
+            "value": a.value,
+            "category": a.category.to_string(),
+            "dominant": a.dominant.to_string(),
+        }),
+        AirQuality::Eu(a) => json!({
+            "standard": "eu",
+            "value": a.category.level(),
+            "category": a.category.to_string(),
+            "dominant": a.dominant.to_string(),
+        }),
+    }
+}
+
+fn measurement_json(
+    m: &Sps30Measurement,
+    avg: Option<(&Aggregate, bool)>,
+    aqi: Option<&AirQuality>,
+    serial: &Option<String>,
+    alarms: &Alarms,
+) -> serde_json::Value {
+    let mut v = serde_json::to_value(m).unwrap();
+    v["timestamp"] = json!(chrono::Local::now().to_rfc3339());
+    if let Some(serial) = serial {
+        v["serial"] = json!(serial);
+    }
+    if !alarms.is_empty() {
+        v["alarm"] = alarms.json();
+    }
+    if let Some((a, min_max)) = avg {
+        v["average"] = a.json(min_max);
+    }
+    if let Some(a) = aqi {
+        v["aqi"] = aqi_json(a);
+    }
+    v
+}
+
+fn print_measurement(
+    out: &Output,
+    m: &Sps30Measurement,
+    avg: Option<(&Aggregate, bool)>,
+    aqi: Option<&AirQuality>,
+    serial: &Option<String>,
+    alarms: &Alarms,
+) {
+    match out.format {
+        Format::Text => {
+            if let Some((a, min_max)) = avg.filter(|_| !out.quiet) {
+                colour::blue_ln!("Mean of {} samples", a.stats.samples);
+                if min_max {
+                    println!("Min: {:#?}\nMax: {:#?}", a.stats.min, a.stats.max);
+                }
+                if let Some((p, m)) = &a.percentile {
+                    println!("{}th percentile: {:#?}", p, m);
+                }
+            }
+            match alarms.level() {
+                Level::Alert => colour::red_ln!("{:#?}", m),
+                Level::Warning => colour::yellow_ln!("{:#?}", m),
+                Level::Normal => println!("{:#?}", m),
+            }
+            if let Some(a) = aqi {
+                println!("{}", a);
+            }
+        }
+        Format::Influx => println!(
+            "{}",
+            out.influx.line(chrono::Local::now(), m, serial.as_deref())
+        ),
+        Format::Json => println!("{}", measurement_json(m, avg, aqi, serial, alarms)),
+    }
+}
+
+/// Where readings go once they've been taken (and averaged).
+struct Sink<'a> {
+    out: &'a Output,
+    serial: Option<String>,
+    alarms: Alarms,
+    log: Option<CsvLog>,
+    min_max: bool,
+    aqi: Option<AqiStandard>,
+}
+
+impl Sink<'_> {
+    fn emit(
+        &mut self,
+        m: &Sps30Measurement,
+        avg: Option<&Aggregate>,
+        status: &Option<DeviceStatus>,
+    ) -> std::io::Result<()> {
+        for (field, v, level) in self.alarms.update(m) {
+            match level {
+                Level::Alert => colour::e_red_ln!("{} alert: {}", field, v),
+                Level::Warning => colour::e_yellow_ln!("{} warning: {}", field, v),
+                Level::Normal => colour::e_green_ln!("{} back to normal: {}", field, v),
+            }
+        }
+        let avg = avg.map(|a| (a, self.min_max));
+        let aqi = self.aqi.map(|s| s.assess(m));
+        let aqi = aqi.as_ref();
+        print_measurement(self.out, m, avg, aqi, &self.serial, &self.alarms);
+        if let Some(socket) = &self.out.socket {
+            socket.publish(&measurement_json(m, avg, aqi, &self.serial, &self.alarms));
+        }
+        if let Some(http) = &self.out.http {
+            http.reading(&measurement_json(m, avg, aqi, &self.serial, &self.alarms));
+            http.status(faults_json(status));
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.out.mqtt {
+            mqtt.reading(&measurement_json(m, avg, aqi, &self.serial, &self.alarms));
+            mqtt.status(&faults_json(status));
+        }
+        match &mut self.log {
+            Some(log) => log.write(chrono::Local::now(), m, status),
+            None => Ok(()),
+        }
+    }
+
+    /// Output a partially filled averaging window.
+    fn flush(&mut self, window: &mut Option<Window>, status: &Option<DeviceStatus>) {
+        if let Some(a) = window.as_mut().and_then(Window::flush)
+            && let Err(e) = self.emit(&a.stats.mean, Some(&a), status)
+        {
+            eprintln!("Can't write CSV log: {}", e);
+        }
+    }
+}
+
+fn measure<P: Read + Write>(
+    sensor: &mut Sps30<P>,
+    args: &MeasureArgs,
+    out: &Output,
+) -> Result<i32, DeviceError> {
+    let format = out.format;
+    let interval = args.interval();
+    if !interval.is_zero() && interval < SENSOR_UPDATE_INTERVAL {
+        colour::e_yellow_ln!(
+            "Warning: interval {} is shorter than the sensor update interval of {}",
+            humantime::format_duration(interval),
+            humantime::format_duration(SENSOR_UPDATE_INTERVAL)
+        );
+    }
+
+    let serial = match format {
+        Format::Json | Format::Influx => sensor.read_serial_number(),
+        Format::Text => None,
+    };
+    sensor.start_measurement()?;
+    notify::ready();
+
+    let mut sink = Sink {
+        out,
+        serial,
+        alarms: args.alarms(),
+        log: args
+            .output
+            .as_ref()
+            .map(|p| CsvLog::new(p.clone(), args.rotate.unwrap_or(Rotate::Never))),
+        min_max: args.min_max,
+        aqi: args.aqi.map(AqiStandard::from),
+    };
+    let mut window = args.average.map(|n| Window::new(n, args.percentile));
+    let mut status = None;
+    let limit = args.limit();
+    let mut readings = 0;
+    let mut last_reading = Instant::now();
+    let mut next = Instant::now() + interval;
+    loop {
+        if SHUTDOWN.load(Ordering::SeqCst) {
+            sink.flush(&mut window, &status);
+            sensor.stop_measurement()?;
+            return Ok(0);
+        }
+
+        // sleep to a fixed schedule so time spent talking to the sensor
+        // doesn't stretch the interval
+        let now = Instant::now();
+        if next > now {
+            sleep(next - now);
+            next += interval;
+        } else {
+            next = now + interval;
+        }
+
+        let s = sensor.read_and_clear_device_status()?;
+        status = Some(s);
+        if format == Format::Text && !out.quiet {
+            colour::blue_ln!("Time: {}", chrono::Local::now());
+            if s.is_healthy() {
+                colour::green_ln!("Sensor OK");
+            } else {
+                colour::red_ln!("Sensor Status: {}", s);
+            }
+        } else if !s.is_healthy() {
+            eprintln!("Sensor Status: {}", s);
+        }
+
+        let measurement = sensor.read_measurement()?;
+        match measurement {
+            None if format == Format::Text && !out.quiet => colour::yellow_ln!("No new data"),
+            None => (),
+            Some(m) => {
+                last_reading = Instant::now();
+                notify::watchdog();
+                let r = match &mut window {
+                    None => Some(sink.emit(&m, None, &status)),
+                    Some(w) => w
+                        .push(&m)
+                        .map(|a| sink.emit(&a.stats.mean, Some(&a), &status)),
+                };
+                match r {
+                    Some(Ok(())) => readings += 1,
+                    Some(Err(e)) => {
+                        eprintln!("Can't write CSV log: {}", e);
+                        sensor.stop_measurement()?;
+                        return Ok(EXIT_IO);
+                    }
+                    None => (),
+                }
+            }
+        }
+
+        if limit.is_some_and(|n| readings >= n) {
+            sensor.stop_measurement()?;
+            return Ok(if sink.alarms.level() == Level::Alert {
+                EXIT_ALERT
+            } else {
+                0
+            });
+        }
+        if last_reading.elapsed() > args.timeout {
+            eprintln!(
+                "No data from the sensor within {}",
+                humantime::format_duration(args.timeout)
+            );
+            sink.flush(&mut window, &status);
+            sensor.stop_measurement()?;
+            return Ok(EXIT_NO_DATA);
+        }
+    }
+}
+
+/// Fan cleaning takes 10 seconds, give it a little longer.
+const CLEANING_TIME: Duration = Duration::from_secs(11);
+
+fn clean<P: Read + Write>(
+    sensor: &mut Sps30<P>,
+    no_wait: bool,
+    out: &Output,
+) -> Result<i32, DeviceError> {
+    // cleaning is only accepted while measuring
+    if sensor.start_fan_cleaning().is_err() {
+        sensor.start_measurement()?;
+        sensor.start_fan_cleaning()?;
+    }
+    if no_wait {
+        eprintln!("Fan cleaning started");
+        return Ok(0);
+    }
+
+    let start = Instant::now();
+    while start.elapsed() < CLEANING_TIME {
+        eprint!(
+            "\rCleaning... {:>2}s",
+            (CLEANING_TIME - start.elapsed()).as_secs()
+        );
+        sleep(Duration::from_secs(1));
+    }
+    eprintln!("\rCleaning done    ");
+
+    let status = sensor.read_and_clear_device_status()?;
+    match out.format {
+        Format::Json => println!("{}", faults_json(&Some(status))),
+        _ if status.is_healthy() => colour::green_ln!("Fan and laser OK"),
+        _ => colour::red_ln!("Faults after cleaning: {}", status),
+    }
+    if !status.is_healthy() {
+        return Ok(EXIT_FAULT);
+    }
+    Ok(0)
+}
+
+fn print_interval(format: Format, seconds: u32) {
+    match format {
+        Format::Json => println!("{}", json!({ "auto_cleaning_interval": seconds })),
+        _ if seconds == 0 => println!("Auto cleaning disabled"),
+        _ => println!(
+            "Auto cleaning interval: {} s ({})",
+            seconds,
+            humantime::format_duration(Duration::from_secs(seconds as u64))
+        ),
+    }
+}
+
+fn info<P: Read + Write>(sensor: &mut Sps30<P>, format: Format) -> i32 {
+    // checked on its own first, as something that isn't an SPS30 is unlikely
+    // to get through the other reads
+    match sensor.identify() {
+        Ok(DeviceModel::Sps30) => (),
+        Ok(m) => {
+            eprintln!("Not an SPS30: {}", m);
+            return EXIT_NOT_SPS30;
+        }
+        Err(e) => {
+            eprintln!("No product type from the device: {}", e);
+            return EXIT_DEVICE;
+        }
+    }
+    let id = match sensor.device_identity() {
+        Ok(id) => id,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_DEVICE;
+        }
+    };
+    let interval = sensor.read_auto_cleaning_interval().ok();
+
+    match format {
+        Format::Json => println!(
+            "{}",
+            json!({
+                "serial": id.serial,
+                "product_type": id.product_type,
+                "firmware": id.version.firmware.to_string(),
+                "hardware": id.version.hardware.to_string(),
+                "shdlc": id.version.shdlc.to_string(),
+                "auto_cleaning_interval": interval,
+            })
+        ),
+        Format::Text | Format::Influx => {
+            let interval = match interval {
+                None => "unknown".to_string(),
+                Some(0) => "disabled".to_string(),
+                Some(s) => format!(
+                    "{} s ({})",
+                    s,
+                    humantime::format_duration(Duration::from_secs(s as u64))
+                ),
+            };
+            let rows = [
+                ("Serial number", id.serial),
+                (
+                    "Product type",
+                    format!("{} ({})", id.product_type, id.model),
+                ),
+                ("Firmware", id.version.firmware.to_string()),
+                ("Hardware", id.version.hardware.to_string()),
+                ("SHDLC", id.version.shdlc.to_string()),
+                ("Auto cleaning", interval),
+            ];
+            for (name, value) in rows {
+                println!("{:<15} {}", name, value);
+            }
+        }
+    }
+    0
+}
+
+fn autoclean<P: Read + Write>(
+    sensor: &mut Sps30<P>,
+    seconds: Option<u32>,
+    confirm: bool,
+    out: &Output,
+) -> Result<i32, DeviceError> {
+    let current = sensor.read_auto_cleaning_interval()?;
+    let Some(seconds) = seconds else {
+        print_interval(out.format, current);
+        return Ok(0);
+    };
+
+    if seconds == 0 && !confirm {
+        eprintln!(
+            "Disabling auto cleaning shortens the sensor lifetime, pass --confirm to do it anyway"
+        );
+        return Ok(EXIT_DEVICE);
+    }
+    sensor.write_auto_cleaning_interval(seconds)?;
+
+    let readback = sensor.read_auto_cleaning_interval()?;
+    if readback == seconds {
+        print_interval(out.format, readback);
+    } else if readback == current {
+        // the sensor reports the old value until it is reset
+        print_interval(out.format, seconds);
+        eprintln!("New interval takes effect now but is reported after the next reset");
+    } else {
+        eprintln!("Read back {} s after setting {} s", readback, seconds);
+        return Ok(EXIT_DEVICE);
+    }
+    Ok(0)
+}
+
+// status subcommand exit codes, as used by Nagios style checks
+const STATUS_OK: i32 = 0;
+const STATUS_WARNING: i32 = 1;
+const STATUS_ERROR: i32 = 2;
+const STATUS_UNKNOWN: i32 = 3;
+
+fn status<P: Read + Write>(sensor: &mut Sps30<P>, clear: bool, out: &Output) -> i32 {
+    let r = if clear {
+        sensor.read_and_clear_device_status()
+    } else {
+        sensor.peek_device_status()
+    };
+    let status = match r {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Can't read device status: {}", e);
+            return STATUS_UNKNOWN;
+        }
+    };
+
+    match out.format {
+        Format::Json => {
+            let list: Vec<_> = status
+                .faults()
+                .map(|f| {
+                    json!({
+                        "fault": f.name(),
+                        "description": f.to_string(),
+                        "severity": f.severity().to_string(),
+                    })
+                })
+                .collect();
+            println!("{}", json!({ "ok": status.is_healthy(), "faults": list }));
+        }
+        _ if status.is_healthy() => colour::green_ln!("Sensor OK"),
+        _ => {
+            for f in status.faults() {
+                match f.severity() {
+                    Severity::Error => colour::red_ln!("Error: {}", f),
+                    Severity::Warning => colour::yellow_ln!("Warning: {}", f),
+                }
+            }
+        }
+    }
+
+    match status.severity() {
+        Some(Severity::Error) => STATUS_ERROR,
+        Some(Severity::Warning) => STATUS_WARNING,
+        None => STATUS_OK,
+    }
+}
+
+fn replay(file: PathBuf, out: &Output) -> i32 {
+    let capture = match std::fs::read_to_string(&file) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", file.display(), e);
+            return EXIT_PORT;
+        }
+    };
+    let mut port = match ReplayPort::from_capture(&capture) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Can't parse {}: {}", file.display(), e);
+            return EXIT_PORT;
+        }
+    };
+    let mut sensor = new_sensor(&mut port, out.link);
+
+    // The replay port refuses writes past the end of the capture, so the
+    // session ends with an error. It only ended cleanly if the whole capture
+    // was played back.
+    let r = sensor.device_reset().and_then(|_| {
+        eprintln!("Device info: {:#?}", sensor.get_device_info());
+        eprintln!("Device versions: {}", sensor.read_version()?);
+        let args = MeasureArgs {
+            interval: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        measure(&mut sensor, &args, out)
+    });
+    drop(sensor);
+    match r {
+        Ok(code) => code,
+        Err(_) if port.is_done() => 0,
+        Err(e) => {
+            eprintln!("Replay failed before the end of the capture: {}", e);
+            EXIT_DEVICE
+        }
+    }
+}
+
+fn run<P: Read + Write>(cmd: Cmd, sensor: &mut Sps30<P>, out: &Output) -> Result<i32, DeviceError> {
+    let format = out.format;
+    match cmd {
+        Cmd::Info => return Ok(info(sensor, format)),
+        Cmd::Version => {
+            let v = sensor.read_version()?;
+            match format {
+                Format::Text | Format::Influx => println!("Device versions: {}", v),
+                Format::Json => println!(
+                    "{}",
+                    json!({
+                        "firmware": v.firmware.to_string(),
+                        "hardware": v.hardware.to_string(),
+                        "shdlc": v.shdlc.to_string(),
+                    })
+                ),
+            }
+        }
+        Cmd::Status { clear } => return Ok(status(sensor, clear, out)),
+        Cmd::Measure(args) => return measure(sensor, &args, out),
+        Cmd::Reset => sensor.device_reset()?,
+        Cmd::Clean { no_wait } => return clean(sensor, no_wait, out),
+        Cmd::Autoclean { seconds, confirm } => return autoclean(sensor, seconds, confirm, out),
+        Cmd::Sleep { force } => {
+            if force {
+                // fails harmlessly when the sensor is already idle
+                sensor.stop_measurement().ok();
+            }
+            if let Err(e) = sensor.sleep() {
+                eprintln!(
+                    "Sensor refused to sleep, it may be measuring. Use --force to stop it first"
+                );
+                return Err(e);
+            }
+            eprintln!("Sensor sleeping");
+        }
+        Cmd::Tui => return Ok(tui::run(sensor)),
+        Cmd::Raw { cmd, data } => {
+            let (state, data) = sensor.send_raw(cmd, &data)?;
+            match format {
+                Format::Json => println!("{}", json!({ "state": state, "data": data })),
+                _ => println!("State {:#04x}, data {:02x?}", state, data),
+            }
+            // the top bit only flags the status register
+            if state & 0x7f != 0 {
+                return Ok(1);
+            }
+        }
+        Cmd::Wake => {
+            sensor.wake_up()?;
+            let v = sensor.read_version()?;
+            match format {
+                Format::Json => println!(
+                    "{}",
+                    json!({ "awake": true, "firmware": v.firmware.to_string() })
+                ),
+                _ => println!("Sensor awake, firmware {}", v.firmware),
+            }
+        }
+        Cmd::Replay { .. } | Cmd::Scan { .. } | Cmd::Config(_) | Cmd::Daemon(_) => {
+            unreachable!()
+        }
+        #[cfg(feature = "mqtt")]
+        Cmd::Mqtt(_) => unreachable!(),
+    }
+    Ok(0)
+}
+
+fn main() {
+    let args = Args::parse();
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => "error",
+        (_, 0) => "warn,linux_test=info",
+        (_, 1) => "info",
+        (_, 2) => "debug",
+        _ => "trace",
+    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
+
+    let config = match config::load(args.config.as_deref()) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(EXIT_CONFIG);
+        }
+    };
+    let port = args.port.or(config.port.clone());
+    let baud = args.baud.or(config.baud).unwrap_or(115200);
+    let format = args.format.or(config.format).unwrap_or(Format::Text);
+
+    let mut out = Output {
+        format,
+        quiet: args.quiet,
+        link: Link {
+            trace: args.trace_frames,
+            response_timeout: args.response_timeout.unwrap_or_else(|| {
+                args.command
+                    .as_ref()
+                    .map_or(Duration::from_millis(500), Cmd::response_timeout)
+            }),
+            retries: args.retries,
+            clamp_negative: args.clamp_negative,
+        },
+        influx: Influx {
+            measurement: args.influx_measurement,
+            tags: args.influx_tags,
+        },
+        #[cfg(feature = "mqtt")]
+        mqtt: None,
+        http: None,
+        socket: None,
+    };
+
+    let filter = PortFilter {
+        usb_only: args.usb_only,
+        vid: args.vid,
+        pid: args.pid,
+    };
+
+    let cmd = match args.command {
+        Some(Cmd::Replay { file }) => exit(replay(file, &out)),
+        Some(Cmd::Config(ConfigCmd::Show)) => {
+            let effective = Config {
+                port,
+                baud: Some(baud),
+                format: Some(format),
+                ..config
+            };
+            print!("{}", toml::to_string(&effective).unwrap());
+            return;
+        }
+        Some(Cmd::Scan { first, timeout }) => {
+            let found = scan::scan(&filter, baud as u32, timeout, first, out.link);
+            for f in &found {
+                match (out.format, first) {
+                    (_, true) => println!("{}", f.port),
+                    (Format::Json, false) => {
+                        println!("{}", json!({ "port": f.port, "firmware": f.firmware }))
+                    }
+                    _ => println!(
+                        "{}: SPS30, firmware {}",
+                        f.port,
+                        f.firmware.as_deref().unwrap_or("unknown")
+                    ),
+                }
+            }
+            exit(if found.is_empty() { EXIT_PORT } else { 0 });
+        }
+        Some(Cmd::Measure(mut m)) => {
+            m.apply_config(&config);
+            Cmd::Measure(m)
+        }
+        #[cfg(feature = "mqtt")]
+        Some(Cmd::Mqtt(mut m)) => {
+            m.measure.apply_config(&config);
+            Cmd::Mqtt(m)
+        }
+        Some(Cmd::Daemon(mut d)) => {
+            d.measure.apply_config(&config);
+            let Some(port) = port else {
+                eprintln!("--port is required");
+                exit(EXIT_PORT);
+            };
+            if let Some(addr) = d.http {
+                match http::HttpServer::start(addr) {
+                    Ok(s) => out.http = Some(s),
+                    Err(e) => {
+                        eprintln!("Can't serve HTTP on {}: {}", addr, e);
+                        exit(EXIT_IO);
+                    }
+                }
+            }
+            if let Some(path) = d.socket {
+                match socket::SocketServer::start(path.clone()) {
+                    Ok(s) => out.socket = Some(s),
+                    Err(e) => {
+                        eprintln!("Can't listen on {}: {}", path.display(), e);
+                        exit(EXIT_IO);
+                    }
+                }
+            }
+            let code = daemon::run(&port, baud, &d.measure, &out);
+            // exit() skips destructors, and the socket file needs removing
+            drop(out);
+            exit(code);
+        }
+        Some(cmd) => cmd,
+        None if port.is_some() => {
+            let mut m = MeasureArgs::default();
+            m.apply_config(&config);
+            Cmd::Measure(m)
+        }
+        None => {
+            list_ports(&filter);
+            return;
+        }
+    };
+    let Some(port) = port else {
+        eprintln!("--port is required");
+        list_ports(&filter);
+        exit(EXIT_PORT);
+    };
+
+    let p = match open_port(&port, baud) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Serial port can't be opened: {}", e);
+            exit(EXIT_PORT);
+        }
+    };
+
+    let mut sensor = new_sensor(p, out.link);
+    #[cfg(feature = "mqtt")]
+    let cmd = match cmd {
+        Cmd::Mqtt(m) => {
+            let serial = sensor.read_serial_number();
+            let firmware = sensor.read_version().ok().map(|v| v.firmware.to_string());
+            out.mqtt = Some(mqtt::Publisher::connect(
+                &m.mqtt,
+                serial.as_deref(),
+                firmware.as_deref(),
+            ));
+            Cmd::Measure(m.measure)
+        }
+        cmd => cmd,
+    };
+    match run(cmd, &mut sensor, &out) {
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", describe_error(&e, &sensor));
+            exit(EXIT_DEVICE);
+        }
+    }
+}
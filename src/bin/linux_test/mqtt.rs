@@ -0,0 +1,306 @@
+//! Publishing readings to an MQTT broker.
+use std::{cell::RefCell, thread, time::Duration};
+
+use clap::Args as ClapArgs;
+use log::{info, warn};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS, Transport};
+use serde_json::{Value, json};
+use sps30_hdlc::Sps30Measurement;
+
+/// Longest wait between reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(ClapArgs)]
+pub struct MqttArgs {
+    /// Broker host name
+    #[arg(long, default_value = "localhost")]
+    pub host: String,
+    /// Broker port [default: 1883, or 8883 with --tls]
+    #[arg(long)]
+    pub broker_port: Option<u16>,
+    /// Connect to the broker over TLS
+    #[arg(long)]
+    pub tls: bool,
+    #[arg(long)]
+    pub username: Option<String>,
+    #[arg(long, requires = "username")]
+    pub password: Option<String>,
+    /// Client ID [default: sps30-<serial>]
+    #[arg(long)]
+    pub client_id: Option<String>,
+    /// Topic readings are published to. Faults go to the sibling `status`
+    /// topic and online/offline to `availability` [default:
+    /// sps30/<serial>/measurement]
+    #[arg(long)]
+    pub topic: Option<String>,
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub qos: u8,
+    /// Ask the broker to retain the latest reading
+    #[arg(long)]
+    pub retain: bool,
+    /// Announce the sensor to Home Assistant through MQTT discovery
+    #[arg(long)]
+    pub ha_discovery: bool,
+    /// Home Assistant discovery prefix
+    #[arg(long, default_value = "homeassistant")]
+    pub ha_prefix: String,
+}
+
+/// Home Assistant entity name and device class for each field of a reading,
+/// in field order.
+const HA_FIELDS: [(&str, Option<&str>); 10] = [
+    ("PM1.0", Some("pm1")),
+    ("PM2.5", Some("pm25")),
+    ("PM4.0", None),
+    ("PM10", Some("pm10")),
+    ("PM0.5 count", None),
+    ("PM1.0 count", None),
+    ("PM2.5 count", None),
+    ("PM4.0 count", None),
+    ("PM10 count", None),
+    ("Typical particle size", None),
+];
+
+/// Discovery config topics and payloads, one per field, all sharing a device
+/// keyed on the serial number.
+pub fn ha_discovery(
+    prefix: &str,
+    serial: &str,
+    firmware: Option<&str>,
+    state_topic: &str,
+    availability_topic: &str,
+) -> Vec<(String, Value)> {
+    let id = format!("sps30_{}", serial);
+    let mut device = json!({
+        "identifiers": [id],
+        "name": format!("SPS30 {}", serial),
+        "manufacturer": "Sensirion",
+        "model": "SPS30",
+    });
+    if let Some(fw) = firmware {
+        device["sw_version"] = json!(fw);
+    }
+    HA_FIELDS
+        .iter()
+        .zip(Sps30Measurement::zeros().fields())
+        .map(|(&(name, class), (key, _, unit))| {
+            let mut config = json!({
+                "name": name,
+                "unique_id": format!("{}_{}", id, key),
+                "state_topic": state_topic,
+                "value_template": format!("{{{{ value_json.{} }}}}", key),
+                "unit_of_measurement": unit.to_string(),
+                "state_class": "measurement",
+                "availability_topic": availability_topic,
+                "device": device,
+            });
+            if let Some(class) = class {
+                config["device_class"] = json!(class);
+            }
+            (format!("{}/sensor/{}/{}/config", prefix, id, key), config)
+        })
+        .collect()
+}
+
+fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+pub struct Publisher {
+    client: Client,
+    topic: String,
+    status_topic: String,
+    qos: QoS,
+    retain: bool,
+    last_status: RefCell<Option<String>>,
+}
+
+impl Publisher {
+    /// Set up the connection, which is made and kept up in the background.
+    /// Publishing never blocks, so losing the broker doesn't hold up polling
+    /// the sensor.
+    pub fn connect(args: &MqttArgs, serial: Option<&str>, firmware: Option<&str>) -> Self {
+        let serial = serial.unwrap_or("unknown");
+        let topic = args
+            .topic
+            .clone()
+            .unwrap_or_else(|| format!("sps30/{}/measurement", serial));
+        let base = topic.rsplit_once('/').map_or("", |(b, _)| b);
+        let sibling = |name: &str| match base {
+            "" => name.to_string(),
+            b => format!("{}/{}", b, name),
+        };
+        let availability = sibling("availability");
+
+        let client_id = args
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("sps30-{}", serial));
+        let port = args
+            .broker_port
+            .unwrap_or(if args.tls { 8883 } else { 1883 });
+        let mut opts = MqttOptions::new(client_id, &args.host, port);
+        opts.set_keep_alive(Duration::from_secs(30));
+        opts.set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(user) = &args.username {
+            opts.set_credentials(user, args.password.as_deref().unwrap_or_default());
+        }
+        if args.tls {
+            opts.set_transport(Transport::tls_with_default_config());
+        }
+
+        let (client, mut connection) = Client::new(opts, 64);
+        if args.ha_discovery {
+            let configs = ha_discovery(&args.ha_prefix, serial, firmware, &topic, &availability);
+            for (t, config) in configs {
+                client
+                    .try_publish(t, QoS::AtLeastOnce, true, config.to_string())
+                    .ok();
+            }
+        }
+        let c = client.clone();
+        thread::spawn(move || {
+            let mut backoff = Duration::from_secs(1);
+            // the event loop reconnects by itself when iterated after an error
+            for event in connection.iter() {
+                match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to MQTT broker");
+                        backoff = Duration::from_secs(1);
+                        c.try_publish(&availability, QoS::AtLeastOnce, true, "online")
+                            .ok();
+                    }
+                    Ok(_) => (),
+                    Err(e) => {
+                        warn!(
+                            "MQTT connection error: {}, retrying in {}",
+                            e,
+                            humantime::format_duration(backoff)
+                        );
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        });
+
+        Self {
+            client,
+            status_topic: sibling("status"),
+            topic,
+            qos: qos(args.qos),
+            retain: args.retain,
+            last_status: RefCell::new(None),
+        }
+    }
+
+    fn publish(&self, topic: &str, retain: bool, payload: String) {
+        if let Err(e) = self.client.try_publish(topic, self.qos, retain, payload) {
+            warn!("Dropped MQTT message for {}: {}", topic, e);
+        }
+    }
+
+    pub fn reading(&self, v: &Value) {
+        self.publish(&self.topic, self.retain, v.to_string());
+    }
+
+    /// Publish the device status, retained, whenever it changes.
+    pub fn status(&self, v: &Value) {
+        let payload = v.to_string();
+        let mut last = self.last_status.borrow_mut();
+        if last.as_ref() != Some(&payload) {
+            self.publish(&self.status_topic, true, payload.clone());
+            *last = Some(payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configs() -> Vec<(String, Value)> {
+        ha_discovery(
+            "homeassistant",
+            "ABCD1234",
+            Some("2.2"),
+            "sps30/ABCD1234/measurement",
+            "sps30/ABCD1234/availability",
+        )
+    }
+
+    #[test]
+    fn one_config_topic_per_field() {
+        let topics: Vec<String> = configs().into_iter().map(|(t, _)| t).collect();
+        assert_eq!(topics.len(), 10);
+        assert_eq!(
+            topics[0],
+            "homeassistant/sensor/sps30_ABCD1234/mass_1_0/config"
+        );
+        assert_eq!(
+            topics[9],
+            "homeassistant/sensor/sps30_ABCD1234/typical_particle_size_um/config"
+        );
+    }
+
+    #[test]
+    fn mass_payload() {
+        let (_, c) = &configs()[1];
+        assert_eq!(c["name"], "PM2.5");
+        assert_eq!(c["unique_id"], "sps30_ABCD1234_mass_2_5");
+        assert_eq!(c["device_class"], "pm25");
+        assert_eq!(c["unit_of_measurement"], "µg/m³");
+        assert_eq!(c["state_class"], "measurement");
+        assert_eq!(c["state_topic"], "sps30/ABCD1234/measurement");
+        assert_eq!(c["availability_topic"], "sps30/ABCD1234/availability");
+        assert_eq!(c["value_template"], "{{ value_json.mass_2_5 }}");
+    }
+
+    #[test]
+    fn fields_without_a_device_class_leave_it_out() {
+        let configs = configs();
+        let classes: Vec<Option<&str>> = configs
+            .iter()
+            .map(|(_, c)| c.get("device_class").map(|v| v.as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            classes,
+            [
+                Some("pm1"),
+                Some("pm25"),
+                None,
+                Some("pm10"),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        assert_eq!(configs[4].1["unit_of_measurement"], "#/cm³");
+        assert_eq!(configs[9].1["unit_of_measurement"], "µm");
+    }
+
+    #[test]
+    fn all_share_one_device() {
+        let configs = configs();
+        let device = &configs[0].1["device"];
+        assert_eq!(device["identifiers"], json!(["sps30_ABCD1234"]));
+        assert_eq!(device["name"], "SPS30 ABCD1234");
+        assert_eq!(device["sw_version"], "2.2");
+        assert!(configs.iter().all(|(_, c)| &c["device"] == device));
+
+        let without_fw = ha_discovery("ha", "X", None, "s", "a");
+        assert!(without_fw[0].1["device"].get("sw_version").is_none());
+    }
+}
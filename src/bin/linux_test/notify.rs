@@ -0,0 +1,30 @@
+//! systemd service notifications for `Type=notify` units. These do nothing
+//! without the systemd feature, or when not started by systemd.
+#[cfg(feature = "systemd")]
+use sd_notify::NotifyState;
+
+#[cfg(feature = "systemd")]
+fn notify(state: NotifyState) {
+    // without NOTIFY_SOCKET this returns Ok without doing anything
+    if let Err(e) = sd_notify::notify(false, &[state]) {
+        log::warn!("Can't notify systemd: {}", e);
+    }
+}
+
+/// The sensor answered and is measuring.
+pub fn ready() {
+    #[cfg(feature = "systemd")]
+    notify(NotifyState::Ready);
+}
+
+/// A reading arrived, so the service isn't wedged.
+pub fn watchdog() {
+    #[cfg(feature = "systemd")]
+    notify(NotifyState::Watchdog);
+}
+
+/// Shutting down.
+pub fn stopping() {
+    #[cfg(feature = "systemd")]
+    notify(NotifyState::Stopping);
+}
@@ -0,0 +1,74 @@
+//! Serial port listing and filtering.
+use serialport::{SerialPortInfo, SerialPortType};
+
+/// USB-UART bridges commonly used to connect an SPS30, by (VID, PID).
+/// The SEK-SPS30 evaluation cable is FTDI based.
+const ADAPTERS: &[(u16, u16, &str)] = &[
+    (0x0403, 0x6001, "FTDI FT232R"),
+    (0x0403, 0x6010, "FTDI FT2232"),
+    (0x0403, 0x6014, "FTDI FT232H"),
+    (0x0403, 0x6015, "FTDI FT-X"),
+    (0x10c4, 0xea60, "Silicon Labs CP210x"),
+    (0x10c4, 0xea70, "Silicon Labs CP2105"),
+    (0x1a86, 0x7523, "WCH CH340"),
+    (0x1a86, 0x5523, "WCH CH341"),
+    (0x1a86, 0x55d4, "WCH CH9102"),
+    (0x067b, 0x2303, "Prolific PL2303"),
+];
+
+pub fn adapter_name(vid: u16, pid: u16) -> Option<&'static str> {
+    ADAPTERS
+        .iter()
+        .find(|(v, p, _)| *v == vid && *p == pid)
+        .map(|(_, _, name)| *name)
+}
+
+/// Parse a USB ID given as hex, with or without a 0x prefix.
+pub fn parse_usb_id(s: &str) -> Result<u16, String> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(s, 16).map_err(|e| format!("invalid USB ID {:?}: {}", s, e))
+}
+
+#[derive(Default)]
+pub struct PortFilter {
+    pub usb_only: bool,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+}
+
+impl PortFilter {
+    pub fn matches(&self, port: &SerialPortInfo) -> bool {
+        match &port.port_type {
+            SerialPortType::UsbPort(info) => {
+                self.vid.is_none_or(|v| v == info.vid) && self.pid.is_none_or(|p| p == info.pid)
+            }
+            _ => !self.usb_only && self.vid.is_none() && self.pid.is_none(),
+        }
+    }
+}
+
+pub fn available(filter: &PortFilter) -> Vec<SerialPortInfo> {
+    serialport::available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|p| filter.matches(p))
+        .collect()
+}
+
+pub fn describe(port: &SerialPortInfo) -> String {
+    match &port.port_type {
+        SerialPortType::UsbPort(info) => {
+            let mut s = format!("USB({:04x}:{:04x})", info.vid, info.pid);
+            if let Some(name) = adapter_name(info.vid, info.pid) {
+                s.push_str(&format!(" {}", name));
+            }
+            if let Some(product) = &info.product {
+                s.push_str(&format!(" \"{}\"", product));
+            }
+            s
+        }
+        SerialPortType::BluetoothPort => "Bluetooth".into(),
+        SerialPortType::PciPort => "PCI".into(),
+        SerialPortType::Unknown => "Unkonwn type".into(),
+    }
+}
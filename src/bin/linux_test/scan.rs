@@ -0,0 +1,62 @@
+//! Probe serial ports for an SPS30.
+use std::{io::Read, sync::mpsc, thread, time::Duration};
+
+use sps30_hdlc::DeviceModel;
+
+use crate::ports::{self, PortFilter};
+use crate::{Link, new_sensor};
+
+pub struct Found {
+    pub port: String,
+    pub firmware: Option<String>,
+}
+
+/// Ask the device on `port` for its product type. With retries the driver
+/// can take several response timeouts to give up, so the probe runs on its
+/// own thread and is abandoned if it doesn't answer within `timeout`.
+fn probe(port: String, baud: u32, timeout: Duration, link: Link) -> Option<Found> {
+    let mut p = serialport::new(&port, baud)
+        .timeout(Duration::from_millis(20))
+        .open()
+        .ok()?;
+    let mut discard = Vec::new();
+    p.read_to_end(&mut discard).ok();
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut sensor = new_sensor(p, link);
+        let model = sensor.identify();
+        let firmware = match model {
+            Ok(DeviceModel::Sps30) => sensor.read_version().ok().map(|v| v.firmware.to_string()),
+            _ => None,
+        };
+        tx.send((model, firmware)).ok();
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok((Ok(DeviceModel::Sps30), firmware)) => Some(Found { port, firmware }),
+        _ => None,
+    }
+}
+
+/// Probe every available port, stopping at the first match if `first` is
+/// set.
+pub fn scan(
+    filter: &PortFilter,
+    baud: u32,
+    timeout: Duration,
+    first: bool,
+    link: Link,
+) -> Vec<Found> {
+    let mut found = Vec::new();
+    for info in ports::available(filter) {
+        eprintln!("Probing {}", info.port_name);
+        if let Some(f) = probe(info.port_name, baud, timeout, link) {
+            found.push(f);
+            if first {
+                break;
+            }
+        }
+    }
+    found
+}
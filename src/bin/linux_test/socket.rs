@@ -0,0 +1,70 @@
+//! Streaming readings to local clients over a Unix domain socket.
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use log::{info, warn};
+use serde_json::Value;
+
+pub struct SocketServer {
+    path: PathBuf,
+    clients: Arc<Mutex<Vec<UnixStream>>>,
+}
+
+impl SocketServer {
+    /// Listen on `path`, replacing a socket left behind by an earlier run.
+    pub fn start(path: PathBuf) -> io::Result<Self> {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+        let listener = UnixListener::bind(&path)?;
+        info!("Streaming readings on {}", path.display());
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let shared = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    // a client that can't keep up gets dropped rather than
+                    // holding up the measurement loop
+                    Ok(s) => match s.set_nonblocking(true) {
+                        Ok(()) => {
+                            info!("Socket client connected");
+                            shared.lock().unwrap().push(s);
+                        }
+                        Err(e) => warn!("Socket client setup failed: {}", e),
+                    },
+                    Err(e) => warn!("Socket accept failed: {}", e),
+                }
+            }
+        });
+        Ok(Self { path, clients })
+    }
+
+    /// Send a reading to every client as one line of JSON.
+    pub fn publish(&self, v: &Value) {
+        let line = format!("{}\n", v);
+        self.clients
+            .lock()
+            .unwrap()
+            .retain_mut(|c| match c.write_all(line.as_bytes()) {
+                Ok(()) => true,
+                Err(e) => {
+                    info!("Dropping socket client: {}", e);
+                    false
+                }
+            });
+    }
+}
+
+impl Drop for SocketServer {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
@@ -0,0 +1,247 @@
+//! Live dashboard for desk-side diagnostics.
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Row, Sparkline, Table},
+};
+use sps30_hdlc::{Sps30, Sps30Fault, Sps30Measurement, Unit};
+
+use crate::{EXIT_IO, SENSOR_UPDATE_INTERVAL};
+
+/// Readings kept for the sparklines, more than fit on any sane terminal.
+const HISTORY: usize = 512;
+
+struct State {
+    header: String,
+    reading: Option<Sps30Measurement>,
+    faults: Vec<Sps30Fault>,
+    pm2_5: VecDeque<u64>,
+    pm10: VecDeque<u64>,
+    message: String,
+}
+
+fn push(history: &mut VecDeque<u64>, v: f32) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+    // sparklines only take integers, keep a decimal place
+    history.push_back((v.max(0.0) * 10.0) as u64);
+}
+
+const MASS: Unit = Unit::MicrogramsPerCubicMeter;
+
+const LABELS: [&str; 10] = [
+    "Mass PM1.0",
+    "Mass PM2.5",
+    "Mass PM4.0",
+    "Mass PM10",
+    "Number PM0.5",
+    "Number PM1.0",
+    "Number PM2.5",
+    "Number PM4.0",
+    "Number PM10",
+    "Typical particle size",
+];
+
+/// Label, value and unit of each field.
+fn rows(m: &Sps30Measurement) -> impl Iterator<Item = (&'static str, f32, Unit)> {
+    LABELS
+        .into_iter()
+        .zip(m.fields())
+        .map(|(label, (_, v, unit))| (label, v, unit))
+}
+
+fn update<P: Read + Write>(sensor: &mut Sps30<P>, state: &mut State) {
+    match sensor.read_and_clear_device_status() {
+        Ok(s) => state.faults = s.faults().collect(),
+        Err(e) => state.message = format!("Can't read status: {}", e),
+    }
+    match sensor.read_measurement() {
+        Ok(Some(m)) => {
+            push(&mut state.pm2_5, m.mass_2_5);
+            push(&mut state.pm10, m.mass_10);
+            state.reading = Some(m);
+        }
+        Ok(None) => (),
+        Err(e) => state.message = format!("Can't read measurement: {}", e),
+    }
+}
+
+fn sparkline<'a>(title: &'a str, history: &VecDeque<u64>, width: u16) -> (Sparkline<'a>, Vec<u64>) {
+    let n = width.saturating_sub(2) as usize;
+    let data = history
+        .iter()
+        .skip(history.len().saturating_sub(n))
+        .copied()
+        .collect();
+    (
+        Sparkline::default().block(Block::bordered().title(title)),
+        data,
+    )
+}
+
+fn draw(f: &mut Frame, state: &State) {
+    let area = f.area();
+    if area.width < 40 || area.height < 16 {
+        f.render_widget(Paragraph::new("Terminal too small"), area);
+        return;
+    }
+
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(12),
+        Constraint::Length(3),
+    ])
+    .areas(area);
+    f.render_widget(
+        Paragraph::new(state.header.as_str()).block(Block::bordered().title("SPS30")),
+        header,
+    );
+
+    let [values, graphs] =
+        Layout::horizontal([Constraint::Length(40), Constraint::Min(0)]).areas(body);
+    let rows: Vec<Row> = match &state.reading {
+        None => vec![Row::new(["Waiting for data"])],
+        Some(m) => rows(m)
+            .map(|(name, v, unit)| {
+                Row::new([name.to_string(), format!("{:.2}", v), unit.to_string()])
+            })
+            .collect(),
+    };
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(22),
+            Constraint::Length(8),
+            Constraint::Length(6),
+        ],
+    )
+    .block(Block::bordered().title("Readings"));
+    f.render_widget(table, values);
+
+    if graphs.width > 4 {
+        let [g2_5, g10] = Layout::vertical([Constraint::Percentage(50); 2]).areas(graphs);
+        let title = format!("PM2.5 {}", MASS);
+        let (s, d) = sparkline(&title, &state.pm2_5, g2_5.width);
+        f.render_widget(s.data(&d).style(Style::new().fg(Color::Cyan)), g2_5);
+        let title = format!("PM10 {}", MASS);
+        let (s, d) = sparkline(&title, &state.pm10, g10.width);
+        f.render_widget(s.data(&d).style(Style::new().fg(Color::Magenta)), g10);
+    }
+
+    let indicator = |name: &'static str, on: bool| {
+        if on {
+            Span::from(format!(" {} ", name)).white().on_red()
+        } else {
+            Span::from(format!(" {} ", name)).dark_gray()
+        }
+    };
+    let has = |f: fn(&Sps30Fault) -> bool| state.faults.iter().any(f);
+    let status = Line::from(vec![
+        indicator("FAN", has(|f| matches!(f, Sps30Fault::FanFailure))),
+        indicator("LASER", has(|f| matches!(f, Sps30Fault::LaserFailure))),
+        indicator(
+            "FAN SPEED",
+            has(|f| matches!(f, Sps30Fault::FanSpeedOutOfRange)),
+        ),
+        Span::from("  q quit  c clean  r reset  "),
+        Span::from(state.message.as_str()).yellow(),
+    ]);
+    f.render_widget(Paragraph::new(status).block(Block::bordered()), footer);
+}
+
+fn dashboard<P: Read + Write>(
+    terminal: &mut DefaultTerminal,
+    sensor: &mut Sps30<P>,
+    state: &mut State,
+) -> io::Result<()> {
+    let mut next = Instant::now();
+    loop {
+        if Instant::now() >= next {
+            next = Instant::now() + SENSOR_UPDATE_INTERVAL;
+            update(sensor, state);
+        }
+        terminal.draw(|f| draw(f, state))?;
+
+        let wait = next.saturating_duration_since(Instant::now());
+        if !event::poll(wait.min(Duration::from_millis(250)))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            // resizes are picked up by the next draw
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('c') => {
+                state.message = match sensor.start_fan_cleaning() {
+                    Ok(()) => "Fan cleaning started".into(),
+                    Err(e) => format!("Fan cleaning failed: {}", e),
+                }
+            }
+            KeyCode::Char('r') => {
+                state.message = match sensor
+                    .device_reset()
+                    .and_then(|_| sensor.start_measurement())
+                {
+                    Ok(()) => "Sensor reset".into(),
+                    Err(e) => format!("Reset failed: {}", e),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn run<P: Read + Write>(sensor: &mut Sps30<P>) -> i32 {
+    let firmware = sensor
+        .read_version()
+        .map(|v| v.firmware.to_string())
+        .unwrap_or_else(|_| "unknown".into());
+    let mut state = State {
+        header: format!(
+            "{}  serial {}  firmware {}",
+            sensor.get_device_info().unwrap_or_default(),
+            sensor.read_serial_number().unwrap_or_default(),
+            firmware
+        ),
+        reading: None,
+        faults: Vec::new(),
+        pm2_5: VecDeque::with_capacity(HISTORY),
+        pm10: VecDeque::with_capacity(HISTORY),
+        message: String::new(),
+    };
+    if let Err(e) = sensor.start_measurement() {
+        state.message = format!("Can't start measurement: {}", e);
+    }
+
+    // log lines on stderr would scribble over the dashboard, errors are shown
+    // in the status line instead
+    log::set_max_level(log::LevelFilter::Off);
+    // restores the terminal on panic as well
+    let mut terminal = ratatui::init();
+    let r = dashboard(&mut terminal, sensor, &mut state);
+    ratatui::restore();
+
+    sensor.stop_measurement().ok();
+    match r {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Terminal error: {}", e);
+            EXIT_IO
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Long running soak test.
+//!
+//! Drives a sensor (or sps30-sim) through the full command mix for a fixed
+//! time, logging every transaction with its latency and the process RSS to
+//! CSV. Exits non-zero if a transaction panics or too many of them fail.
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    panic::{AssertUnwindSafe, catch_unwind},
+    process::exit,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use clap::{self, Parser};
+use sps30_hdlc::{DeviceError, Sps30};
+
+#[derive(clap::Parser)]
+#[command(version, about = "SPS30 soak test", long_about = None)]
+struct Args {
+    #[arg(short, long)]
+    port: String,
+    #[arg(long, short, default_value_t = 115200)]
+    baud: u32,
+    /// How long to run for, e.g. `36h`
+    #[arg(long, short, default_value = "1h", value_parser = humantime::parse_duration)]
+    duration: Duration,
+    /// Per transaction log
+    #[arg(long, default_value = "soak.csv")]
+    csv: String,
+    /// Highest acceptable failure rate, in percent
+    #[arg(long, default_value_t = 1.0)]
+    max_error_rate: f64,
+    /// Stop and restart measurement this often
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    restart_every: Duration,
+    /// Reset the device this often
+    #[arg(long, default_value = "1h", value_parser = humantime::parse_duration)]
+    reset_every: Duration,
+}
+
+#[derive(Default)]
+struct Stats {
+    ok: u64,
+    failed: u64,
+    panicked: u64,
+    total: Duration,
+    max: Duration,
+}
+
+struct Soak {
+    log: BufWriter<File>,
+    start: Instant,
+    stats: BTreeMap<&'static str, Stats>,
+}
+
+/// Resident set size in kB, from /proc.
+fn rss_kb() -> u64 {
+    let statm = std::fs::read_to_string("/proc/self/statm").unwrap_or_default();
+    let pages: u64 = statm
+        .split_whitespace()
+        .nth(1)
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(0);
+    pages * 4
+}
+
+impl Soak {
+    fn transaction<T>(&mut self, name: &'static str, f: impl FnOnce() -> Result<T, DeviceError>) {
+        let t = Instant::now();
+        let r = catch_unwind(AssertUnwindSafe(f));
+        let latency = t.elapsed();
+
+        let stats = self.stats.entry(name).or_default();
+        let result = match r {
+            Ok(Ok(_)) => {
+                stats.ok += 1;
+                "ok"
+            }
+            Ok(Err(_)) => {
+                stats.failed += 1;
+                "failed"
+            }
+            Err(_) => {
+                stats.panicked += 1;
+                "panicked"
+            }
+        };
+        stats.total += latency;
+        stats.max = stats.max.max(latency);
+
+        writeln!(
+            self.log,
+            "{},{},{},{},{}",
+            self.start.elapsed().as_millis(),
+            name,
+            result,
+            latency.as_micros(),
+            rss_kb()
+        )
+        .unwrap();
+        self.log.flush().unwrap();
+    }
+
+    fn report(&self) -> (u64, u64, u64) {
+        println!(
+            "{:<12} {:>8} {:>8} {:>8} {:>10} {:>10}",
+            "command", "ok", "failed", "panicked", "mean_us", "max_us"
+        );
+        let (mut ok, mut failed, mut panicked) = (0, 0, 0);
+        for (name, s) in &self.stats {
+            let n = s.ok + s.failed + s.panicked;
+            println!(
+                "{:<12} {:>8} {:>8} {:>8} {:>10} {:>10}",
+                name,
+                s.ok,
+                s.failed,
+                s.panicked,
+                (s.total / n.max(1) as u32).as_micros(),
+                s.max.as_micros()
+            );
+            ok += s.ok;
+            failed += s.failed;
+            panicked += s.panicked;
+        }
+        println!("RSS: {} kB", rss_kb());
+        (ok, failed, panicked)
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut p = serialport::new(&args.port, args.baud)
+        .open()
+        .expect("Serial port can't be opened");
+    p.set_timeout(Duration::from_millis(20)).unwrap();
+    let mut sensor = Sps30::new(p);
+
+    let mut log = BufWriter::new(File::create(&args.csv).expect("Can't create CSV file"));
+    writeln!(log, "elapsed_ms,command,result,latency_us,rss_kb").unwrap();
+    let mut soak = Soak {
+        log,
+        start: Instant::now(),
+        stats: BTreeMap::new(),
+    };
+
+    soak.transaction("reset", || sensor.device_reset());
+    soak.transaction("start", || sensor.start_measurement());
+
+    let mut last_status = Instant::now();
+    let mut last_restart = Instant::now();
+    let mut last_reset = Instant::now();
+    while soak.start.elapsed() < args.duration {
+        sleep(Duration::from_secs(1));
+        soak.transaction("measure", || sensor.read_measurement());
+
+        if last_status.elapsed() >= Duration::from_secs(60) {
+            last_status = Instant::now();
+            soak.transaction("status", || sensor.read_and_clear_device_status());
+        }
+        if last_reset.elapsed() >= args.reset_every {
+            last_reset = Instant::now();
+            last_restart = Instant::now();
+            soak.transaction("reset", || sensor.device_reset());
+            soak.transaction("start", || sensor.start_measurement());
+        } else if last_restart.elapsed() >= args.restart_every {
+            last_restart = Instant::now();
+            soak.transaction("stop", || sensor.stop_measurement());
+            soak.transaction("start", || sensor.start_measurement());
+        }
+    }
+    soak.transaction("stop", || sensor.stop_measurement());
+
+    let (ok, failed, panicked) = soak.report();
+    let rate = 100.0 * failed as f64 / (ok + failed + panicked).max(1) as f64;
+    println!("Error rate: {:.3}%", rate);
+    if panicked > 0 {
+        eprintln!("{} transactions panicked", panicked);
+        exit(2);
+    }
+    if rate > args.max_error_rate {
+        eprintln!("Error rate above {}%", args.max_error_rate);
+        exit(1);
+    }
+}
@@ -0,0 +1,293 @@
+//! SPS30 simulator.
+//!
+//! Emulates the sensor side of the SHDLC link on a pseudo terminal (default) or
+//! a TCP socket, so the library and linux_test can be exercised without
+//! hardware. Frames are parsed and built with the crate's own codec.
+use std::{
+    f32::consts::PI,
+    fs::File,
+    io::{Read, Write},
+    net::TcpListener,
+    time::Instant,
+};
+
+use clap::{self, Parser};
+use nix::{
+    pty::openpty,
+    sys::termios::{SetArg, cfmakeraw, tcgetattr, tcsetattr},
+    unistd::ttyname,
+};
+use sps30_hdlc::{Command, Frame, shdlc::FEND};
+
+#[derive(clap::Parser)]
+#[command(version, about = "SPS30 SHDLC device simulator", long_about = None)]
+struct Args {
+    /// Listen on this TCP address instead of creating a pseudo terminal
+    #[arg(long)]
+    tcp: Option<String>,
+    /// Report a fan error in the device status register
+    #[arg(long)]
+    fan_error: bool,
+    /// Corrupt the checksum of every Nth response
+    #[arg(long)]
+    corrupt_every: Option<usize>,
+    /// Stop responding after N responses
+    #[arg(long)]
+    silent_after: Option<usize>,
+}
+
+// SHDLC state byte values
+const STATE_OK: u8 = 0x00;
+const STATE_WRONG_LENGTH: u8 = 0x01;
+const STATE_NOT_ALLOWED: u8 = 0x43;
+
+const PRODUCT_TYPE: &str = "00080000";
+const SERIAL: &str = "SIM0000000000001";
+
+struct Device {
+    measuring: bool,
+    sleeping: bool,
+    auto_clean_interval: u32,
+    fan_error: bool,
+    corrupt_every: Option<usize>,
+    silent_after: Option<usize>,
+    responses: usize,
+    started: Instant,
+}
+
+impl Device {
+    fn new(args: &Args) -> Self {
+        Self {
+            measuring: false,
+            sleeping: false,
+            auto_clean_interval: 604800,
+            fan_error: args.fan_error,
+            corrupt_every: args.corrupt_every,
+            silent_after: args.silent_after,
+            responses: 0,
+            started: Instant::now(),
+        }
+    }
+
+    fn measurement(&self) -> Vec<u8> {
+        // slowly varying synthetic values, derived from pm1.0
+        let t = self.started.elapsed().as_secs_f32();
+        let base = 10.0 + 5.0 * (2.0 * PI * t / 60.0).sin();
+        let values = [
+            base,
+            base * 1.1,
+            base * 1.15,
+            base * 1.2,
+            base * 6.0,
+            base * 7.0,
+            base * 7.1,
+            base * 7.15,
+            base * 7.2,
+            0.55,
+        ];
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn status_register(&self) -> Vec<u8> {
+        let mut reg = 0_u32;
+        if self.fan_error {
+            reg |= 1 << 4;
+        }
+        let mut data = reg.to_be_bytes().to_vec();
+        data.push(0);
+        data
+    }
+
+    /// Handle one request, returning the state byte and response payload.
+    /// Returns None when the device would not answer at all.
+    fn handle(&mut self, f: &Frame) -> Option<(u8, Vec<u8>)> {
+        if self.sleeping && !matches!(f.cmd, Command::WakeUp) {
+            return None;
+        }
+        let r = match f.cmd {
+            Command::StartMeasurement => {
+                if self.measuring {
+                    (STATE_NOT_ALLOWED, Vec::new())
+                } else if f.data.len() != 2 {
+                    (STATE_WRONG_LENGTH, Vec::new())
+                } else {
+                    self.measuring = true;
+                    (STATE_OK, Vec::new())
+                }
+            }
+            Command::StopMeasurement => {
+                if !self.measuring {
+                    (STATE_NOT_ALLOWED, Vec::new())
+                } else {
+                    self.measuring = false;
+                    (STATE_OK, Vec::new())
+                }
+            }
+            Command::ReadMeasuredValue => {
+                if !self.measuring {
+                    (STATE_NOT_ALLOWED, Vec::new())
+                } else {
+                    (STATE_OK, self.measurement())
+                }
+            }
+            Command::Sleep => {
+                if self.measuring {
+                    (STATE_NOT_ALLOWED, Vec::new())
+                } else {
+                    self.sleeping = true;
+                    (STATE_OK, Vec::new())
+                }
+            }
+            Command::WakeUp => {
+                self.sleeping = false;
+                (STATE_OK, Vec::new())
+            }
+            Command::StartFanCleaning => {
+                if !self.measuring {
+                    (STATE_NOT_ALLOWED, Vec::new())
+                } else {
+                    (STATE_OK, Vec::new())
+                }
+            }
+            Command::RWAutoCleaningInterval => match f.data.len() {
+                1 => (STATE_OK, self.auto_clean_interval.to_be_bytes().to_vec()),
+                5 => {
+                    self.auto_clean_interval = u32::from_be_bytes(f.data[1..5].try_into().unwrap());
+                    (STATE_OK, Vec::new())
+                }
+                _ => (STATE_WRONG_LENGTH, Vec::new()),
+            },
+            Command::DeviceInformation => {
+                let s = match f.data.first() {
+                    Some(0x00) => PRODUCT_TYPE,
+                    Some(0x03) => SERIAL,
+                    _ => return Some((STATE_WRONG_LENGTH, Vec::new())),
+                };
+                let mut data = s.as_bytes().to_vec();
+                data.push(0);
+                (STATE_OK, data)
+            }
+            Command::ReadVersion => (STATE_OK, vec![2, 2, 0, 7, 0, 2, 0]),
+            Command::ReadDeviceStatusRegister => {
+                let data = self.status_register();
+                if f.data.first() == Some(&0x01) {
+                    self.fan_error = false;
+                }
+                (STATE_OK, data)
+            }
+            Command::Reset => {
+                self.measuring = false;
+                self.sleeping = false;
+                (STATE_OK, Vec::new())
+            }
+        };
+        Some(r)
+    }
+
+    fn respond(&mut self, f: &Frame) -> Option<Vec<u8>> {
+        if let Some(n) = self.silent_after
+            && self.responses >= n
+        {
+            println!("Silent, ignoring {:?}", f.cmd);
+            return None;
+        }
+        let (state, data) = self.handle(f)?;
+        self.responses += 1;
+
+        let response = Frame {
+            addr: f.addr,
+            cmd: f.cmd,
+            data,
+        };
+        let mut packet = response.encode_response(state).unwrap();
+        if let Some(n) = self.corrupt_every
+            && n > 0
+            && self.responses.is_multiple_of(n)
+        {
+            println!("Corrupting response to {:?}", f.cmd);
+            let l = packet.len();
+            // the checksum sits right before the end delimiter
+            packet[l - 2] ^= 0x01;
+        }
+        println!("{:?} -> state {:#04x}", f.cmd, state);
+        Some(packet)
+    }
+}
+
+fn serve<S: Read + Write>(mut s: S, dev: &mut Device) {
+    let mut buf = [0u8; 256];
+    let mut frame: Vec<u8> = Vec::new();
+    loop {
+        let n = match s.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => n,
+            Err(e) => {
+                eprintln!("Read error: {}", e);
+                return;
+            }
+        };
+        for &b in &buf[..n] {
+            if b != FEND {
+                if !frame.is_empty() {
+                    frame.push(b);
+                }
+                continue;
+            }
+            if frame.len() <= 1 {
+                frame.clear();
+                frame.push(b);
+                continue;
+            }
+            frame.push(b);
+            match Frame::decode_request(&frame) {
+                Ok(f) => {
+                    if let Some(packet) = dev.respond(&f)
+                        && let Err(e) = s.write_all(&packet)
+                    {
+                        eprintln!("Write error: {}", e);
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("Bad request {:02x?}: {}", frame, e),
+            }
+            frame.clear();
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut dev = Device::new(&args);
+
+    if let Some(addr) = &args.tcp {
+        let listener = TcpListener::bind(addr).expect("Can't bind TCP address");
+        println!("Listening on {}", addr);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(s) => {
+                    println!("Connection from {:?}", s.peer_addr());
+                    serve(s, &mut dev);
+                }
+                Err(e) => eprintln!("Connection failed: {}", e),
+            }
+        }
+        return;
+    }
+
+    let pty = openpty(None, None).expect("Can't open pseudo terminal");
+    let mut t = tcgetattr(&pty.slave).unwrap();
+    cfmakeraw(&mut t);
+    tcsetattr(&pty.slave, SetArg::TCSANOW, &t).unwrap();
+    println!(
+        "Simulated SPS30 on {}",
+        ttyname(&pty.slave).unwrap().display()
+    );
+
+    // keep the slave side open so reads on the master don't fail when the
+    // client disconnects
+    let _slave = pty.slave;
+    let master = File::from(pty.master);
+    loop {
+        serve(master.try_clone().unwrap(), &mut dev);
+    }
+}
@@ -0,0 +1,133 @@
+//! Throughput and latency stress test.
+//!
+//! Issues back to back ReadMeasuredValue / ReadVersion commands at each of
+//! the given pacing gaps and reports the round trip latency distribution and
+//! protocol error count per pacing, as CSV or JSON.
+use std::{thread::sleep, time::Duration};
+
+use clap::{self, Parser, ValueEnum};
+use sps30_hdlc::Sps30;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(clap::Parser)]
+#[command(version, about = "SPS30 throughput and latency stress test", long_about = None)]
+struct Args {
+    #[arg(short, long)]
+    port: String,
+    #[arg(long, short, default_value_t = 115200)]
+    baud: u32,
+    /// Gaps to leave between commands, e.g. `0ms,5ms,20ms`
+    #[arg(long, value_delimiter = ',', default_value = "0ms,5ms,10ms,20ms", value_parser = humantime::parse_duration)]
+    pacing: Vec<Duration>,
+    /// Commands to issue per pacing setting
+    #[arg(long, short, default_value_t = 1000)]
+    count: usize,
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+}
+
+struct PacingResult {
+    pacing: Duration,
+    ok: usize,
+    errors: usize,
+    p50: Duration,
+    p95: Duration,
+    max: Duration,
+    elapsed: Duration,
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let i = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[i]
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut p = serialport::new(&args.port, args.baud)
+        .open()
+        .expect("Serial port can't be opened");
+    p.set_timeout(Duration::from_millis(20)).unwrap();
+    let mut sensor = Sps30::new(p);
+
+    sensor.device_reset().expect("Reset failed");
+    sensor
+        .start_measurement()
+        .expect("Start measurement failed");
+
+    let mut results = Vec::new();
+    for &pacing in &args.pacing {
+        let mut latencies = Vec::with_capacity(args.count);
+        let mut errors = 0;
+        let start = std::time::Instant::now();
+        for i in 0..args.count {
+            let ok = if i % 2 == 0 {
+                sensor.read_measurement().is_ok()
+            } else {
+                sensor.read_version().is_ok()
+            };
+            match (ok, sensor.last_latency()) {
+                (true, Some(l)) => latencies.push(l),
+                _ => errors += 1,
+            }
+            sleep(pacing);
+        }
+        let elapsed = start.elapsed();
+        latencies.sort();
+        results.push(PacingResult {
+            pacing,
+            ok: latencies.len(),
+            errors,
+            p50: percentile(&latencies, 0.5),
+            p95: percentile(&latencies, 0.95),
+            max: latencies.last().copied().unwrap_or_default(),
+            elapsed,
+        });
+    }
+
+    sensor.stop_measurement().ok();
+
+    match args.format {
+        Format::Csv => {
+            println!("pacing_us,ok,errors,p50_us,p95_us,max_us,commands_per_s");
+            for r in &results {
+                println!(
+                    "{},{},{},{},{},{},{:.1}",
+                    r.pacing.as_micros(),
+                    r.ok,
+                    r.errors,
+                    r.p50.as_micros(),
+                    r.p95.as_micros(),
+                    r.max.as_micros(),
+                    (r.ok + r.errors) as f64 / r.elapsed.as_secs_f64()
+                );
+            }
+        }
+        Format::Json => {
+            let rows: Vec<String> = results
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{{\"pacing_us\":{},\"ok\":{},\"errors\":{},\"p50_us\":{},\"p95_us\":{},\"max_us\":{},\"commands_per_s\":{:.1}}}",
+                        r.pacing.as_micros(),
+                        r.ok,
+                        r.errors,
+                        r.p50.as_micros(),
+                        r.p95.as_micros(),
+                        r.max.as_micros(),
+                        (r.ok + r.errors) as f64 / r.elapsed.as_secs_f64()
+                    )
+                })
+                .collect();
+            println!("[{}]", rows.join(","));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Session capture and replay.
+//!
+//! [`RecordingPort`] wraps a port and logs every chunk of traffic to a writer,
+//! one line per chunk:
+//!
+//! ```text
+//! <microseconds since start> <TX|RX> <hex bytes>
+//! 0000001234 TX 7e 00 03 00 fc 7e
+//! ```
+//!
+//! [`ReplayPort`] reads such a capture back and plays the device side of it,
+//! so a session reported by a user can be reproduced without their sensor.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::string::String;
+use std::time::Instant;
+use std::vec::Vec;
+
+pub use crate::Direction;
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub micros: u64,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Parse a capture, ignoring blank lines and `#` comments.
+pub fn parse_capture(s: &str) -> io::Result<Vec<Record>> {
+    let invalid = |line: usize| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            std::format!("bad capture line {}", line + 1),
+        )
+    };
+    let mut records = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let micros = parts
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| invalid(i))?;
+        let direction = match parts.next() {
+            Some("TX") => Direction::Tx,
+            Some("RX") => Direction::Rx,
+            _ => return Err(invalid(i)),
+        };
+        let data = parts
+            .map(|b| u8::from_str_radix(b, 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid(i))?;
+        records.push(Record {
+            micros,
+            direction,
+            data,
+        });
+    }
+    Ok(records)
+}
+
+pub struct RecordingPort<P, W: Write> {
+    inner: P,
+    log: W,
+    start: Instant,
+}
+
+impl<P, W: Write> RecordingPort<P, W> {
+    pub fn new(inner: P, log: W) -> Self {
+        Self {
+            inner,
+            log,
+            start: Instant::now(),
+        }
+    }
+    pub fn into_inner(self) -> (P, W) {
+        (self.inner, self.log)
+    }
+
+    fn record(&mut self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut line = String::new();
+        write!(
+            line,
+            "{:010} {}",
+            self.start.elapsed().as_micros(),
+            direction.as_str()
+        )
+        .unwrap();
+        for b in data {
+            write!(line, " {:02x}", b).unwrap();
+        }
+        line.push('\n');
+        self.log.write_all(line.as_bytes())
+    }
+}
+
+impl<P: Read, W: Write> Read for RecordingPort<P, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.record(Direction::Rx, &buf[..n])?;
+        Ok(n)
+    }
+}
+
+impl<P: Write, W: Write> Write for RecordingPort<P, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.record(Direction::Tx, &buf[..n])?;
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.log.flush()
+    }
+}
+
+/// Plays back the device side of a capture. Writes must match the captured
+/// TX bytes; once they do, the RX bytes that followed become readable.
+pub struct ReplayPort {
+    records: VecDeque<Record>,
+    expected: Vec<u8>,
+    written: Vec<u8>,
+    pending: VecDeque<u8>,
+}
+
+impl ReplayPort {
+    pub fn new(records: Vec<Record>) -> Self {
+        let mut p = Self {
+            records: records.into(),
+            expected: Vec::new(),
+            written: Vec::new(),
+            pending: VecDeque::new(),
+        };
+        p.advance();
+        p
+    }
+    pub fn from_capture(s: &str) -> io::Result<Self> {
+        Ok(Self::new(parse_capture(s)?))
+    }
+    /// True once the whole capture has been played back.
+    pub fn is_done(&self) -> bool {
+        self.records.is_empty() && self.expected.is_empty() && self.pending.is_empty()
+    }
+
+    /// Queue RX records up to the next TX, and collect that TX as expected.
+    fn advance(&mut self) {
+        while let Some(r) = self.records.front() {
+            match r.direction {
+                Direction::Rx if self.expected.is_empty() => {
+                    let r = self.records.pop_front().unwrap();
+                    self.pending.extend(r.data);
+                }
+                Direction::Tx => {
+                    let r = self.records.pop_front().unwrap();
+                    self.expected.extend(r.data);
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+impl Read for ReplayPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.pending.pop_front() {
+                Some(b) => {
+                    buf[n] = b;
+                    n += 1;
+                }
+                None => break,
+            }
+        }
+        if n == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        Ok(n)
+    }
+}
+
+impl Write for ReplayPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.expected.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "end of capture reached",
+            ));
+        }
+        self.written.extend_from_slice(buf);
+        while !self.expected.is_empty() && self.written.len() >= self.expected.len() {
+            let got: Vec<u8> = self.written.drain(..self.expected.len()).collect();
+            if got != self.expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    std::format!(
+                        "replay mismatch: wrote {:02x?}, capture has {:02x?}",
+                        got,
+                        self.expected
+                    ),
+                ));
+            }
+            self.expected.clear();
+            self.advance();
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sps30;
+    use crate::shdlc::{encode_request, encode_response};
+
+    const READ_VERSION: u8 = 0xd1;
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| std::format!(" {:02x}", b)).collect()
+    }
+
+    /// A capture of one Read Version exchange.
+    fn version_capture() -> String {
+        let tx = encode_request(0, READ_VERSION, &[]).unwrap();
+        let rx = encode_response(0, READ_VERSION, 0, &[2, 2, 0, 7, 0, 2, 0]).unwrap();
+        std::format!(
+            "# one exchange\n\n0000001200 TX{}\n0000003400 RX{}\n",
+            hex(&tx),
+            hex(&rx)
+        )
+    }
+
+    #[test]
+    fn parses_records_skipping_comments() {
+        let records = parse_capture(&version_capture()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].micros, 1200);
+        assert_eq!(records[0].direction, Direction::Tx);
+        assert_eq!(records[0].data, [0x7e, 0x00, 0xd1, 0x00, 0x2e, 0x7e]);
+        assert_eq!(records[1].direction, Direction::Rx);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(parse_capture("12 XX 7e").is_err());
+        assert!(parse_capture("12 TX 7g").is_err());
+        assert!(parse_capture("TX 7e").is_err());
+    }
+
+    #[test]
+    fn replays_the_device_side() {
+        let mut sensor = Sps30::new(ReplayPort::from_capture(&version_capture()).unwrap());
+        assert_eq!(sensor.read_version().unwrap().hardware, 7);
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn a_recorded_session_replays() {
+        let replay = ReplayPort::from_capture(&version_capture()).unwrap();
+        let mut log = Vec::new();
+        let mut sensor = Sps30::new(RecordingPort::new(replay, &mut log));
+        let version = sensor.read_version().unwrap();
+        assert!(sensor.link.port_mut().inner.is_done());
+        drop(sensor);
+
+        let recorded = String::from_utf8(log).unwrap();
+        let mut sensor = Sps30::new(ReplayPort::from_capture(&recorded).unwrap());
+        assert_eq!(sensor.read_version().unwrap(), version);
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn writes_that_differ_from_the_capture_fail() {
+        let mut port = ReplayPort::from_capture(&version_capture()).unwrap();
+        assert!(port.write(&[0x7e, 0x00, 0xd0, 0x00, 0x2f, 0x7e]).is_err());
+    }
+}
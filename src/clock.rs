@@ -0,0 +1,73 @@
+//! Time sources for stamping readings.
+//!
+//! On std, [`SystemClock`] gives wall clock time and [`MonotonicClock`] time
+//! that never goes backwards. On no_std any function or closure returning a
+//! timestamp is a clock, so an RTC read or a tick counter can be used
+//! directly.
+
+use core::time::Duration;
+
+pub trait Clock {
+    type Timestamp;
+    fn now(&self) -> Self::Timestamp;
+}
+
+impl<T, F: Fn() -> T> Clock for F {
+    type Timestamp = T;
+    fn now(&self) -> T {
+        self()
+    }
+}
+
+/// Timestamps the time between can be measured, for rates of change. None
+/// if `earlier` is actually later, e.g. after the wall clock was set back.
+pub trait Elapsed {
+    fn since(&self, earlier: &Self) -> Option<Duration>;
+}
+
+/// Time since some fixed point, e.g. boot, as a tick counter gives on no_std.
+impl Elapsed for Duration {
+    fn since(&self, earlier: &Self) -> Option<Duration> {
+        self.checked_sub(*earlier)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Elapsed for std::time::SystemTime {
+    fn since(&self, earlier: &Self) -> Option<Duration> {
+        self.duration_since(*earlier).ok()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Elapsed for std::time::Instant {
+    fn since(&self, earlier: &Self) -> Option<Duration> {
+        self.checked_duration_since(*earlier)
+    }
+}
+
+/// Wall clock time.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    type Timestamp = std::time::SystemTime;
+    fn now(&self) -> Self::Timestamp {
+        std::time::SystemTime::now()
+    }
+}
+
+/// Monotonic time, for measuring intervals between readings.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MonotonicClock;
+
+#[cfg(feature = "std")]
+impl Clock for MonotonicClock {
+    type Timestamp = std::time::Instant;
+    fn now(&self) -> Self::Timestamp {
+        std::time::Instant::now()
+    }
+}
@@ -0,0 +1,401 @@
+//! Driver configuration in one place.
+//!
+//! [`Sps30Config`] holds the plain settings, so it can be filled in from a
+//! config file and applied to a driver with [`Sps30::apply_config`].
+//! [`Sps30Builder`] adds the hooks and builds a configured driver:
+//!
+//! ```ignore
+//! let sensor = Sps30Builder::new()
+//!     .retry_policy(RetryPolicy {
+//!         retries: 2,
+//!         delay: Duration::from_millis(50),
+//!     })
+//!     .clamp_negative(true)
+//!     .build(port);
+//! ```
+
+use core::time::Duration;
+
+use crate::{
+    DEFAULT_RESPONSE_TIMEOUT, DelayFn, EventFn, FaultFn, HealthCheck, ProtocolCheck, RetryPolicy,
+    Sps30, TraceFn, Transport,
+};
+
+/// Settings for [`Sps30`]. The defaults are those of [`Sps30::new`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sps30Config {
+    /// See [`Sps30::set_address`].
+    pub address: u8,
+    /// See [`Sps30::set_response_timeout`].
+    pub response_timeout: Option<Duration>,
+    /// See [`Sps30::set_retry_policy`].
+    pub retry: RetryPolicy,
+    /// See [`Sps30::set_clamp_negative`].
+    pub clamp_negative: bool,
+    /// See [`Sps30::set_auto_status`].
+    pub auto_status: bool,
+    /// See [`Sps30::set_health_check`].
+    pub health_check: Option<HealthCheck>,
+    /// See [`Sps30::set_protocol_check`].
+    pub protocol_check: ProtocolCheck,
+    /// See [`Sps30::set_skip_firmware_checks`].
+    pub skip_firmware_checks: bool,
+}
+
+impl Default for Sps30Config {
+    fn default() -> Self {
+        Self {
+            address: 0,
+            response_timeout: Some(DEFAULT_RESPONSE_TIMEOUT),
+            retry: RetryPolicy::default(),
+            clamp_negative: false,
+            auto_status: false,
+            health_check: None,
+            protocol_check: ProtocolCheck::default(),
+            skip_firmware_checks: false,
+        }
+    }
+}
+
+impl<P: Transport> Sps30<P> {
+    /// Apply every setting in `config`.
+    pub fn apply_config(&mut self, config: &Sps30Config) {
+        self.set_address(config.address);
+        self.set_response_timeout(config.response_timeout);
+        self.set_retry_policy(config.retry);
+        self.set_clamp_negative(config.clamp_negative);
+        self.set_auto_status(config.auto_status);
+        self.set_health_check(config.health_check);
+        self.set_protocol_check(config.protocol_check);
+        self.set_skip_firmware_checks(config.skip_firmware_checks);
+    }
+}
+
+/// Builds an [`Sps30`] with chained setters, see the module documentation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sps30Builder {
+    config: Sps30Config,
+    trace: Option<TraceFn>,
+    delay: Option<DelayFn>,
+    on_fault: Option<FaultFn>,
+    on_event: Option<EventFn>,
+}
+
+impl Sps30Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from `config`, replacing any settings made so far.
+    pub fn config(mut self, config: Sps30Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn address(mut self, addr: u8) -> Self {
+        self.config.address = addr;
+        self
+    }
+
+    pub fn response_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.response_timeout = timeout;
+        self
+    }
+
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry = policy;
+        self
+    }
+
+    pub fn clamp_negative(mut self, clamp: bool) -> Self {
+        self.config.clamp_negative = clamp;
+        self
+    }
+
+    pub fn auto_status(mut self, enabled: bool) -> Self {
+        self.config.auto_status = enabled;
+        self
+    }
+
+    pub fn health_check(mut self, check: Option<HealthCheck>) -> Self {
+        self.config.health_check = check;
+        self
+    }
+
+    pub fn protocol_check(mut self, check: ProtocolCheck) -> Self {
+        self.config.protocol_check = check;
+        self
+    }
+
+    pub fn skip_firmware_checks(mut self, skip: bool) -> Self {
+        self.config.skip_firmware_checks = skip;
+        self
+    }
+
+    pub fn trace(mut self, trace: TraceFn) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub fn delay(mut self, delay: DelayFn) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    pub fn on_fault(mut self, on_fault: FaultFn) -> Self {
+        self.on_fault = Some(on_fault);
+        self
+    }
+
+    pub fn on_event(mut self, on_event: EventFn) -> Self {
+        self.on_event = Some(on_event);
+        self
+    }
+
+    pub fn build<P: Transport>(self, port: P) -> Sps30<P> {
+        let mut sensor = Sps30::new(port);
+        sensor.apply_config(&self.config);
+        sensor.set_trace(self.trace);
+        sensor.set_delay(self.delay);
+        sensor.set_on_fault(self.on_fault);
+        sensor.set_on_event(self.on_event);
+        sensor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Exchange, MockPort};
+    use crate::shdlc::{encode_request, encode_response};
+    use crate::{Direction, Event, Sps30Measurement};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Instant;
+
+    const START_MEASUREMENT: u8 = 0x00;
+    const READ_MEASURED_VALUE: u8 = 0x03;
+    const SLEEP: u8 = 0x10;
+    const READ_VERSION: u8 = 0xd1;
+    const READ_DEVICE_STATUS: u8 = 0xd2;
+    const RESET: u8 = 0xd3;
+    const VERSION: [u8; 7] = [2, 2, 0, 7, 0, 2, 0];
+
+    fn build(builder: Sps30Builder, script: Vec<Exchange>) -> Sps30<MockPort> {
+        builder.build(MockPort::new(script))
+    }
+
+    fn reading(m: &Sps30Measurement) -> Exchange {
+        Exchange::command(READ_MEASURED_VALUE, &[], 0, &m.to_bytes())
+    }
+
+    #[test]
+    fn an_empty_builder_matches_new() {
+        let built = build(Sps30Builder::new(), vec![]);
+        let new = Sps30::new(MockPort::new(vec![]));
+        assert_eq!(built.addr, new.addr);
+        assert_eq!(built.response_timeout, new.response_timeout);
+        assert_eq!(built.clamp_negative, new.clamp_negative);
+        assert_eq!(built.auto_status, new.auto_status);
+        assert_eq!(built.health_check, new.health_check);
+        assert_eq!(built.protocol_check, new.protocol_check);
+        assert_eq!(built.skip_firmware_checks, new.skip_firmware_checks);
+        assert_eq!(built.response_timeout, Some(DEFAULT_RESPONSE_TIMEOUT));
+    }
+
+    #[test]
+    fn address_is_used_in_frames() {
+        let mut sensor = build(
+            Sps30Builder::new().address(2),
+            vec![Exchange::new(
+                &encode_request(2, READ_VERSION, &[]).unwrap(),
+                &encode_response(2, READ_VERSION, 0, &VERSION).unwrap(),
+            )],
+        );
+        assert_eq!(sensor.address(), 2);
+        assert_eq!(sensor.read_version().unwrap().hardware, 7);
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn response_timeout_bounds_the_wait() {
+        let request = Exchange::command(READ_VERSION, &[], 0, &[]).request;
+        let mut sensor = build(
+            Sps30Builder::new().response_timeout(Some(Duration::from_millis(20))),
+            vec![Exchange::new(&request, &[])],
+        );
+        let start = Instant::now();
+        assert!(sensor.read_version().is_err());
+        assert!(sensor.timed_out());
+        assert!(start.elapsed() < DEFAULT_RESPONSE_TIMEOUT);
+    }
+
+    #[test]
+    fn retry_policy_resends() {
+        let mut flipped = Exchange::command(READ_VERSION, &[], 0, &VERSION);
+        flipped.response[6] ^= 0x01;
+        let mut sensor = build(
+            Sps30Builder::new().retry_policy(RetryPolicy {
+                retries: 1,
+                ..Default::default()
+            }),
+            vec![flipped, Exchange::command(READ_VERSION, &[], 0, &VERSION)],
+        );
+        sensor.read_version().unwrap();
+        assert_eq!(sensor.last_attempts(), 2);
+    }
+
+    #[test]
+    fn clamp_negative_zeroes_negative_readings() {
+        let m = Sps30Measurement::from_array([-0.5, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.5]);
+        let mut sensor = build(Sps30Builder::new().clamp_negative(true), vec![reading(&m)]);
+        let m = sensor.read_measurement().unwrap().unwrap();
+        assert_eq!(m.mass_1_0, 0.0);
+        assert_eq!(m.mass_2_5, 1.0);
+    }
+
+    #[test]
+    fn auto_status_peeks_on_every_flagged_response() {
+        let m = Sps30Measurement::zeros();
+        let flagged = Exchange::command(READ_MEASURED_VALUE, &[], 0x80, &m.to_bytes());
+        let status = Exchange::command(READ_DEVICE_STATUS, &[0x00], 0x80, &[0, 0, 0, 0x10, 0]);
+        let mut sensor = build(
+            Sps30Builder::new().auto_status(true),
+            vec![flagged.clone(), status.clone(), flagged, status],
+        );
+        sensor.read_measurement().unwrap();
+        sensor.read_measurement().unwrap();
+        assert!(sensor.pending_status().unwrap().has_errors());
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn health_check_peeks_after_readings() {
+        let m = Sps30Measurement::zeros();
+        let mut sensor = build(
+            Sps30Builder::new().health_check(Some(HealthCheck::Readings(1))),
+            vec![
+                reading(&m),
+                Exchange::command(READ_DEVICE_STATUS, &[0x00], 0, &[0, 0x20, 0, 0, 0]),
+            ],
+        );
+        sensor.read_measurement().unwrap();
+        assert!(sensor.last_status().unwrap().has_warnings());
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn protocol_check_can_refuse_unknown_versions() {
+        let shdlc_1 = [2, 2, 0, 7, 0, 1, 0];
+        let script = || vec![Exchange::command(READ_VERSION, &[], 0, &shdlc_1)];
+        let mut strict = build(
+            Sps30Builder::new().protocol_check(ProtocolCheck::Strict),
+            script(),
+        );
+        assert!(strict.read_version().is_err());
+        let mut lenient = build(Sps30Builder::new(), script());
+        assert!(lenient.read_version().is_ok());
+    }
+
+    #[test]
+    fn skip_firmware_checks_sends_anyway() {
+        let mut sensor = build(
+            Sps30Builder::new().skip_firmware_checks(true),
+            vec![
+                Exchange::command(READ_VERSION, &[], 0, &[1, 0, 0, 7, 0, 2, 0]),
+                Exchange::command(SLEEP, &[], 0, &[]),
+            ],
+        );
+        sensor.read_version().unwrap();
+        sensor.sleep().unwrap();
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn trace_sees_both_directions() {
+        static SENT: AtomicU32 = AtomicU32::new(0);
+        static RECEIVED: AtomicU32 = AtomicU32::new(0);
+        fn trace(direction: Direction, frame: &[u8]) {
+            let count = match direction {
+                Direction::Tx => &SENT,
+                Direction::Rx => &RECEIVED,
+            };
+            count.fetch_add(frame.len() as u32, Ordering::Relaxed);
+        }
+
+        let e = Exchange::command(READ_VERSION, &[], 0, &VERSION);
+        let (tx, rx) = (e.request.len() as u32, e.response.len() as u32);
+        let mut sensor = build(Sps30Builder::new().trace(trace), vec![e]);
+        sensor.read_version().unwrap();
+        assert_eq!(SENT.load(Ordering::Relaxed), tx);
+        assert_eq!(RECEIVED.load(Ordering::Relaxed), rx);
+    }
+
+    #[test]
+    fn delay_is_used_for_waits() {
+        static WAITED_MS: AtomicU32 = AtomicU32::new(0);
+        fn delay(ms: u32) {
+            WAITED_MS.fetch_add(ms, Ordering::Relaxed);
+        }
+
+        let mut sensor = build(
+            Sps30Builder::new().delay(delay),
+            vec![Exchange::command(RESET, &[], 0, &[])],
+        );
+        sensor.device_reset().unwrap();
+        assert_eq!(WAITED_MS.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn on_fault_hears_about_faults() {
+        static FAULTS: AtomicU32 = AtomicU32::new(0);
+        fn on_fault(_: &crate::DeviceStatus) {
+            FAULTS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut sensor = build(
+            Sps30Builder::new().on_fault(on_fault),
+            vec![Exchange::command(
+                READ_DEVICE_STATUS,
+                &[0x00],
+                0,
+                &[0, 0, 0, 0x20, 0],
+            )],
+        );
+        sensor.peek_device_status().unwrap();
+        assert_eq!(FAULTS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn on_event_hears_about_events() {
+        static STARTED: AtomicU32 = AtomicU32::new(0);
+        fn on_event(event: &Event) {
+            if *event == Event::MeasurementStarted {
+                STARTED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut sensor = build(
+            Sps30Builder::new().on_event(on_event),
+            vec![Exchange::command(START_MEASUREMENT, &[0x01, 0x03], 0, &[])],
+        );
+        sensor.start_measurement().unwrap();
+        assert_eq!(STARTED.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn config_replaces_earlier_settings() {
+        let config = Sps30Config {
+            clamp_negative: true,
+            ..Default::default()
+        };
+        let sensor = build(Sps30Builder::new().address(5).config(config), vec![]);
+        assert_eq!(sensor.address(), 0);
+        assert!(sensor.clamp_negative);
+
+        let sensor = build(Sps30Builder::new().config(config).address(5), vec![]);
+        assert_eq!(sensor.address(), 5);
+        assert!(sensor.clamp_negative);
+    }
+}
@@ -0,0 +1,88 @@
+//! Things happening inside the driver that an application may want to count
+//! or show, such as retries or the sensor's error flag coming and going.
+//!
+//! Set a sink with [`crate::Sps30::set_on_event`]. The driver logs these
+//! anyway; [`log_event`] is a sink that only logs, for applications that
+//! turn the driver's own logging down.
+
+use core::fmt;
+
+use cfg_block::cfg_block;
+cfg_block! {
+    #[cfg(feature = "std")]{
+        use log::info;
+    }
+    #[cfg(feature = "no_std")]{
+        use defmt::info;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "no_std", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Event {
+    /// No response to command `cmd` arrived within the response timeout.
+    Timeout {
+        cmd: u8,
+    },
+    /// The response to command `cmd` was corrupted: a bad checksum or
+    /// length, or for another address.
+    BadResponse {
+        cmd: u8,
+    },
+    /// Command `cmd` is being resent, `attempt` counting the first send as 1.
+    Retry {
+        cmd: u8,
+        attempt: u8,
+    },
+    /// Command `cmd` was answered after being resent, taking `attempts`
+    /// sends in all.
+    Recovered {
+        cmd: u8,
+        attempts: u8,
+    },
+    MeasurementStarted,
+    MeasurementStopped,
+    Reset,
+    Asleep,
+    Awake,
+    /// Responses started flagging an error in the device status register.
+    ErrorFlagged,
+    /// Responses stopped flagging an error.
+    ErrorCleared,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Timeout { cmd } => write!(f, "command {:#04x} timed out", cmd),
+            Event::BadResponse { cmd } => write!(f, "bad response to command {:#04x}", cmd),
+            Event::Retry { cmd, attempt } => {
+                write!(f, "resending command {:#04x}, attempt {}", cmd, attempt)
+            }
+            Event::Recovered { cmd, attempts } => {
+                write!(
+                    f,
+                    "command {:#04x} answered after {} attempts",
+                    cmd, attempts
+                )
+            }
+            Event::MeasurementStarted => f.write_str("measurement started"),
+            Event::MeasurementStopped => f.write_str("measurement stopped"),
+            Event::Reset => f.write_str("reset"),
+            Event::Asleep => f.write_str("asleep"),
+            Event::Awake => f.write_str("awake"),
+            Event::ErrorFlagged => f.write_str("device error flagged"),
+            Event::ErrorCleared => f.write_str("device error cleared"),
+        }
+    }
+}
+
+/// Called with every [`Event`] as it happens.
+pub type EventFn = fn(&Event);
+
+/// A sink that logs every event at info level.
+pub fn log_event(event: &Event) {
+    info!("Event: {}", event);
+}
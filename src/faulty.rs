@@ -0,0 +1,272 @@
+//! Fault injecting transport decorator.
+//!
+//! [`FaultyPort`] wraps any port and damages what the device sends back: it
+//! can drop bytes, flip bits, duplicate whole frames, delay responses, or stop
+//! answering altogether after a number of transactions. Faults are driven by
+//! a seeded PRNG so a failing run can be reproduced exactly.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use cfg_block::cfg_block;
+cfg_block! {
+    #[cfg(feature = "std")]{
+        use std::thread::sleep;
+        use std::time::Duration;
+    }
+}
+
+use crate::shdlc::FEND;
+use crate::{Transport, TransportError};
+
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    /// PRNG seed. The same seed and traffic always give the same faults.
+    pub seed: u64,
+    /// Probability of dropping each received byte.
+    pub drop_byte: f32,
+    /// Probability of flipping one bit in each received byte.
+    pub flip_bit: f32,
+    /// Probability of delivering a received frame twice.
+    pub duplicate_frame: f32,
+    /// Delay before the first byte of each response. Ignored on no_std.
+    pub delay_ms: u32,
+    /// Stop delivering anything once this many requests have been written.
+    pub silent_after: Option<usize>,
+    /// Flip a bit in the response to this request, counting from 1, for a
+    /// single fault at a known point.
+    pub corrupt_response: Option<usize>,
+}
+
+pub struct FaultyPort<P> {
+    inner: P,
+    config: FaultConfig,
+    rng: u64,
+    transactions: usize,
+    delay_pending: bool,
+    corrupted: bool,
+    frame: Vec<u8>,
+    pending: VecDeque<u8>,
+}
+
+impl<P> FaultyPort<P> {
+    pub fn new(inner: P, config: FaultConfig) -> Self {
+        Self {
+            inner,
+            // xorshift gets stuck on zero
+            rng: config.seed | 1,
+            config,
+            transactions: 0,
+            delay_pending: false,
+            corrupted: false,
+            frame: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+    /// Number of requests written through the port so far.
+    pub fn transactions(&self) -> usize {
+        self.transactions
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        (x >> 32) as u32
+    }
+    fn chance(&mut self, p: f32) -> bool {
+        p > 0.0 && (self.next_u32() as f32 / u32::MAX as f32) < p
+    }
+    fn silent(&self) -> bool {
+        match self.config.silent_after {
+            Some(n) => self.transactions > n,
+            None => false,
+        }
+    }
+
+    fn on_write(&mut self) {
+        self.transactions += 1;
+        self.delay_pending = true;
+    }
+    /// Run freshly received bytes through the configured faults.
+    fn inject(&mut self, data: &[u8]) {
+        if self.silent() {
+            return;
+        }
+        for &b in data {
+            if self.chance(self.config.drop_byte) {
+                continue;
+            }
+            let mut b = b;
+            if self.chance(self.config.flip_bit) {
+                b ^= 1 << (self.next_u32() % 8);
+            }
+            // the first byte inside the frame
+            if self.config.corrupt_response == Some(self.transactions)
+                && !self.corrupted
+                && b != FEND
+                && !self.frame.is_empty()
+            {
+                b ^= 1;
+                self.corrupted = true;
+            }
+            self.pending.push_back(b);
+
+            if b == FEND && self.frame.len() > 1 {
+                self.frame.push(b);
+                if self.chance(self.config.duplicate_frame) {
+                    self.pending.extend(&self.frame);
+                }
+                self.frame.clear();
+            } else if b == FEND {
+                self.frame.clear();
+                self.frame.push(b);
+            } else if !self.frame.is_empty() {
+                self.frame.push(b);
+            }
+        }
+    }
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.pending.pop_front() {
+                Some(b) => {
+                    buf[n] = b;
+                    n += 1;
+                }
+                None => break,
+            }
+        }
+        n
+    }
+    fn delay(&mut self) {
+        if !self.delay_pending {
+            return;
+        }
+        self.delay_pending = false;
+        #[cfg(feature = "std")]
+        if self.config.delay_ms > 0 {
+            sleep(Duration::from_millis(self.config.delay_ms as u64));
+        }
+    }
+}
+
+impl<P: Transport> Transport for FaultyPort<P> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        self.delay();
+        if self.pending.is_empty() {
+            let mut tmp = [0u8; 64];
+            let n = self.inner.read(&mut tmp)?;
+            self.inject(&tmp[..n]);
+        }
+        Ok(self.take(buf))
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+        if buf.first() == Some(&FEND) {
+            self.on_write();
+        }
+        self.inner.write_all(buf)
+    }
+    fn read_ready(&mut self) -> Option<bool> {
+        if !self.pending.is_empty() {
+            return Some(true);
+        }
+        self.inner.read_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Exchange, MockPort};
+    use crate::{RetryPolicy, Sps30, Sps30Measurement};
+
+    const READ_MEASURED_VALUE: u8 = 0x03;
+
+    fn readings(n: usize) -> MockPort {
+        let m = Sps30Measurement::from_array([1.0; 10]);
+        let e = Exchange::command(READ_MEASURED_VALUE, &[], 0, &m.to_bytes());
+        MockPort::new(alloc::vec![e; n])
+    }
+
+    fn one_bad_response(port: MockPort) -> Sps30<FaultyPort<MockPort>> {
+        Sps30::new(FaultyPort::new(
+            port,
+            FaultConfig {
+                corrupt_response: Some(37),
+                ..Default::default()
+            },
+        ))
+    }
+
+    #[test]
+    fn one_bad_response_in_100_is_retried() {
+        // one extra exchange for the resend
+        let mut sensor = one_bad_response(readings(101));
+        sensor.set_retry_policy(RetryPolicy {
+            retries: 1,
+            ..Default::default()
+        });
+        for i in 1..=100 {
+            assert!(
+                sensor.read_measurement().unwrap().is_some(),
+                "reading {}",
+                i
+            );
+        }
+        let port = sensor.link.port_mut();
+        assert_eq!(port.transactions(), 101);
+        assert!(port.inner.is_done());
+    }
+
+    #[test]
+    fn one_bad_response_in_100_fails_once_without_retries() {
+        let mut sensor = one_bad_response(readings(100));
+        let failed: Vec<usize> = (1..=100)
+            .filter(|_| sensor.read_measurement().is_err())
+            .collect();
+        assert_eq!(failed, [37]);
+        assert!(sensor.link.port_mut().inner.is_done());
+    }
+
+    #[test]
+    fn faults_are_reproducible_from_the_seed() {
+        let config = FaultConfig {
+            seed: 42,
+            flip_bit: 0.01,
+            drop_byte: 0.01,
+            ..Default::default()
+        };
+        let run = || {
+            let mut sensor = Sps30::new(FaultyPort::new(readings(50), config.clone()));
+            sensor.set_response_timeout(Some(core::time::Duration::from_millis(5)));
+            (0..50)
+                .map(|_| sensor.read_measurement().is_ok())
+                .collect::<Vec<_>>()
+        };
+        let first = run();
+        assert!(first.contains(&false));
+        assert_eq!(run(), first);
+    }
+
+    #[test]
+    fn goes_silent_after_n_requests() {
+        let mut sensor = Sps30::new(FaultyPort::new(
+            readings(3),
+            FaultConfig {
+                silent_after: Some(2),
+                ..Default::default()
+            },
+        ));
+        sensor.set_response_timeout(Some(core::time::Duration::from_millis(5)));
+        assert!(sensor.read_measurement().is_ok());
+        assert!(sensor.read_measurement().is_ok());
+        assert!(sensor.read_measurement().is_err());
+        assert!(sensor.timed_out());
+    }
+}
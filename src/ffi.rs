@@ -0,0 +1,270 @@
+//! C ABI for using the driver from C and C++.
+//!
+//! Build the library with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or
+//! `staticlib`) and include `include/sps30.h`, which is regenerated with
+//! `cbindgen --config cbindgen.toml --output include/sps30.h`.
+//!
+//! Every function returns one of the `SPS30_*` codes, and panics are caught
+//! here rather than unwinding into C.
+#![allow(non_camel_case_types)]
+
+use std::boxed::Box;
+use std::ffi::{CStr, c_char, c_int};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{FromRawFd, RawFd};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::time::Duration;
+
+use crate::{Sps30, Sps30Measurement};
+
+pub const SPS30_OK: c_int = 0;
+/// A handle or pointer argument was NULL.
+pub const SPS30_ERR_NULL: c_int = -1;
+/// The port couldn't be opened.
+pub const SPS30_ERR_PORT: c_int = -2;
+/// The sensor didn't answer, answered with a corrupt frame or reported an
+/// error.
+pub const SPS30_ERR_DEVICE: c_int = -3;
+/// No new measurement is available yet.
+pub const SPS30_ERR_NO_DATA: c_int = -4;
+/// The driver panicked. The handle should be closed.
+pub const SPS30_ERR_PANIC: c_int = -5;
+
+trait Port: Read + Write + Send {}
+impl<T: Read + Write + Send> Port for T {}
+
+/// Opaque driver handle.
+pub struct sps30_handle {
+    sensor: Sps30<Box<dyn Port>>,
+}
+
+/// Mass concentrations in µg/m³, number concentrations in #/cm³ and the
+/// typical particle size in µm.
+#[repr(C)]
+pub struct sps30_measurement {
+    pub mass_1_0: f32,
+    pub mass_2_5: f32,
+    pub mass_4_0: f32,
+    pub mass_10: f32,
+    pub number_pm0_5: f32,
+    pub number_pm1_0: f32,
+    pub number_pm2_5: f32,
+    pub number_pm4_0: f32,
+    pub number_pm10: f32,
+    pub typical_particle_size_um: f32,
+}
+
+impl From<Sps30Measurement> for sps30_measurement {
+    fn from(m: Sps30Measurement) -> Self {
+        Self {
+            mass_1_0: m.mass_1_0,
+            mass_2_5: m.mass_2_5,
+            mass_4_0: m.mass_4_0,
+            mass_10: m.mass_10,
+            number_pm0_5: m.number_pm0_5,
+            number_pm1_0: m.number_pm1_0,
+            number_pm2_5: m.number_pm2_5,
+            number_pm4_0: m.number_pm4_0,
+            number_pm10: m.number_pm10,
+            typical_particle_size_um: m.typical_particle_size_um,
+        }
+    }
+}
+
+fn guard(f: impl FnOnce() -> c_int) -> c_int {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(SPS30_ERR_PANIC)
+}
+
+fn new_handle(port: Box<dyn Port>, out: *mut *mut sps30_handle) -> c_int {
+    let handle = Box::new(sps30_handle {
+        sensor: Sps30::new(port),
+    });
+    // SAFETY: checked for NULL by the callers
+    unsafe { *out = Box::into_raw(handle) };
+    SPS30_OK
+}
+
+/// Open the serial port at `path` and store a new handle in `*out`.
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string and `out` a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sps30_open(
+    path: *const c_char,
+    baud: u32,
+    out: *mut *mut sps30_handle,
+) -> c_int {
+    guard(|| {
+        if path.is_null() || out.is_null() {
+            return SPS30_ERR_NULL;
+        }
+        // SAFETY: the caller passes a NUL terminated string
+        let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+            return SPS30_ERR_PORT;
+        };
+        match serialport::new(path, baud)
+            .timeout(Duration::from_millis(20))
+            .open()
+        {
+            Ok(p) => new_handle(Box::new(p), out),
+            Err(_) => SPS30_ERR_PORT,
+        }
+    })
+}
+
+/// Take ownership of an already configured file descriptor and store a new
+/// handle in `*out`. The descriptor is closed by `sps30_close`.
+///
+/// # Safety
+///
+/// `fd` must be an open descriptor not owned by anything else, and `out` a
+/// valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sps30_open_fd(fd: c_int, out: *mut *mut sps30_handle) -> c_int {
+    guard(|| {
+        if out.is_null() {
+            return SPS30_ERR_NULL;
+        }
+        if fd < 0 {
+            return SPS30_ERR_PORT;
+        }
+        // SAFETY: the caller hands over ownership of the descriptor
+        let file = unsafe { File::from_raw_fd(fd as RawFd) };
+        new_handle(Box::new(file), out)
+    })
+}
+
+fn with_handle(
+    handle: *mut sps30_handle,
+    f: impl FnOnce(&mut Sps30<Box<dyn Port>>) -> c_int,
+) -> c_int {
+    guard(|| {
+        // SAFETY: the caller passes a handle from sps30_open, or NULL
+        match unsafe { handle.as_mut() } {
+            Some(h) => f(&mut h.sensor),
+            None => SPS30_ERR_NULL,
+        }
+    })
+}
+
+/// # Safety
+///
+/// `handle` must come from `sps30_open` or `sps30_open_fd`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sps30_start_measurement(handle: *mut sps30_handle) -> c_int {
+    with_handle(handle, |s| match s.start_measurement() {
+        Ok(()) => SPS30_OK,
+        Err(_) => SPS30_ERR_DEVICE,
+    })
+}
+
+/// # Safety
+///
+/// `handle` must come from `sps30_open` or `sps30_open_fd`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sps30_stop_measurement(handle: *mut sps30_handle) -> c_int {
+    with_handle(handle, |s| match s.stop_measurement() {
+        Ok(()) => SPS30_OK,
+        Err(_) => SPS30_ERR_DEVICE,
+    })
+}
+
+/// Read the latest measurement into `*out`. Returns `SPS30_ERR_NO_DATA`,
+/// leaving `*out` untouched, if the sensor has nothing new.
+///
+/// # Safety
+///
+/// `handle` must come from `sps30_open` or `sps30_open_fd`, and `out` must
+/// be a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sps30_read_measurement(
+    handle: *mut sps30_handle,
+    out: *mut sps30_measurement,
+) -> c_int {
+    if out.is_null() {
+        return SPS30_ERR_NULL;
+    }
+    with_handle(handle, |s| match s.read_measurement() {
+        Ok(Some(m)) => {
+            // SAFETY: checked for NULL above
+            unsafe { *out = m.into() };
+            SPS30_OK
+        }
+        Ok(None) => SPS30_ERR_NO_DATA,
+        Err(_) => SPS30_ERR_DEVICE,
+    })
+}
+
+/// Free the handle and close its port. NULL is ignored.
+///
+/// # Safety
+///
+/// `handle` must come from `sps30_open` or `sps30_open_fd` and not be used
+/// afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sps30_close(handle: *mut sps30_handle) {
+    if !handle.is_null() {
+        // SAFETY: the handle was created by Box::into_raw
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(handle) })));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn header_fields_match() {
+        let header = include_str!("../include/sps30.h");
+        let start = header.find("typedef struct sps30_measurement {").unwrap();
+        let end = start + header[start..].find("} sps30_measurement;").unwrap();
+        let fields: Vec<&str> = header[start..end]
+            .lines()
+            .skip(1)
+            .map(|l| {
+                l.trim()
+                    .strip_prefix("float ")
+                    .unwrap()
+                    .trim_end_matches(';')
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "mass_1_0",
+                "mass_2_5",
+                "mass_4_0",
+                "mass_10",
+                "number_pm0_5",
+                "number_pm1_0",
+                "number_pm2_5",
+                "number_pm4_0",
+                "number_pm10",
+                "typical_particle_size_um",
+            ]
+        );
+    }
+
+    #[test]
+    fn converts_field_by_field() {
+        let m = Sps30Measurement::from_array([1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0]);
+        let c = sps30_measurement::from(m);
+        let fields = [
+            c.mass_1_0,
+            c.mass_2_5,
+            c.mass_4_0,
+            c.mass_10,
+            c.number_pm0_5,
+            c.number_pm1_0,
+            c.number_pm2_5,
+            c.number_pm4_0,
+            c.number_pm10,
+            c.typical_particle_size_um,
+        ];
+        assert_eq!(fields, m.as_array());
+    }
+}
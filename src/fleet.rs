@@ -0,0 +1,242 @@
+//! Polling several sensors on their own serial ports.
+//!
+//! [`Sps30Fleet`] probes ports for SPS30s and keys them on their serial
+//! numbers, so readings stay attributed to the right sensor however the
+//! adapters get enumerated. A sensor that stops answering is closed and
+//! looked for again on the next rescan, which picks it up even if it comes
+//! back on a different port.
+//!
+//! Serial numbers should be unique, but cloned sensors may share one. A
+//! sensor found with the serial number of one that is still connected is
+//! keyed as `<serial>-2`, `-3` and so on, in the order they were found.
+
+use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::format;
+use std::string::{String, ToString};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use log::{info, warn};
+use serialport::SerialPort;
+
+use crate::{DeviceError, DeviceStatus, HealthCheck, RetryPolicy, Sps30, Sps30Measurement};
+
+/// How often lost sensors are looked for. Probing a port that has something
+/// else on it takes a full response timeout.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+type Sensor = Sps30<Box<dyn SerialPort>>;
+
+struct Member {
+    /// Port the sensor was last found on.
+    path: String,
+    /// None while the sensor is lost.
+    sensor: Option<Sensor>,
+}
+
+pub struct Sps30Fleet {
+    /// Ports to probe, or None for every port on the system.
+    ports: Option<Vec<String>>,
+    members: BTreeMap<String, Member>,
+    last_scan: Option<Instant>,
+    health_check: Option<HealthCheck>,
+}
+
+/// Open the port at `path`, and if there's an SPS30 on it, start it measuring
+/// and return it with its serial number.
+fn connect(path: &str, health_check: Option<HealthCheck>) -> Option<(String, Sensor)> {
+    let mut sensor = Sps30::open_probed(path).ok()?;
+    sensor.set_retry_policy(RetryPolicy {
+        retries: 2,
+        ..Default::default()
+    });
+    sensor.set_health_check(health_check);
+    let serial = sensor.read_serial_number()?;
+    // it may still be measuring from before it was lost
+    sensor.stop_measurement().ok();
+    if let Err(e) = sensor.start_measurement() {
+        warn!("Can't start {} on {}: {}", serial, path, e);
+        return None;
+    }
+    info!("Found {} on {}", serial, path);
+    Some((serial, sensor))
+}
+
+/// Read from `sensor`, dropping it if it fails so it gets looked for again.
+fn read(serial: &str, m: &mut Member) -> Option<Result<Sps30Measurement, DeviceError>> {
+    let Some(sensor) = &mut m.sensor else {
+        return Some(Err(DeviceError::default()));
+    };
+    match sensor.read_measurement() {
+        Ok(Some(r)) => Some(Ok(r)),
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Lost {} on {}", serial, m.path);
+            m.sensor = None;
+            Some(Err(e))
+        }
+    }
+}
+
+impl Sps30Fleet {
+    /// Probe each of `paths` and start measuring on every SPS30 found.
+    pub fn discover(paths: &[&str]) -> Self {
+        let mut fleet = Self {
+            ports: Some(paths.iter().map(|p| p.to_string()).collect()),
+            members: BTreeMap::new(),
+            last_scan: None,
+            health_check: None,
+        };
+        fleet.rescan();
+        fleet
+    }
+
+    /// Probe every serial port on the system.
+    pub fn discover_all() -> Self {
+        let mut fleet = Self {
+            ports: None,
+            members: BTreeMap::new(),
+            last_scan: None,
+            health_check: None,
+        };
+        fleet.rescan();
+        fleet
+    }
+
+    /// Serial numbers of every sensor seen, lost or not.
+    pub fn serials(&self) -> impl Iterator<Item = &str> {
+        self.members.keys().map(|s| s.as_str())
+    }
+
+    /// Each sensor's serial number and the port it is on, None while lost.
+    pub fn ports(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.members.iter().map(|(serial, m)| {
+            let port = m.sensor.as_ref().map(|_| m.path.as_str());
+            (serial.as_str(), port)
+        })
+    }
+
+    /// The key for a sensor found with `serial`: the serial itself, unless a
+    /// connected sensor already has it.
+    fn key_for(&self, serial: String) -> String {
+        let free = |key: &str| self.members.get(key).is_none_or(|m| m.sensor.is_none());
+        if free(&serial) {
+            return serial;
+        }
+        let key = (2..)
+            .map(|n| format!("{}-{}", serial, n))
+            .find(|k| free(k))
+            .unwrap();
+        warn!("Duplicate serial number {}, keyed as {}", serial, key);
+        key
+    }
+
+    /// Port a sensor is on, or None if it isn't part of the fleet or is lost.
+    pub fn port(&self, serial: &str) -> Option<&str> {
+        self.members
+            .get(serial)
+            .filter(|m| m.sensor.is_some())
+            .map(|m| m.path.as_str())
+    }
+
+    /// Check the status of every sensor as often as `check` says while
+    /// polling, see [`Sps30::set_health_check`]. Applies to sensors found
+    /// later as well.
+    pub fn set_health_check(&mut self, check: Option<HealthCheck>) {
+        self.health_check = check;
+        for sensor in self.members.values_mut().filter_map(|m| m.sensor.as_mut()) {
+            sensor.set_health_check(check);
+        }
+    }
+
+    /// Device status found by a sensor's last health check, or None if it
+    /// hasn't had one or is lost.
+    pub fn status(&self, serial: &str) -> Option<DeviceStatus> {
+        self.members
+            .get(serial)?
+            .sensor
+            .as_ref()
+            .and_then(Sps30::last_status)
+    }
+
+    /// Probe the ports not in use for sensors, picking up lost ones again and
+    /// adding new ones.
+    pub fn rescan(&mut self) {
+        self.last_scan = Some(Instant::now());
+        let paths = match &self.ports {
+            Some(p) => p.clone(),
+            None => serialport::available_ports()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|p| p.port_name)
+                .collect(),
+        };
+        for path in paths {
+            let in_use = self
+                .members
+                .values()
+                .any(|m| m.sensor.is_some() && m.path == path);
+            if in_use {
+                continue;
+            }
+            if let Some((serial, sensor)) = connect(&path, self.health_check) {
+                let key = self.key_for(serial);
+                self.members.insert(
+                    key,
+                    Member {
+                        path,
+                        sensor: Some(sensor),
+                    },
+                );
+            }
+        }
+    }
+
+    fn rescan_if_lost(&mut self) {
+        let lost = self.members.values().any(|m| m.sensor.is_none());
+        let due = self.last_scan.is_none_or(|t| t.elapsed() > RESCAN_INTERVAL);
+        if lost && due {
+            self.rescan();
+        }
+    }
+
+    /// Read every sensor in turn. Sensors with nothing new since the last
+    /// poll are left out, and lost ones give an error until they're found
+    /// again.
+    pub fn poll(&mut self) -> Vec<(String, Result<Sps30Measurement, DeviceError>)> {
+        self.rescan_if_lost();
+        self.members
+            .iter_mut()
+            .filter_map(|(serial, m)| read(serial, m).map(|r| (serial.clone(), r)))
+            .collect()
+    }
+
+    /// Like [`Sps30Fleet::poll`], but reads every sensor on its own thread,
+    /// so one that is timing out doesn't hold up the others.
+    pub fn poll_parallel(&mut self) -> Vec<(String, Result<Sps30Measurement, DeviceError>)> {
+        self.rescan_if_lost();
+        thread::scope(|s| {
+            let handles: Vec<_> = self
+                .members
+                .iter_mut()
+                .map(|(serial, m)| s.spawn(move || read(serial, m).map(|r| (serial.clone(), r))))
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|h| h.join().ok().flatten())
+                .collect()
+        })
+    }
+}
+
+impl Drop for Sps30Fleet {
+    fn drop(&mut self) {
+        for m in self.members.values_mut() {
+            if let Some(sensor) = &mut m.sensor {
+                sensor.stop_measurement().ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,531 @@
+//! Driver for the SPS30's I2C interface.
+//!
+//! Over I2C the sensor is at address 0x69 and commands are 16 bit register
+//! pointers. Data is transferred in 16 bit words, each followed by a CRC-8 of
+//! the two bytes. Readings, faults and errors are the same types the UART
+//! driver uses.
+use cfg_block::cfg_block;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+use crate::{
+    DeviceError, DeviceInfo, DeviceStatus, FirmwareVersion, Sps30Interface, Sps30Measurement,
+    device_info,
+};
+
+cfg_block! {
+    #[cfg(feature = "std")]{
+        use log::{info, warn};
+    }
+    #[cfg(feature = "no_std")]{
+        use defmt::{info, warn};
+    }
+}
+
+/// The sensor's fixed I2C address.
+pub const ADDRESS: u8 = 0x69;
+
+const START_MEASUREMENT: u16 = 0x0010;
+const STOP_MEASUREMENT: u16 = 0x0104;
+const READ_DATA_READY: u16 = 0x0202;
+const READ_MEASURED_VALUES: u16 = 0x0300;
+const SLEEP: u16 = 0x1001;
+const WAKE_UP: u16 = 0x1103;
+const START_FAN_CLEANING: u16 = 0x5607;
+const AUTO_CLEANING_INTERVAL: u16 = 0x8004;
+const READ_PRODUCT_TYPE: u16 = 0xd002;
+const READ_SERIAL_NUMBER: u16 = 0xd033;
+const READ_VERSION: u16 = 0xd100;
+const READ_DEVICE_STATUS: u16 = 0xd206;
+const CLEAR_DEVICE_STATUS: u16 = 0xd210;
+const RESET: u16 = 0xd304;
+
+/// Longest read: a measurement, as 20 words with their CRCs.
+const MAX_READ: usize = Sps30Measurement::SIZE / 2 * 3;
+// the 32 byte serial number is the next longest
+const _: () = assert!(MAX_READ >= 32 / 2 * 3);
+
+/// Sensirion's CRC-8: polynomial 0x31, initial value 0xff, no reflection or
+/// final XOR.
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0xff_u8;
+    for b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Check and drop the CRC after each word of `raw`, writing the data bytes to
+/// `out`, which must be two thirds the length of `raw`.
+pub fn strip_crc(raw: &[u8], out: &mut [u8]) -> Result<(), DeviceError> {
+    if raw.len() * 2 != out.len() * 3 {
+        return Err(DeviceError::default());
+    }
+    for (chunk, word) in raw.chunks_exact(3).zip(out.chunks_exact_mut(2)) {
+        if crc8(&chunk[..2]) != chunk[2] {
+            warn!("CRC error");
+            return Err(DeviceError::default());
+        }
+        word.copy_from_slice(&chunk[..2]);
+    }
+    Ok(())
+}
+
+/// String from a NUL terminated word payload.
+fn to_string(data: &[u8]) -> Option<DeviceInfo> {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    device_info(core::str::from_utf8(&data[..end]).ok()?)
+}
+
+pub struct Sps30I2c<I2C, D> {
+    i2c: I2C,
+    delay: D,
+}
+
+impl<I2C: I2c, D: DelayNs> Sps30I2c<I2C, D> {
+    pub fn new(i2c: I2C, delay: D) -> Self {
+        Self { i2c, delay }
+    }
+    /// Give back the bus and delay.
+    pub fn release(self) -> (I2C, D) {
+        (self.i2c, self.delay)
+    }
+
+    /// Write a command pointer followed by up to two argument words.
+    fn write_command(&mut self, cmd: u16, args: &[u16]) -> Result<(), DeviceError> {
+        let mut buf = [0u8; 8];
+        buf[..2].copy_from_slice(&cmd.to_be_bytes());
+        let mut n = 2;
+        for w in args.iter().take(2) {
+            buf[n..n + 2].copy_from_slice(&w.to_be_bytes());
+            buf[n + 2] = crc8(&buf[n..n + 2]);
+            n += 3;
+        }
+        self.i2c.write(ADDRESS, &buf[..n]).map_err(|_| {
+            warn!("I2C write failed");
+            DeviceError::default()
+        })
+    }
+
+    /// Point at `cmd` and read `out.len()` data bytes back.
+    fn read(&mut self, cmd: u16, out: &mut [u8]) -> Result<(), DeviceError> {
+        self.write_command(cmd, &[])?;
+        let mut raw = [0u8; MAX_READ];
+        let raw = &mut raw[..out.len() / 2 * 3];
+        self.i2c.read(ADDRESS, raw).map_err(|_| {
+            warn!("I2C read failed");
+            DeviceError::default()
+        })?;
+        strip_crc(raw, out)
+    }
+
+    /// Product type string, "00080000" for the SPS30.
+    pub fn get_device_info(&mut self) -> Option<DeviceInfo> {
+        info!("Read product type");
+        let mut data = [0u8; 8];
+        self.read(READ_PRODUCT_TYPE, &mut data).ok()?;
+        to_string(&data)
+    }
+
+    pub fn read_serial_number(&mut self) -> Option<DeviceInfo> {
+        info!("Read serial number");
+        let mut data = [0u8; 32];
+        self.read(READ_SERIAL_NUMBER, &mut data).ok()?;
+        to_string(&data)
+    }
+
+    /// Firmware major and minor version. The I2C interface doesn't report
+    /// the hardware or protocol versions.
+    pub fn read_firmware_version(&mut self) -> Result<FirmwareVersion, DeviceError> {
+        info!("Read version");
+        let mut data = [0u8; 2];
+        self.read(READ_VERSION, &mut data)?;
+        Ok(FirmwareVersion::new(data[0], data[1]))
+    }
+
+    pub fn start_measurement(&mut self) -> Result<(), DeviceError> {
+        info!("Start Device measurement");
+        // IEEE754 float output
+        self.write_command(START_MEASUREMENT, &[0x0300])?;
+        self.delay.delay_ms(20);
+        Ok(())
+    }
+
+    pub fn stop_measurement(&mut self) -> Result<(), DeviceError> {
+        info!("Stop Device measurement");
+        self.write_command(STOP_MEASUREMENT, &[])?;
+        self.delay.delay_ms(20);
+        Ok(())
+    }
+
+    /// Whether a new measurement can be read.
+    pub fn read_data_ready(&mut self) -> Result<bool, DeviceError> {
+        let mut data = [0u8; 2];
+        self.read(READ_DATA_READY, &mut data)?;
+        Ok(data[1] == 1)
+    }
+
+    /// The latest measurement, or None if there's nothing new since the last
+    /// one.
+    pub fn read_measurement(&mut self) -> Result<Option<Sps30Measurement>, DeviceError> {
+        info!("Read Measurement");
+        if !self.read_data_ready()? {
+            return Ok(None);
+        }
+        let mut data = [0u8; Sps30Measurement::SIZE];
+        self.read(READ_MEASURED_VALUES, &mut data)?;
+        Sps30Measurement::from_bytes(&data)
+    }
+
+    pub fn start_fan_cleaning(&mut self) -> Result<(), DeviceError> {
+        info!("Start fan cleaning");
+        self.write_command(START_FAN_CLEANING, &[])?;
+        self.delay.delay_ms(5);
+        Ok(())
+    }
+
+    pub fn read_auto_cleaning_interval(&mut self) -> Result<u32, DeviceError> {
+        info!("Read auto cleaning interval");
+        let mut data = [0u8; 4];
+        self.read(AUTO_CLEANING_INTERVAL, &mut data)?;
+        Ok(u32::from_be_bytes(data))
+    }
+
+    pub fn write_auto_cleaning_interval(&mut self, seconds: u32) -> Result<(), DeviceError> {
+        info!("Write auto cleaning interval: {}", seconds);
+        let words = [(seconds >> 16) as u16, seconds as u16];
+        self.write_command(AUTO_CLEANING_INTERVAL, &words)?;
+        self.delay.delay_ms(20);
+        Ok(())
+    }
+
+    pub fn sleep(&mut self) -> Result<(), DeviceError> {
+        info!("Sleep");
+        self.write_command(SLEEP, &[])?;
+        self.delay.delay_ms(5);
+        Ok(())
+    }
+
+    pub fn wake_up(&mut self) -> Result<(), DeviceError> {
+        info!("Wake up");
+        // the first command only wakes the interface up and isn't
+        // acknowledged
+        self.write_command(WAKE_UP, &[]).ok();
+        self.write_command(WAKE_UP, &[])?;
+        self.delay.delay_ms(5);
+        Ok(())
+    }
+
+    pub fn device_reset(&mut self) -> Result<(), DeviceError> {
+        info!("Sending Reset");
+        self.write_command(RESET, &[])?;
+        self.delay.delay_ms(100);
+        Ok(())
+    }
+
+    fn read_status_register(&mut self) -> Result<u32, DeviceError> {
+        let mut data = [0u8; 4];
+        self.read(READ_DEVICE_STATUS, &mut data)?;
+        Ok(u32::from_be_bytes(data))
+    }
+
+    fn clear_status_register(&mut self) -> Result<(), DeviceError> {
+        self.write_command(CLEAR_DEVICE_STATUS, &[])?;
+        self.delay.delay_ms(5);
+        Ok(())
+    }
+
+    /// Read the device status register, then clear it with a separate
+    /// command, so faults that have gone away stop being reported. Anything
+    /// else checking the status afterwards sees a clean register; use
+    /// [`Sps30I2c::peek_device_status`] to leave it as it is.
+    pub fn read_and_clear_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        info!("Reading and clearing device status");
+        let raw = self.read_status_register()?;
+        self.clear_status_register()?;
+        Ok(DeviceStatus::new(raw, true))
+    }
+
+    /// Read the device status register, leaving any flagged faults set.
+    pub fn peek_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        info!("Peeking device status");
+        self.read_status_register()
+            .map(|raw| DeviceStatus::new(raw, false))
+    }
+
+    /// Read the device status register and clear it.
+    #[deprecated(
+        since = "0.2.0",
+        note = "use read_and_clear_device_status, or peek_device_status to leave the register set"
+    )]
+    pub fn read_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        self.read_and_clear_device_status()
+    }
+
+    /// Read the whole device status register and clear it.
+    #[deprecated(since = "0.2.0", note = "use read_and_clear_device_status().raw()")]
+    pub fn read_device_status_raw(&mut self) -> Result<u32, DeviceError> {
+        self.read_and_clear_device_status().map(|s| s.raw())
+    }
+
+    /// Read the whole device status register, leaving any flagged faults
+    /// set.
+    #[deprecated(since = "0.2.0", note = "use peek_device_status().raw()")]
+    pub fn peek_device_status_raw(&mut self) -> Result<u32, DeviceError> {
+        self.peek_device_status().map(|s| s.raw())
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> Sps30Interface for Sps30I2c<I2C, D> {
+    fn get_device_info(&mut self) -> Option<DeviceInfo> {
+        Sps30I2c::get_device_info(self)
+    }
+    fn read_serial_number(&mut self) -> Option<DeviceInfo> {
+        Sps30I2c::read_serial_number(self)
+    }
+    fn read_firmware_version(&mut self) -> Result<FirmwareVersion, DeviceError> {
+        Sps30I2c::read_firmware_version(self)
+    }
+    fn start_measurement(&mut self) -> Result<(), DeviceError> {
+        Sps30I2c::start_measurement(self)
+    }
+    fn stop_measurement(&mut self) -> Result<(), DeviceError> {
+        Sps30I2c::stop_measurement(self)
+    }
+    fn read_measurement(&mut self) -> Result<Option<Sps30Measurement>, DeviceError> {
+        Sps30I2c::read_measurement(self)
+    }
+    fn start_fan_cleaning(&mut self) -> Result<(), DeviceError> {
+        Sps30I2c::start_fan_cleaning(self)
+    }
+    fn read_auto_cleaning_interval(&mut self) -> Result<u32, DeviceError> {
+        Sps30I2c::read_auto_cleaning_interval(self)
+    }
+    fn write_auto_cleaning_interval(&mut self, seconds: u32) -> Result<(), DeviceError> {
+        Sps30I2c::write_auto_cleaning_interval(self, seconds)
+    }
+    fn sleep(&mut self) -> Result<(), DeviceError> {
+        Sps30I2c::sleep(self)
+    }
+    fn wake_up(&mut self) -> Result<(), DeviceError> {
+        Sps30I2c::wake_up(self)
+    }
+    fn device_reset(&mut self) -> Result<(), DeviceError> {
+        Sps30I2c::device_reset(self)
+    }
+    fn read_and_clear_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        Sps30I2c::read_and_clear_device_status(self)
+    }
+    fn peek_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        Sps30I2c::peek_device_status(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::VecDeque;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, Operation};
+
+    /// Answers reads from a queue of data payloads, adding the CRCs, and
+    /// records what was written.
+    #[derive(Default)]
+    struct FakeBus {
+        replies: VecDeque<Vec<u8>>,
+        written: Vec<Vec<u8>>,
+    }
+
+    impl ErrorType for FakeBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for FakeBus {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), ErrorKind> {
+            assert_eq!(address, ADDRESS);
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => self.written.push(bytes.to_vec()),
+                    Operation::Read(buf) => {
+                        let data = self.replies.pop_front().ok_or(ErrorKind::Other)?;
+                        assert_eq!(data.len() / 2 * 3, buf.len());
+                        for (word, out) in data.chunks_exact(2).zip(buf.chunks_exact_mut(3)) {
+                            out[..2].copy_from_slice(word);
+                            out[2] = crc8(word);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn crc8_datasheet_vector() {
+        assert_eq!(crc8(&[0xbe, 0xef]), 0x92);
+    }
+
+    #[test]
+    fn strip_crc_keeps_the_data_words() {
+        let mut out = [0u8; 4];
+        strip_crc(&[0xbe, 0xef, 0x92, 0x00, 0x00, 0x81], &mut out).unwrap();
+        assert_eq!(out, [0xbe, 0xef, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn strip_crc_rejects_a_bad_crc() {
+        let mut out = [0u8; 2];
+        assert!(strip_crc(&[0xbe, 0xef, 0x93], &mut out).is_err());
+    }
+
+    #[test]
+    fn strip_crc_rejects_mismatched_lengths() {
+        let mut out = [0u8; 4];
+        assert!(strip_crc(&[0xbe, 0xef, 0x92], &mut out).is_err());
+        assert!(strip_crc(&[0xbe, 0xef, 0x92, 0x00], &mut out[..2]).is_err());
+    }
+
+    #[test]
+    fn strings_end_at_the_first_nul() {
+        assert_eq!(to_string(b"00080000\0\0").unwrap().as_str(), "00080000");
+        assert_eq!(to_string(b"ABC").unwrap().as_str(), "ABC");
+        assert!(to_string(&[0xff, 0xfe]).is_none());
+    }
+
+    #[test]
+    fn reads_a_full_measurement() {
+        let m = Sps30Measurement {
+            mass_1_0: 1.0,
+            mass_2_5: 2.5,
+            mass_4_0: 4.0,
+            mass_10: 10.0,
+            number_pm0_5: 0.5,
+            number_pm1_0: 1.5,
+            number_pm2_5: 2.0,
+            number_pm4_0: 3.0,
+            number_pm10: 4.5,
+            typical_particle_size_um: 0.6,
+        };
+        let mut bus = FakeBus::default();
+        bus.replies.push_back(vec![0, 1]);
+        bus.replies.push_back(m.to_bytes().to_vec());
+        let mut sensor = Sps30I2c::new(bus, NoDelay);
+        assert_eq!(sensor.read_measurement().unwrap(), Some(m));
+        let (bus, _) = sensor.release();
+        assert_eq!(
+            bus.written,
+            [
+                READ_DATA_READY.to_be_bytes().to_vec(),
+                READ_MEASURED_VALUES.to_be_bytes().to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_to_read_until_data_is_ready() {
+        let mut bus = FakeBus::default();
+        bus.replies.push_back(vec![0, 0]);
+        let mut sensor = Sps30I2c::new(bus, NoDelay);
+        assert_eq!(sensor.read_measurement().unwrap(), None);
+    }
+
+    #[test]
+    fn writes_argument_words_with_crcs() {
+        let mut sensor = Sps30I2c::new(FakeBus::default(), NoDelay);
+        sensor.write_auto_cleaning_interval(0xbeef).unwrap();
+        let (bus, _) = sensor.release();
+        assert_eq!(
+            bus.written,
+            [vec![0x80, 0x04, 0x00, 0x00, 0x81, 0xbe, 0xef, 0x92]]
+        );
+    }
+
+    /// What a session through the shared trait saw, to compare the drivers.
+    type Session = (
+        crate::DeviceModel,
+        Option<DeviceInfo>,
+        FirmwareVersion,
+        Option<Sps30Measurement>,
+        u32,
+        DeviceStatus,
+    );
+
+    fn session(sensor: &mut dyn Sps30Interface) -> Session {
+        let model = sensor.identify().unwrap();
+        let serial = sensor.read_serial_number();
+        let firmware = sensor.read_firmware_version().unwrap();
+        sensor.start_measurement().unwrap();
+        let reading = sensor.read_measurement().unwrap();
+        let interval = sensor.read_auto_cleaning_interval().unwrap();
+        let status = sensor.peek_device_status().unwrap();
+        sensor.stop_measurement().unwrap();
+        (model, serial, firmware, reading, interval, status)
+    }
+
+    #[test]
+    fn both_drivers_behave_alike_through_the_trait() {
+        use crate::Sps30;
+        use crate::mock::{Exchange, MockPort};
+
+        const SERIAL: &[u8; 16] = b"ABCDEF0123456789";
+        let m = Sps30Measurement::from_array([1.0, 2.5, 4.0, 10.0, 0.5, 1.5, 2.0, 3.0, 4.5, 0.6]);
+        let week = 604_800_u32.to_be_bytes();
+        let status = [0x00, 0x20, 0x00, 0x00];
+
+        let mut serial = SERIAL.to_vec();
+        serial.push(0);
+        let mut uart = Sps30::new(MockPort::new(vec![
+            Exchange::command(0xd0, &[0x00], 0, b"00080000\0"),
+            Exchange::command(0xd0, &[0x03], 0, &serial),
+            Exchange::command(0xd1, &[], 0, &[2, 2, 0, 7, 0, 2, 0]),
+            Exchange::command(0x00, &[0x01, 0x03], 0, &[]),
+            Exchange::command(0x03, &[], 0, &m.to_bytes()),
+            Exchange::command(0x80, &[0x00], 0, &week),
+            Exchange::command(0xd2, &[0x00], 0, &[status[0], status[1], 0, 0, 0]),
+            Exchange::command(0x01, &[], 0, &[]),
+        ]));
+
+        let mut bus = FakeBus::default();
+        let mut serial = SERIAL.to_vec();
+        serial.resize(32, 0);
+        for reply in [
+            b"00080000".to_vec(),
+            serial,
+            vec![2, 2],
+            vec![0, 1],
+            m.to_bytes().to_vec(),
+            week.to_vec(),
+            status.to_vec(),
+        ] {
+            bus.replies.push_back(reply);
+        }
+        let mut i2c = Sps30I2c::new(bus, NoDelay);
+
+        let over_uart = session(&mut uart);
+        let over_i2c = session(&mut i2c);
+        assert_eq!(over_uart, over_i2c);
+        assert_eq!(over_uart.0, crate::DeviceModel::Sps30);
+        assert_eq!(over_uart.3, Some(m));
+        assert!(over_uart.5.has_warnings());
+        assert!(uart.link.port_mut().is_done());
+        assert!(i2c.release().0.replies.is_empty());
+    }
+}
@@ -7,31 +7,65 @@ compile_error!("Only one of std or no-std may be selected at a time");
 
 extern crate alloc;
 
+// The JsonSchema derive expands to code naming Box, vec! and to_owned
+// unqualified, which a no_std crate has no prelude for.
+#[cfg(feature = "schemars")]
+use std::prelude::rust_2024::*;
+
 use cfg_block::cfg_block;
 cfg_block! {
     #[cfg(feature = "std")]{
         extern crate std;
-        use std::collections::HashMap;
-        use log::info;
-        use std::io::{Read, Write};
-        use std::time::Duration;
+        use log::{debug, info, warn};
+        use std::time::{Duration, Instant};
         use std::thread::sleep;
     }
     #[cfg(feature = "no_std")]{
-        use hashbrown::HashMap;
-        use defmt::info;
-        use embedded_io::{Read, Write};
+        use core::time::Duration;
+        use defmt::{debug, info, warn};
     }
 }
 
-use alloc::borrow::ToOwned;
+pub mod aqi;
+#[cfg(feature = "std")]
+pub mod capture;
+pub mod clock;
+pub mod config;
+pub mod event;
+#[cfg(feature = "mock")]
+pub mod faulty;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "serialport")]
+pub mod fleet;
+#[cfg(feature = "i2c")]
+pub mod i2c;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "std")]
+pub mod self_test;
+pub mod shdlc;
+pub mod stats;
+pub mod transport;
+#[cfg(feature = "uom")]
+pub mod units;
+
+pub use clock::{Clock, Elapsed};
+pub use config::{Sps30Builder, Sps30Config};
+pub use event::{Event, EventFn};
+pub use shdlc::{Direction, FrameError, Payload, RetryPolicy, TraceFn, checksum};
+#[cfg(feature = "std")]
+pub use transport::PortTimeout;
+pub use transport::{Transport, TransportError};
+
+use shdlc::ShdlcTransceiver;
+
 use alloc::string::String;
 use alloc::string::ToString;
-use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Command {
     StartMeasurement,
     StopMeasurement,
@@ -80,7 +114,7 @@ impl TryFrom<u8> for Command {
             0xD2 => Ok(Command::ReadDeviceStatusRegister),
             0xD3 => Ok(Command::Reset),
             _ => {
-                info!("Unknown command for: {}", value);
+                warn!("Unknown command for: {}", value);
                 Err(CommandError {})
             }
         }
@@ -88,18 +122,63 @@ impl TryFrom<u8> for Command {
 }
 
 #[derive(Debug)]
-struct Frame {
-    addr: u8,
-    cmd: Command,
-    data: Vec<u8>,
+pub struct Frame {
+    pub addr: u8,
+    pub cmd: Command,
+    pub data: Payload,
 }
 
-#[derive(Debug)]
-pub struct FrameError {}
+/// Strings read from the device, such as the serial number: a `String`, or
+/// with the `heapless` feature one of fixed capacity, as the device sends at
+/// most 32 bytes.
+#[cfg(not(feature = "heapless"))]
+pub type DeviceInfo = String;
+#[cfg(feature = "heapless")]
+pub type DeviceInfo = heapless::String<32>;
 
-impl core::fmt::Display for FrameError {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "FrameError")
+#[cfg(not(feature = "heapless"))]
+fn device_info(s: &str) -> Option<DeviceInfo> {
+    Some(s.into())
+}
+#[cfg(feature = "heapless")]
+fn device_info(s: &str) -> Option<DeviceInfo> {
+    DeviceInfo::try_from(s).ok()
+}
+
+impl Frame {
+    /// Build the stuffed MOSI bytes for this frame, ready to be written to the port.
+    pub fn encode(&self) -> Result<Vec<u8>, FrameError> {
+        shdlc::encode_request(self.addr, self.cmd.into(), &self.data)
+    }
+
+    /// Parse a raw MISO frame as returned by the HDLC frame reader, returning the
+    /// device state byte along with the frame.
+    pub fn decode(raw: &[u8]) -> Result<(u8, Frame), FrameError> {
+        let (state, f) = shdlc::decode_response(raw)?;
+        Ok((state, f.try_into()?))
+    }
+
+    /// Build the stuffed MISO bytes the device would send for this frame. Used
+    /// when emulating the sensor side of the link.
+    pub fn encode_response(&self, state: u8) -> Result<Vec<u8>, FrameError> {
+        shdlc::encode_response(self.addr, self.cmd.into(), state, &self.data)
+    }
+
+    /// Parse a raw MOSI frame, as the device would receive it.
+    pub fn decode_request(raw: &[u8]) -> Result<Frame, FrameError> {
+        shdlc::decode_request(raw)?.try_into()
+    }
+}
+
+impl TryFrom<shdlc::RawFrame> for Frame {
+    type Error = FrameError;
+
+    fn try_from(f: shdlc::RawFrame) -> Result<Self, Self::Error> {
+        Ok(Frame {
+            addr: f.addr,
+            cmd: f.cmd.try_into().map_err(|_| FrameError {})?,
+            data: f.data,
+        })
     }
 }
 
@@ -111,231 +190,606 @@ impl core::fmt::Display for CommandError {
     }
 }
 
-#[derive(Debug)]
-pub struct DeviceError {}
+#[derive(Debug, Default)]
+pub struct DeviceError {
+    unsupported: Option<UnsupportedByFirmware>,
+}
+impl DeviceError {
+    /// Why the command wasn't sent, if the sensor's firmware is too old for
+    /// it.
+    pub fn unsupported_by_firmware(&self) -> Option<&UnsupportedByFirmware> {
+        self.unsupported.as_ref()
+    }
+}
 impl core::fmt::Display for DeviceError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "DeviceError")
+        match &self.unsupported {
+            Some(u) => write!(f, "DeviceError: {}", u),
+            None => write!(f, "DeviceError"),
+        }
     }
 }
-
-fn checksum(buf: Vec<u8>) -> u8 {
-    let c = buf.iter().fold(0_u8, |acc, x| acc.wrapping_add(*x));
-    !c
+impl From<FrameError> for DeviceError {
+    fn from(_: FrameError) -> Self {
+        DeviceError::default()
+    }
 }
 
 fn slice_to_f32(a: &[u8]) -> f32 {
-    let mut t = Vec::new();
-    a.clone_into(&mut t);
-    let v: [u8; 4] = t.try_into().unwrap();
-    f32::from_be_bytes(v)
+    f32::from_be_bytes(a.try_into().unwrap())
 }
 
-fn to_bool(i: u8) -> bool {
-    match i {
-        0 => false,
-        _ => true,
-    }
+/// Blocks for the given number of milliseconds.
+pub type DelayFn = fn(u32);
+
+/// Called with the device status whenever the driver finds faults flagged.
+pub type FaultFn = fn(&DeviceStatus);
+
+/// How long after measurement starts the SPS30's readings can't be trusted:
+/// until the fan and air flow have settled they read zero or implausibly low.
+pub const WARM_UP: core::time::Duration = core::time::Duration::from_secs(8);
+
+/// How often [`Sps30::read_measurement`] also peeks at the device status
+/// register, see [`Sps30::set_health_check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthCheck {
+    /// After every this many new readings.
+    Readings(u32),
+    /// After the first new reading once this long has passed since the last
+    /// check.
+    #[cfg(feature = "std")]
+    Interval(Duration),
 }
 
+/// Set in the state byte of every response while the device status register
+/// has an error flagged. The other seven bits are the command's error code.
+const STATE_ERROR_FLAG: u8 = 0x80;
+
+/// Response timeout until one is set, the end of the 100 to 200 ms window the
+/// datasheet gives for the device to answer.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_millis(200);
+
 pub struct Sps30<P> {
-    port: P,
+    link: ShdlcTransceiver<P>,
+    addr: u8,
     running: bool,
+    /// New readings returned since measurement started.
+    readings: u32,
+    #[cfg(feature = "std")]
+    started: Option<Instant>,
+    response_timeout: Option<Duration>,
+    delay: Option<DelayFn>,
+    clamp_negative: bool,
+    error_pending: bool,
+    auto_status: bool,
+    pending_status: Option<DeviceStatus>,
+    on_fault: Option<FaultFn>,
+    health_check: Option<HealthCheck>,
+    /// New readings since the last health check.
+    since_check: u32,
+    #[cfg(feature = "std")]
+    last_check: Option<Instant>,
+    last_status: Option<DeviceStatus>,
+    identity: Option<DeviceIdentity>,
+    version: Option<Sps30Version>,
+    serial: Option<DeviceInfo>,
+    protocol_check: ProtocolCheck,
+    skip_firmware_checks: bool,
 }
 
-impl<P: Write + Read> Sps30<P> {
+impl<P: Transport> Sps30<P> {
     pub fn new(port: P) -> Self {
         Self {
-            port,
+            link: ShdlcTransceiver::new(port),
+            addr: 0,
             running: false,
+            readings: 0,
+            #[cfg(feature = "std")]
+            started: None,
+            response_timeout: Some(DEFAULT_RESPONSE_TIMEOUT),
+            delay: None,
+            clamp_negative: false,
+            error_pending: false,
+            auto_status: false,
+            pending_status: None,
+            on_fault: None,
+            health_check: None,
+            since_check: 0,
+            #[cfg(feature = "std")]
+            last_check: None,
+            last_status: None,
+            identity: None,
+            version: None,
+            serial: None,
+            protocol_check: ProtocolCheck::Warn,
+            skip_firmware_checks: false,
         }
     }
-    fn send_frame(&mut self, f: Frame) -> Result<(), FrameError> {
-        let HDLC_CONFIG: hdlc::SpecialChars = hdlc::SpecialChars {
-            fend: 0x7e,
-            fesc: 0x7d,
-            translate: HashMap::from([
-                (0x7e_u8, 0x5e_u8),
-                (0x7d, 0x5d),
-                (0x11, 0x31),
-                (0x13, 0x33),
-            ]),
-        };
-        // println!("Sending frame: {:#x?}", f);
-        if f.addr != 0 {
-            return Err(FrameError {});
+    /// SHDLC slave address used for requests and expected in responses. The
+    /// SPS30 always answers on 0, the default; other addresses are for
+    /// devices sharing a bus.
+    pub fn set_address(&mut self, addr: u8) {
+        self.addr = addr;
+    }
+    pub fn address(&self) -> u8 {
+        self.addr
+    }
+    /// Round trip time of the last completed transaction, from the request
+    /// being written to its response being decoded.
+    #[cfg(feature = "std")]
+    pub fn last_latency(&self) -> Option<Duration> {
+        self.link.last_latency()
+    }
+    /// Hand every frame to `trace` as it is sent or received, for debugging
+    /// protocol issues. [`Frame::decode_request`] and [`Frame::decode`] turn
+    /// the bytes back into frames.
+    pub fn set_trace(&mut self, trace: Option<TraceFn>) {
+        self.link.set_trace(trace);
+    }
+    /// Resend commands according to `policy` when their response times out
+    /// or is corrupted. No retries are made by default.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.link.set_retry_policy(policy);
+    }
+    /// How many times the last command was sent, including the first.
+    pub fn last_attempts(&self) -> u8 {
+        self.link.last_attempts()
+    }
+    /// Hand every [`Event`] to `on_event` as it happens, e.g. to count
+    /// retries or show that the sensor is recovering.
+    pub fn set_on_event(&mut self, on_event: Option<EventFn>) {
+        self.link.set_on_event(on_event);
+    }
+    /// Whether the last command failed because its response didn't arrive
+    /// within the response timeout, after any retries.
+    pub fn timed_out(&self) -> bool {
+        self.link.timed_out()
+    }
+    /// Give up on a response that hasn't arrived this long after its command
+    /// was sent, resending the command if the retry policy allows. Defaults
+    /// to [`DEFAULT_RESPONSE_TIMEOUT`]; `None` waits forever.
+    ///
+    /// The deadline is only checked between reads from the port, so a port
+    /// whose reads block for longer than this overshoots it by up to its own
+    /// timeout. For ports implementing `PortTimeout`, [`Sps30::set_timeout`]
+    /// sets both. On no_std the timeout only applies once a delay is set, see
+    /// [`shdlc::ShdlcTransceiver::receive`].
+    pub fn set_response_timeout(&mut self, timeout: Option<Duration>) {
+        self.response_timeout = timeout;
+    }
+    /// Use `delay` for the waits some commands need, the pause between
+    /// retries and, on no_std, timing responses out. On std it defaults to
+    /// sleeping the thread; no_std has no way to wait without one.
+    pub fn set_delay(&mut self, delay: Option<DelayFn>) {
+        self.delay = delay;
+        self.link.set_delay(delay);
+    }
+    /// Replace negative values in readings with 0, see
+    /// [`Sps30Measurement::clamp_negative`]. Off by default, so readings are
+    /// passed on exactly as the sensor sent them.
+    pub fn set_clamp_negative(&mut self, clamp: bool) {
+        self.clamp_negative = clamp;
+    }
+    /// When a response flags an error in the device status register, peek at
+    /// the register straight away and keep what it says for
+    /// [`Sps30::pending_status`]. Off by default, as it costs an extra
+    /// transaction after every command while the error persists.
+    pub fn set_auto_status(&mut self, enabled: bool) {
+        self.auto_status = enabled;
+    }
+    /// Whether the last response flagged an error in the device status
+    /// register, e.g. a fan or laser failure. It stays flagged until the
+    /// register is cleared with [`Sps30::read_and_clear_device_status`].
+    pub fn device_error_pending(&self) -> bool {
+        self.error_pending
+    }
+    /// The status register as read after the last response flagged an error,
+    /// with [`Sps30::set_auto_status`] enabled. None while nothing is flagged.
+    pub fn pending_status(&self) -> Option<DeviceStatus> {
+        self.pending_status
+    }
+    /// Call `on_fault` when a status read finds faults, and when a response
+    /// newly flags an error, after peeking at the status register to see
+    /// which. With [`Sps30::set_auto_status`] it is also called whenever the
+    /// flagged status changes while the error persists.
+    pub fn set_on_fault(&mut self, on_fault: Option<FaultFn>) {
+        self.on_fault = on_fault;
+    }
+    /// Peek at the device status register from [`Sps30::read_measurement`]
+    /// as often as `check` says, right after a new reading so the extra
+    /// transaction doesn't delay it. The result is kept for
+    /// [`Sps30::last_status`] and faults go to the [`Sps30::set_on_fault`]
+    /// callback. A failed check is logged but doesn't fail the reading.
+    pub fn set_health_check(&mut self, check: Option<HealthCheck>) {
+        self.health_check = check;
+        self.since_check = 0;
+    }
+    /// The device status found by the last health check.
+    pub fn last_status(&self) -> Option<DeviceStatus> {
+        self.last_status
+    }
+    fn health_check_due(&self) -> bool {
+        match self.health_check {
+            None => false,
+            Some(HealthCheck::Readings(n)) => self.since_check >= n,
+            #[cfg(feature = "std")]
+            Some(HealthCheck::Interval(d)) => self.last_check.is_none_or(|t| t.elapsed() >= d),
+        }
+    }
+    fn check_health(&mut self) {
+        self.since_check = 0;
+        #[cfg(feature = "std")]
+        {
+            self.last_check = Some(Instant::now());
+        }
+        match self.peek_device_status() {
+            Ok(s) => self.last_status = Some(s),
+            Err(_) => warn!("Health check failed"),
+        }
+    }
+    fn notify_fault(&self, status: &DeviceStatus) {
+        if let Some(on_fault) = self.on_fault {
+            on_fault(status);
+        }
+    }
+    fn wait_ms(&self, ms: u32) {
+        match self.delay {
+            Some(delay) => delay(ms),
+            #[cfg(feature = "std")]
+            None => sleep(Duration::from_millis(ms as u64)),
+            #[cfg(feature = "no_std")]
+            None => warn!("No delay set, not waiting {}ms", ms),
+        }
+    }
+    /// Send a command and wait for a response of at most `expected_len`
+    /// bytes, returning the device state byte and the response data.
+    fn exchange(
+        &mut self,
+        cmd: Command,
+        data: &[u8],
+        expected_len: usize,
+    ) -> Result<(u8, Payload), FrameError> {
+        let (state, data) = self.link.transceive(
+            self.addr,
+            cmd.into(),
+            data,
+            expected_len,
+            self.response_timeout,
+        )?;
+        self.note_state(cmd.into(), state);
+        Ok((state, data))
+    }
+
+    /// Track the error flag in a response's state byte.
+    fn note_state(&mut self, cmd: u8, state: u8) {
+        let pending = state & STATE_ERROR_FLAG != 0;
+        let newly = pending && !self.error_pending;
+        if newly {
+            warn!("Device reports an error in its status register");
+            self.link.emit(Event::ErrorFlagged);
+        } else if self.error_pending && !pending {
+            self.link.emit(Event::ErrorCleared);
+        }
+        self.error_pending = pending;
+        if !pending {
+            self.pending_status = None;
+            return;
+        }
+        let peek = self.auto_status || (newly && self.on_fault.is_some());
+        if !peek || cmd == u8::from(Command::ReadDeviceStatusRegister) {
+            return;
         }
-        let mut buffer = Vec::new();
-        buffer.push(f.addr);
-        buffer.push(f.cmd.into());
-        buffer.push(f.data.len() as u8);
-        buffer.append(&mut f.data.clone());
-        let c = checksum(buffer.clone());
-        buffer.push(c);
+        match self.read_status_register(false) {
+            Ok(raw) => {
+                let status = DeviceStatus::new(raw, false);
+                if self.pending_status != Some(status) {
+                    self.notify_fault(&status);
+                }
+                self.pending_status = Some(status);
+            }
+            Err(_) => warn!("Can't read the flagged device status"),
+        }
+    }
 
-        let packet = hdlc::encode(&buffer, HDLC_CONFIG).unwrap();
-        // println!("Send Packet: {:#x?}", packet);
+    /// Send a command and wait for its response, failing if the device
+    /// reports an error code in the state byte. The status register's error
+    /// flag doesn't fail the command, see [`Sps30::device_error_pending`].
+    fn transact(
+        &mut self,
+        cmd: Command,
+        data: &[u8],
+        expected_len: usize,
+    ) -> Result<Payload, DeviceError> {
+        let (status, data) = self.exchange(cmd, data, expected_len)?;
 
-        self.port.write_all(&packet).unwrap();
+        if status & !STATE_ERROR_FLAG != 0 {
+            warn!(
+                "Command failed with error code {}",
+                status & !STATE_ERROR_FLAG
+            );
+            return Err(DeviceError::default());
+        }
 
-        Ok(())
+        Ok(data)
     }
-    fn receive_frame(&mut self) -> Result<(u8, Frame), FrameError> {
-        let HDLC_CONFIG: hdlc::SpecialChars = hdlc::SpecialChars::new_custom(
-            0x7e,
-            0x7d,
-            HashMap::from([(0x7e, 0x5e), (0x7d, 0x5d), (0x11, 0x31), (0x13, 0x33)]),
-        );
 
-        let mut reader = hdlc::FrameReader::new(&mut self.port, HDLC_CONFIG.clone());
+    fn read_device_information(&mut self, kind: u8) -> Option<DeviceInfo> {
+        // strings are at most 32 bytes, including the terminator
+        let data = self
+            .exchange(Command::DeviceInformation, &[kind], 32)
+            .ok()?
+            .1;
+        debug!("Data received: {:?}", &data[..]);
 
-        let frame: Vec<u8>;
-        loop {
-            let f = reader.read_frame();
-            if f != None {
-                frame = f.unwrap();
-                break;
-            }
-        }
+        let s = str::from_utf8(&data).ok()?;
+        debug!("Data content: {:?}", s);
 
-        let mut d = hdlc::decode(&frame, HDLC_CONFIG.clone()).unwrap();
-        // println!("Packet read: {:#x?}", d);
+        // strings are null terminated on the wire
+        device_info(s.trim_end_matches('\0'))
+    }
 
-        let c = d.pop().unwrap();
-        if c != checksum(d.clone()) {
-            info!("Checksum error!");
-            return Err(FrameError {});
-        }
+    /// Product type string, "00080000" for the SPS30.
+    pub fn get_device_info(&mut self) -> Option<DeviceInfo> {
+        info!("Get Device Info command");
+        self.read_device_information(0x00)
+    }
 
-        let addr = d.remove(0);
-        let cmd = d.remove(0).try_into().unwrap();
-        let state = d.remove(0);
-        let l = d.remove(0);
-        if d.len() as u8 != l {
-            info!("Packet read: l({}) != d.len({})", l, d.len());
-            return Err(FrameError {});
+    /// What the device is, from its product type. Fails if it doesn't answer
+    /// with one at all.
+    pub fn identify(&mut self) -> Result<DeviceModel, DeviceError> {
+        self.get_device_info()
+            .map(|t| DeviceModel::from_product_type(&t))
+            .ok_or(DeviceError::default())
+    }
+
+    pub fn read_serial_number(&mut self) -> Option<DeviceInfo> {
+        info!("Read serial number");
+        let serial = self.read_device_information(0x03)?;
+        self.serial = Some(serial.clone());
+        Some(serial)
+    }
+
+    pub fn read_version(&mut self) -> Result<Sps30Version, DeviceError> {
+        info!("Read version");
+
+        // later firmware may append fields, which are ignored
+        let (status, data) = self.exchange(Command::ReadVersion, &[], shdlc::MAX_DATA)?;
+        debug!("Status: {}", status);
+
+        let version = Sps30Version::from_bytes(&data)?;
+        if !version.shdlc.is_supported() {
+            match self.protocol_check {
+                ProtocolCheck::Ignore => (),
+                ProtocolCheck::Warn => warn!("Unsupported SHDLC version {}", version.shdlc),
+                ProtocolCheck::Strict => {
+                    warn!("Refusing SHDLC version {}", version.shdlc);
+                    return Err(DeviceError::default());
+                }
+            }
         }
+        self.version = Some(version);
+        Ok(version)
+    }
 
-        Ok((
-            state,
-            Frame {
-                addr,
-                cmd,
-                data: d.clone(),
-            },
-        ))
+    /// The version from the last successful [`Sps30::read_version`] or
+    /// [`Sps30::device_identity`], without asking the device again.
+    pub fn cached_version(&self) -> Option<Sps30Version> {
+        self.version
     }
 
-    pub fn get_device_info(&mut self) -> Option<String> {
-        info!("Get Device Info command");
+    /// The serial number from the last successful
+    /// [`Sps30::read_serial_number`] or [`Sps30::device_identity`], without
+    /// asking the device again.
+    pub fn cached_serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
 
-        let f = Frame {
-            addr: 0x0,
-            cmd: Command::DeviceInformation,
-            data: vec![0x0],
-        };
-        self.send_frame(f).unwrap();
+    /// How [`Sps30::read_version`] treats an SHDLC protocol version this
+    /// crate doesn't implement. Warns by default.
+    pub fn set_protocol_check(&mut self, check: ProtocolCheck) {
+        self.protocol_check = check;
+    }
 
-        let d = self.receive_frame();
-        info!("Data recevied: {:#x?}", d);
+    /// Whether the firmware is at least `required` for `feature`, going by
+    /// the cached version. Passes when no version has been read, or when
+    /// checks are skipped with [`Sps30::set_skip_firmware_checks`].
+    pub fn check_firmware(
+        &self,
+        feature: &'static str,
+        required: FirmwareVersion,
+    ) -> Result<(), UnsupportedByFirmware> {
+        match self.version {
+            Some(v) if !self.skip_firmware_checks && v.firmware < required => {
+                Err(UnsupportedByFirmware {
+                    feature,
+                    required,
+                    actual: v.firmware,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
 
-        let data = d.unwrap().1.data;
-        let s = str::from_utf8(&data).unwrap();
-        info!("Data content: {:?}", s);
+    /// Send commands even when the cached firmware version is too old for
+    /// them, e.g. for beta firmware that reports an old version. Off by
+    /// default.
+    pub fn set_skip_firmware_checks(&mut self, skip: bool) {
+        self.skip_firmware_checks = skip;
+    }
 
-        let s = s.to_string();
+    fn require_firmware(
+        &self,
+        feature: &'static str,
+        required: FirmwareVersion,
+    ) -> Result<(), DeviceError> {
+        self.check_firmware(feature, required).map_err(|e| {
+            warn!("{}", e);
+            e.into()
+        })
+    }
 
-        Some(s)
+    /// Drop what is known about the device, when it may have changed.
+    fn forget_device(&mut self) {
+        self.identity = None;
+        self.version = None;
+        self.serial = None;
     }
 
-    pub fn read_version(&mut self) -> Result<Sps30Version, DeviceError> {
-        info!("Read version");
+    /// Talk to the sensor through `port` from now on, e.g. after its adapter
+    /// was unplugged and came back, returning the old port. The cached
+    /// identity is dropped, as it may be a different sensor.
+    pub fn replace_port(&mut self, port: P) -> P {
+        self.forget_device();
+        self.link.replace_port(port)
+    }
 
-        let f = Frame {
-            addr: 0,
-            cmd: Command::ReadVersion,
-            data: Vec::new(),
+    /// Read the product type, serial number and versions in one go. The
+    /// result is kept for [`Sps30::cached_identity`].
+    pub fn device_identity(&mut self) -> Result<DeviceIdentity, IdentityError> {
+        let product_type = self.get_device_info().ok_or(IdentityError::ProductType)?;
+        let serial = self
+            .read_serial_number()
+            .ok_or(IdentityError::SerialNumber)?;
+        let version = self.read_version().map_err(|_| IdentityError::Version)?;
+        let identity = DeviceIdentity {
+            model: DeviceModel::from_product_type(&product_type),
+            product_type: product_type.as_str().into(),
+            serial: serial.as_str().into(),
+            version,
         };
+        self.identity = Some(identity.clone());
+        Ok(identity)
+    }
 
-        self.send_frame(f).unwrap();
-        let (status, frame) = self.receive_frame().unwrap();
-        if frame.data.len() != 7 {
-            info!("Wrong received data length: {}", frame.data.len());
-            return Err(DeviceError {});
-        }
-
-        let firmware_major = frame.data[0];
-        let firmware_minor = frame.data[1];
-        let hardware_rev = frame.data[3];
-        let sdlc_major = frame.data[5];
-        let sdlc_minor = frame.data[6];
-
-        let mut buf = [0u8; 32];
-        let firmware = String::from(
-            format_no_std::show(
-                &mut buf,
-                format_args!("{}.{}", firmware_major, firmware_minor),
-            )
-            .unwrap(),
-        );
-        let hardware =
-            String::from(format_no_std::show(&mut buf, format_args!("{}", hardware_rev)).unwrap());
-        let shdlc = String::from(
-            format_no_std::show(&mut buf, format_args!("{}.{}", sdlc_major, sdlc_minor)).unwrap(),
-        );
+    /// The identity read by the last [`Sps30::device_identity`].
+    pub fn cached_identity(&self) -> Option<&DeviceIdentity> {
+        self.identity.as_ref()
+    }
+    /// Send command `cmd` with `data`, returning the device state byte and the
+    /// response data as they are, for commands this driver doesn't implement.
+    /// An error code in the state byte doesn't fail the call. The driver
+    /// doesn't know what the command did, so sending ones it does implement,
+    /// such as starting measurement, this way leaves its idea of the sensor's
+    /// state behind.
+    pub fn send_raw(&mut self, cmd: u8, data: &[u8]) -> Result<(u8, Payload), DeviceError> {
+        info!("Send raw command {:x}", cmd);
 
-        info!("Firmware: {}.{}", firmware_major, firmware_minor);
-        info!("Hardware: {}", hardware_rev);
-        info!("SDLC: {}.{}", sdlc_major, sdlc_minor);
+        let (state, data) =
+            self.link
+                .transceive(self.addr, cmd, data, shdlc::MAX_DATA, self.response_timeout)?;
+        self.note_state(cmd, state);
 
-        Ok(Sps30Version {
-            firmware,
-            hardware,
-            shdlc,
-        })
+        Ok((state, data))
     }
     pub fn start_measurement(&mut self) -> Result<(), DeviceError> {
         info!("Start Device measurement");
         if self.running {
-            info!("Trying to start device when already running");
-            return Err(DeviceError {});
+            warn!("Trying to start device when already running");
+            return Err(DeviceError::default());
         }
 
-        let f = Frame {
-            addr: 0x0,
-            cmd: Command::StartMeasurement,
-            data: vec![0x01u8, 0x03], // ieee floating point
-        };
-        self.send_frame(f).unwrap();
-        let (status, frame) = self.receive_frame().unwrap();
+        // ieee floating point
+        self.transact(Command::StartMeasurement, &[0x01, 0x03], 0)?;
+
+        self.running = true;
+        self.readings = 0;
+        #[cfg(feature = "std")]
+        {
+            self.started = Some(Instant::now());
+        }
+        self.link.emit(Event::MeasurementStarted);
 
-        info!("Status: {:x}", status);
-        info!("Received frame: {:#x?}", frame);
+        Ok(())
+    }
+    pub fn stop_measurement(&mut self) -> Result<(), DeviceError> {
+        info!("Stop Device measurement");
 
-        if status != 0 {
-            info!("Status is not zero!");
-            return Err(DeviceError {});
+        self.transact(Command::StopMeasurement, &[], 0)?;
+
+        self.running = false;
+        #[cfg(feature = "std")]
+        {
+            self.started = None;
         }
+        self.link.emit(Event::MeasurementStopped);
+
+        Ok(())
+    }
+    /// Run the fan at maximum speed for 10 seconds to blow out dust. Only
+    /// accepted by the device while measuring.
+    pub fn start_fan_cleaning(&mut self) -> Result<(), DeviceError> {
+        info!("Start fan cleaning");
+
+        self.transact(Command::StartFanCleaning, &[], 0)?;
+
+        Ok(())
+    }
+    /// Auto cleaning interval in seconds, 0 when disabled.
+    pub fn read_auto_cleaning_interval(&mut self) -> Result<u32, DeviceError> {
+        info!("Read auto cleaning interval");
+
+        let data = self.transact(Command::RWAutoCleaningInterval, &[0x00], 4)?;
+        let data: [u8; 4] = data.as_slice().try_into().map_err(|_| {
+            warn!("Wrong received data length: {}", data.len());
+            DeviceError::default()
+        })?;
+
+        Ok(u32::from_be_bytes(data))
+    }
+    /// Set the auto cleaning interval in seconds, 0 disables it. The device
+    /// keeps reporting the previous value until it is reset or measurement is
+    /// restarted.
+    pub fn write_auto_cleaning_interval(&mut self, seconds: u32) -> Result<(), DeviceError> {
+        info!("Write auto cleaning interval: {}", seconds);
+
+        let mut data = [0u8; 5];
+        data[1..].copy_from_slice(&seconds.to_be_bytes());
+        self.transact(Command::RWAutoCleaningInterval, &data, 0)?;
+
+        Ok(())
+    }
+    /// Enter sleep mode. Only accepted by the device while idle.
+    pub fn sleep(&mut self) -> Result<(), DeviceError> {
+        info!("Sleep");
+        self.require_firmware("sleep", FirmwareVersion::SLEEP)?;
+
+        self.transact(Command::Sleep, &[], 0)?;
+        self.link.emit(Event::Asleep);
+
+        Ok(())
+    }
+    /// Leave sleep mode. The UART interface is powered down while sleeping,
+    /// so a single 0xFF byte is sent first to wake it up, followed by the
+    /// Wake-up command.
+    pub fn wake_up(&mut self) -> Result<(), DeviceError> {
+        info!("Wake up");
+        self.require_firmware("wake-up", FirmwareVersion::SLEEP)?;
+
+        self.link.write_raw(&[0xff])?;
+        self.transact(Command::WakeUp, &[], 0)?;
+        self.link.emit(Event::Awake);
 
         Ok(())
     }
     pub fn device_reset(&mut self) -> Result<(), DeviceError> {
         info!("Sending Reset");
 
-        let f = Frame {
-            addr: 0x0,
-            cmd: Command::Reset,
-            data: Vec::new(),
-        };
-
-        self.send_frame(f).unwrap();
-        sleep(Duration::from_millis(100)); // we need to wait a bit after a reset. FIXME on no-std
+        self.link.send(self.addr, Command::Reset.into(), &[])?;
+        self.wait_ms(100); // we need to wait a bit after a reset
 
-        let (status, frame) = self.receive_frame().unwrap();
-        info!("Status: {}", status);
-        info!("Frame: {:#x?}", frame);
+        let (status, frame) = self.link.receive(self.addr, self.response_timeout)?;
+        debug!("Status: {}", status);
+        debug!("Frame data: {:?}", &frame.data[..]);
+        self.note_state(Command::Reset.into(), status);
 
+        // a reset may follow a firmware update
+        self.forget_device();
         self.running = false;
+        #[cfg(feature = "std")]
+        {
+            self.started = None;
+        }
+        self.link.emit(Event::Reset);
 
         Ok(())
     }
@@ -343,129 +797,1704 @@ impl<P: Write + Read> Sps30<P> {
     pub fn read_measurement(&mut self) -> Result<Option<Sps30Measurement>, DeviceError> {
         info!("Read Measurement");
 
-        let f = Frame {
-            addr: 0x0,
-            cmd: Command::ReadMeasuredValue,
-            data: Vec::new(),
-        };
-        self.send_frame(f).unwrap();
-        let (status, frame) = self.receive_frame().unwrap();
-        info!("Status: {}", status);
-        //println!("Frame: {:#x?}", frame);
-
-        let mut mass_1_0 = 0_f32;
-        let mut mass_2_5 = 0_f32;
-        let mut mass_4_0 = 0_f32;
-        let mut mass_10 = 0_f32;
-        let mut concentration_pm005 = 0_f32;
-        let mut concentration_pm010 = 0_f32;
-        let mut concentration_pm025 = 0_f32;
-        let mut concentration_pm040 = 0_f32;
-        let mut concentration_pm100 = 0_f32;
-        let mut particle = 0_f32;
-        if frame.data.len() > 0 {
-            mass_1_0 = slice_to_f32(&frame.data[0..4]);
-            mass_2_5 = slice_to_f32(&frame.data[4..8]);
-            mass_4_0 = slice_to_f32(&frame.data[8..12]);
-            mass_10 = slice_to_f32(&frame.data[12..16]);
-            concentration_pm005 = slice_to_f32(&frame.data[16..20]);
-            concentration_pm010 = slice_to_f32(&frame.data[20..24]);
-            concentration_pm025 = slice_to_f32(&frame.data[24..28]);
-            concentration_pm040 = slice_to_f32(&frame.data[28..32]);
-            concentration_pm100 = slice_to_f32(&frame.data[32..36]);
-            particle = slice_to_f32(&frame.data[36..40]);
-
-            info!("mass pm1.0: {} µg/m³", mass_1_0);
-            info!("mass pm2.5: {} µg/m³", mass_2_5);
-            info!("mass pm4.0: {} µg/m³", mass_4_0);
-            info!("mass pm10: {} µg/m³", mass_10);
-            info!("concentration pm0.5: {} #/cm³", concentration_pm005);
-            info!("concentration pm1.0: {} #/cm³", concentration_pm010);
-            info!("concentration pm2.5: {} #/cm³", concentration_pm025);
-            info!("concentration pm4.0: {} #/cm³", concentration_pm040);
-            info!("concentration pm10.0: {} #/cm³", concentration_pm100);
-            info!("Typical particle size: {} nm", particle);
-        } else {
-            info!("No data changed");
-            return Ok(None);
+        let (status, data) =
+            self.exchange(Command::ReadMeasuredValue, &[], Sps30Measurement::SIZE)?;
+        debug!("Status: {}", status);
+
+        let mut m = Sps30Measurement::from_bytes(&data)?;
+        if let Some(m) = &mut m {
+            self.readings = self.readings.saturating_add(1);
+            self.since_check = self.since_check.saturating_add(1);
+            if self.clamp_negative && m.clamp_negative() {
+                debug!("Clamped negative values to 0");
+            }
         }
+        if m.is_some() && self.health_check_due() {
+            self.check_health();
+        }
+        Ok(m)
+    }
 
-        Ok(Some(Sps30Measurement {
-            mass_1_0,
-            mass_2_5,
-            mass_4_0,
-            mass_10,
-            concentration_pm005,
-            concentration_pm010,
-            concentration_pm025,
-            concentration_pm040,
-            concentration_pm100,
-            particle,
+    /// Like [`Sps30::read_measurement`], with the reading stamped by `clock`
+    /// as soon as it has been received.
+    pub fn read_measurement_timestamped<C: Clock>(
+        &mut self,
+        clock: &C,
+    ) -> Result<Option<Timestamped<C::Timestamp>>, DeviceError> {
+        let m = self.read_measurement()?;
+        let timestamp = clock.now();
+        Ok(m.map(|measurement| Timestamped {
+            timestamp,
+            measurement,
         }))
     }
 
-    pub fn read_device_status(&mut self) -> Result<Option<Vec<Sps30Fault>>, DeviceError> {
-        info!("Reading device status");
+    /// How long ago measurement was started, or None if it isn't running.
+    #[cfg(feature = "std")]
+    pub fn measuring_for(&self) -> Option<Duration> {
+        self.started.map(|t| t.elapsed())
+    }
 
-        let f = Frame {
-            addr: 0x0,
-            cmd: Command::ReadDeviceStatusRegister,
-            data: vec![0x01], // clear register after reading
-        };
+    /// Whether measurement started less than [`WARM_UP`] ago, so readings
+    /// may still be zero or too low rather than showing clean air.
+    #[cfg(feature = "std")]
+    pub fn is_warming_up(&self) -> bool {
+        self.measuring_for().is_some_and(|t| t < WARM_UP)
+    }
+    /// Whether fewer new readings than [`WARM_UP`] has seconds have come in
+    /// since measurement started, so readings may still be zero or too low
+    /// rather than showing clean air. The sensor produces one reading a
+    /// second, and there is no clock to go by on no_std.
+    #[cfg(feature = "no_std")]
+    pub fn is_warming_up(&self) -> bool {
+        self.running && (self.readings as u64) < WARM_UP.as_secs()
+    }
 
-        self.send_frame(f).unwrap();
-        let (status, frame) = self.receive_frame().unwrap();
+    fn read_status_register(&mut self, clear: bool) -> Result<u32, DeviceError> {
+        self.require_firmware("device status register", FirmwareVersion::STATUS_REGISTER)?;
+        let (status, data) = self.exchange(Command::ReadDeviceStatusRegister, &[clear as u8], 5)?;
 
-        if frame.data.len() != 5 {
-            info!("wrong frame size read: {}", frame.data.len());
-            return Err(DeviceError {});
+        debug!("Status: {}", status);
+
+        status_register(&data)
+    }
+
+    /// Read the device status register and clear it (subcommand 0x01), so
+    /// faults that have gone away stop being reported. Anything else checking
+    /// the status afterwards sees a clean register; use
+    /// [`Sps30::peek_device_status`] to leave it as it is.
+    pub fn read_and_clear_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        info!("Reading and clearing device status");
+        let status = DeviceStatus::new(self.read_status_register(true)?, true);
+        if !status.is_healthy() {
+            self.notify_fault(&status);
+        }
+        Ok(status)
+    }
+
+    /// Read the device status register, leaving any flagged faults set
+    /// (subcommand 0x00).
+    pub fn peek_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        info!("Peeking device status");
+        let status = DeviceStatus::new(self.read_status_register(false)?, false);
+        if !status.is_healthy() {
+            self.notify_fault(&status);
         }
-        let fan_err = to_bool(frame.data[3] & (1 << 4));
-        let laser_err = to_bool(frame.data[3] & (1 << 5));
-        let speed_err = to_bool(frame.data[1] & (1 << 5));
+        Ok(status)
+    }
+
+    /// Read the device status register and clear it.
+    #[deprecated(
+        since = "0.2.0",
+        note = "use read_and_clear_device_status, or peek_device_status to leave the register set"
+    )]
+    pub fn read_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        self.read_and_clear_device_status()
+    }
+
+    /// Read the whole device status register and clear it.
+    #[deprecated(since = "0.2.0", note = "use read_and_clear_device_status().raw()")]
+    pub fn read_device_status_raw(&mut self) -> Result<u32, DeviceError> {
+        self.read_and_clear_device_status().map(|s| s.raw())
+    }
+
+    /// Read the whole device status register, leaving any flagged faults
+    /// set.
+    #[deprecated(since = "0.2.0", note = "use peek_device_status().raw()")]
+    pub fn peek_device_status_raw(&mut self) -> Result<u32, DeviceError> {
+        self.peek_device_status().map(|s| s.raw())
+    }
+}
+
+/// Product type string reported by the SPS30.
+pub const PRODUCT_TYPE: &str = "00080000";
+
+/// What a device is, going by the product type it reports.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceModel {
+    Sps30,
+    /// Some other product type, as reported.
+    Unknown(String),
+}
 
-        let mut faults = Vec::new();
-        if fan_err {
-            faults.push(Sps30Fault::Fan);
+impl DeviceModel {
+    pub fn from_product_type(product_type: &str) -> Self {
+        match product_type {
+            PRODUCT_TYPE => DeviceModel::Sps30,
+            t => DeviceModel::Unknown(t.to_string()),
         }
-        if laser_err {
-            faults.push(Sps30Fault::Laser);
+    }
+}
+
+impl fmt::Display for DeviceModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceModel::Sps30 => f.write_str("SPS30"),
+            DeviceModel::Unknown(t) => write!(f, "unknown device, product type {:?}", t),
         }
-        if speed_err {
-            faults.push(Sps30Fault::FanSpeed);
+    }
+}
+
+/// Everything that labels a sensor, as read by [`Sps30::device_identity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub model: DeviceModel,
+    pub product_type: String,
+    pub serial: String,
+    pub version: Sps30Version,
+}
+
+/// Which read [`Sps30::device_identity`] failed at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityError {
+    ProductType,
+    SerialNumber,
+    Version,
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            Self::ProductType => "product type",
+            Self::SerialNumber => "serial number",
+            Self::Version => "version",
+        };
+        write!(f, "can't read the {}", what)
+    }
+}
+
+impl From<IdentityError> for DeviceError {
+    fn from(_: IdentityError) -> Self {
+        DeviceError::default()
+    }
+}
+
+/// Longest a single read from a [`PortTimeout`] port is left to block, so the
+/// response timeout is checked this often.
+#[cfg(feature = "std")]
+const PORT_POLL: Duration = Duration::from_millis(20);
+
+#[cfg(feature = "std")]
+impl<P: Transport + PortTimeout> Sps30<P> {
+    /// Set the response timeout, see [`Sps30::set_response_timeout`], and the
+    /// port's read timeout to match, so there's one number to configure.
+    ///
+    /// The port's timeout only bounds a single read, after which the driver
+    /// checks its deadline and reads again; an empty read isn't an error. It
+    /// is set to `timeout` or 20 ms, whichever is shorter, so a response
+    /// times out within 20 ms of `timeout`. A port timeout shorter than the
+    /// deadline only means more reads, never a spurious timeout.
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), DeviceError> {
+        self.link
+            .port_mut()
+            .set_port_timeout(timeout.min(PORT_POLL))
+            .map_err(|_| DeviceError::default())?;
+        self.link.set_reads_time_out(true);
+        self.set_response_timeout(Some(timeout));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serialport")]
+impl Sps30<std::boxed::Box<dyn serialport::SerialPort>> {
+    /// Open the sensor on the serial port at `path` with its fixed 115200 8N1
+    /// settings, discarding anything already waiting in the input buffer.
+    /// Responses time out after a second.
+    pub fn open(path: &str) -> Result<Self, DeviceError> {
+        info!("Opening {}", path);
+        let p = serialport::new(path, 115200)
+            .data_bits(serialport::DataBits::Eight)
+            .parity(serialport::Parity::None)
+            .stop_bits(serialport::StopBits::One)
+            .timeout(PORT_POLL)
+            .open()
+            .map_err(|e| {
+                warn!("Can't open {}: {}", path, e);
+                DeviceError::default()
+            })?;
+        p.clear(serialport::ClearBuffer::Input).map_err(|e| {
+            warn!("Can't clear input on {}: {}", path, e);
+            DeviceError::default()
+        })?;
+
+        let mut sensor = Self::new(p);
+        sensor.set_timeout(Duration::from_secs(1))?;
+        Ok(sensor)
+    }
+
+    /// Like [`Sps30::open`], but also checks that the device answers with the
+    /// SPS30's product type and a protocol version this crate implements.
+    pub fn open_probed(path: &str) -> Result<Self, DeviceError> {
+        Self::open_probed_with(path, ProtocolCheck::default())
+    }
+
+    /// Like [`Sps30::open_probed`], treating the protocol version as `check`
+    /// says.
+    pub fn open_probed_with(path: &str, check: ProtocolCheck) -> Result<Self, DeviceError> {
+        let mut sensor = Self::open(path)?;
+        sensor.set_protocol_check(check);
+        match sensor.identify() {
+            Ok(DeviceModel::Sps30) => sensor.read_version().map(|_| sensor),
+            Ok(m) => {
+                warn!("No SPS30 on {}, {}", path, m);
+                Err(DeviceError::default())
+            }
+            Err(e) => {
+                warn!("No answer on {}", path);
+                Err(e)
+            }
         }
-        if faults.len() > 0 {
-            Ok(Some(faults))
-        } else {
-            Ok(None)
+    }
+}
+
+/// The sensor's operations, whichever interface it is connected through, so
+/// applications can hold a `Box<dyn Sps30Interface>` or be generic over it
+/// rather than over the UART and I2C drivers.
+pub trait Sps30Interface {
+    /// Product type string, "00080000" for the SPS30.
+    fn get_device_info(&mut self) -> Option<DeviceInfo>;
+    /// What the device is, from its product type.
+    fn identify(&mut self) -> Result<DeviceModel, DeviceError> {
+        self.get_device_info()
+            .map(|t| DeviceModel::from_product_type(&t))
+            .ok_or(DeviceError::default())
+    }
+    fn read_serial_number(&mut self) -> Option<DeviceInfo>;
+    /// Firmware major and minor version.
+    fn read_firmware_version(&mut self) -> Result<FirmwareVersion, DeviceError>;
+    fn start_measurement(&mut self) -> Result<(), DeviceError>;
+    fn stop_measurement(&mut self) -> Result<(), DeviceError>;
+    /// The latest measurement, or None if there's nothing new.
+    fn read_measurement(&mut self) -> Result<Option<Sps30Measurement>, DeviceError>;
+    fn start_fan_cleaning(&mut self) -> Result<(), DeviceError>;
+    fn read_auto_cleaning_interval(&mut self) -> Result<u32, DeviceError>;
+    fn write_auto_cleaning_interval(&mut self, seconds: u32) -> Result<(), DeviceError>;
+    fn sleep(&mut self) -> Result<(), DeviceError>;
+    fn wake_up(&mut self) -> Result<(), DeviceError>;
+    fn device_reset(&mut self) -> Result<(), DeviceError>;
+    /// Read the device status register and clear it.
+    fn read_and_clear_device_status(&mut self) -> Result<DeviceStatus, DeviceError>;
+    /// Read the device status register, leaving any flagged faults set.
+    fn peek_device_status(&mut self) -> Result<DeviceStatus, DeviceError>;
+    /// Read the device status register and clear it.
+    #[deprecated(
+        since = "0.2.0",
+        note = "use read_and_clear_device_status, or peek_device_status to leave the register set"
+    )]
+    fn read_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        self.read_and_clear_device_status()
+    }
+}
+
+impl<P: Transport> Sps30Interface for Sps30<P> {
+    fn get_device_info(&mut self) -> Option<DeviceInfo> {
+        Sps30::get_device_info(self)
+    }
+    fn read_serial_number(&mut self) -> Option<DeviceInfo> {
+        Sps30::read_serial_number(self)
+    }
+    fn read_firmware_version(&mut self) -> Result<FirmwareVersion, DeviceError> {
+        let data = self.transact(Command::ReadVersion, &[], shdlc::MAX_DATA)?;
+        match data[..] {
+            [major, minor, ..] => Ok(FirmwareVersion::new(major, minor)),
+            _ => Err(DeviceError::default()),
         }
     }
+    fn start_measurement(&mut self) -> Result<(), DeviceError> {
+        Sps30::start_measurement(self)
+    }
+    fn stop_measurement(&mut self) -> Result<(), DeviceError> {
+        Sps30::stop_measurement(self)
+    }
+    fn read_measurement(&mut self) -> Result<Option<Sps30Measurement>, DeviceError> {
+        Sps30::read_measurement(self)
+    }
+    fn start_fan_cleaning(&mut self) -> Result<(), DeviceError> {
+        Sps30::start_fan_cleaning(self)
+    }
+    fn read_auto_cleaning_interval(&mut self) -> Result<u32, DeviceError> {
+        Sps30::read_auto_cleaning_interval(self)
+    }
+    fn write_auto_cleaning_interval(&mut self, seconds: u32) -> Result<(), DeviceError> {
+        Sps30::write_auto_cleaning_interval(self, seconds)
+    }
+    fn sleep(&mut self) -> Result<(), DeviceError> {
+        Sps30::sleep(self)
+    }
+    fn wake_up(&mut self) -> Result<(), DeviceError> {
+        Sps30::wake_up(self)
+    }
+    fn device_reset(&mut self) -> Result<(), DeviceError> {
+        Sps30::device_reset(self)
+    }
+    fn read_and_clear_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        Sps30::read_and_clear_device_status(self)
+    }
+    fn peek_device_status(&mut self) -> Result<DeviceStatus, DeviceError> {
+        Sps30::peek_device_status(self)
+    }
 }
 
-#[derive(Debug)]
+/// A condition flagged in the device status register. New firmware may
+/// flag more, so matches need a catch-all arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "no_std", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Sps30Fault {
-    Fan,
-    Laser,
-    FanSpeed,
+    /// Bit 21, a warning: the fan is running but too fast or too slow, e.g.
+    /// just after cleaning. Clears itself once the speed recovers.
+    FanSpeedOutOfRange,
+    /// Bit 4, an error: the fan is switched on but measures 0 RPM.
+    FanFailure,
+    /// Bit 5, an error: the laser current is out of range.
+    LaserFailure,
+    /// Set bits this crate doesn't recognise, with the known ones masked out.
+    Unknown(u32),
 }
 
-#[derive(Debug)]
-pub struct Sps30Measurement {
-    mass_1_0: f32,
-    mass_2_5: f32,
-    mass_4_0: f32,
-    mass_10: f32,
-    concentration_pm005: f32,
-    concentration_pm010: f32,
-    concentration_pm025: f32,
-    concentration_pm040: f32,
-    concentration_pm100: f32,
-    particle: f32,
+/// How serious a fault is, as classified in the datasheet. Ordered, so the
+/// worst of several faults is their `max()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "no_std", derive(defmt::Format))]
+pub enum Severity {
+    /// The sensor still measures, but readings may be off for a while.
+    Warning,
+    /// The sensor can't measure until it is repaired.
+    Error,
 }
 
-#[derive(Debug)]
-pub struct Sps30Version {
-    firmware: String,
-    hardware: String,
-    shdlc: String,
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+impl fmt::Display for Sps30Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sps30Fault::FanSpeedOutOfRange => f.write_str("fan speed out of range"),
+            Sps30Fault::FanFailure => f.write_str("fan failure"),
+            Sps30Fault::LaserFailure => f.write_str("laser failure"),
+            Sps30Fault::Unknown(bits) => write!(f, "unknown status bits {:#010x}", bits),
+        }
+    }
+}
+
+impl Sps30Fault {
+    /// The datasheet's classification of the fault. Bits it doesn't document
+    /// are reported as warnings.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Sps30Fault::FanSpeedOutOfRange => Severity::Warning,
+            Sps30Fault::FanFailure | Sps30Fault::LaserFailure => Severity::Error,
+            Sps30Fault::Unknown(_) => Severity::Warning,
+        }
+    }
+    /// A stable identifier for the fault, for machine-readable output. Unlike
+    /// the `Display` text it won't be reworded, and unknown bits all share
+    /// one name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sps30Fault::FanSpeedOutOfRange => "fan_speed_out_of_range",
+            Sps30Fault::FanFailure => "fan_failure",
+            Sps30Fault::LaserFailure => "laser_failure",
+            Sps30Fault::Unknown(_) => "unknown",
+        }
+    }
+}
+
+/// The register value from a Read Device Status Register payload: a
+/// big-endian u32 followed by a reserved byte, which is ignored.
+fn status_register(data: &[u8]) -> Result<u32, DeviceError> {
+    match *data {
+        [a, b, c, d, _reserved] => Ok(u32::from_be_bytes([a, b, c, d])),
+        _ => {
+            warn!("wrong frame size read: {}", data.len());
+            Err(DeviceError::default())
+        }
+    }
+}
+
+/// The device status register's value and what it flags. Decoding is done
+/// on the fly from the raw bits, so this is `Copy` and needs no allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "no_std", derive(defmt::Format))]
+pub struct DeviceStatus {
+    raw: u32,
+    cleared: bool,
+}
+
+impl DeviceStatus {
+    const SPEED_WARNING: u32 = 1 << 21;
+    const FAN_ERROR: u32 = 1 << 4;
+    const LASER_ERROR: u32 = 1 << 5;
+    const KNOWN: u32 = Self::SPEED_WARNING | Self::FAN_ERROR | Self::LASER_ERROR;
+
+    /// Wrap a register value, `cleared` saying whether reading it also
+    /// cleared the register on the device.
+    pub const fn new(raw: u32, cleared: bool) -> Self {
+        Self { raw, cleared }
+    }
+
+    /// Decode a Read Device Status Register payload.
+    pub fn from_payload(data: &[u8], cleared: bool) -> Result<Self, DeviceError> {
+        status_register(data).map(|raw| Self::new(raw, cleared))
+    }
+
+    /// The whole register, including bits this crate doesn't know about.
+    pub const fn raw(&self) -> u32 {
+        self.raw
+    }
+
+    /// Whether the read that returned this status also cleared the register.
+    pub const fn was_cleared(&self) -> bool {
+        self.cleared
+    }
+
+    /// Whether no bits at all are set.
+    pub const fn is_healthy(&self) -> bool {
+        self.raw == 0
+    }
+
+    /// The worst severity of the flagged faults, None if there are none.
+    pub fn severity(&self) -> Option<Severity> {
+        self.faults().map(|f| f.severity()).max()
+    }
+
+    /// Whether any flagged fault is an error, e.g. the fan or laser failing.
+    pub fn has_errors(&self) -> bool {
+        self.faults().any(|f| f.severity() == Severity::Error)
+    }
+
+    /// Whether any flagged fault is a warning, e.g. the fan speed being out
+    /// of range.
+    pub fn has_warnings(&self) -> bool {
+        self.faults().any(|f| f.severity() == Severity::Warning)
+    }
+
+    /// The flagged faults, warnings first, followed by an
+    /// [`Sps30Fault::Unknown`] for any unrecognised bits.
+    pub fn faults(&self) -> Faults {
+        Faults {
+            remaining: self.raw,
+        }
+    }
+}
+
+/// "OK", or the flagged faults separated by commas.
+impl fmt::Display for DeviceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_healthy() {
+            return f.write_str("OK");
+        }
+        for (i, fault) in self.faults().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", fault)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the faults in a [`DeviceStatus`].
+#[derive(Debug, Clone)]
+pub struct Faults {
+    remaining: u32,
+}
+
+impl Iterator for Faults {
+    type Item = Sps30Fault;
+
+    fn next(&mut self) -> Option<Sps30Fault> {
+        let known = [
+            (DeviceStatus::SPEED_WARNING, Sps30Fault::FanSpeedOutOfRange),
+            (DeviceStatus::FAN_ERROR, Sps30Fault::FanFailure),
+            (DeviceStatus::LASER_ERROR, Sps30Fault::LaserFailure),
+        ];
+        for (bit, fault) in known {
+            if self.remaining & bit != 0 {
+                self.remaining &= !bit;
+                return Some(fault);
+            }
+        }
+        match self.remaining & !DeviceStatus::KNOWN {
+            0 => None,
+            unknown => {
+                self.remaining = 0;
+                Some(Sps30Fault::Unknown(unknown))
+            }
+        }
+    }
+}
+
+/// One reading. With the serde feature it serializes as a flat record with
+/// the fields in declaration order, so it can be written as a CSV row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Sps30Measurement {
+    pub mass_1_0: f32,
+    pub mass_2_5: f32,
+    pub mass_4_0: f32,
+    pub mass_10: f32,
+    #[cfg_attr(feature = "serde", serde(alias = "concentration_pm005"))]
+    pub number_pm0_5: f32,
+    #[cfg_attr(feature = "serde", serde(alias = "concentration_pm010"))]
+    pub number_pm1_0: f32,
+    #[cfg_attr(feature = "serde", serde(alias = "concentration_pm025"))]
+    pub number_pm2_5: f32,
+    #[cfg_attr(feature = "serde", serde(alias = "concentration_pm040"))]
+    pub number_pm4_0: f32,
+    #[cfg_attr(feature = "serde", serde(alias = "concentration_pm100"))]
+    pub number_pm10: f32,
+    /// Typical particle size, in µm. Called `particle` before 0.2.
+    #[cfg_attr(feature = "serde", serde(alias = "particle"))]
+    pub typical_particle_size_um: f32,
+}
+
+impl Sps30Measurement {
+    /// Size of a measurement in the sensor's IEEE754 format.
+    pub const SIZE: usize = 40;
+
+    /// Compact encoding in the sensor's own Read Measured Values layout, ten
+    /// big-endian f32s in field order:
+    ///
+    /// | offset | field                    |
+    /// |--------|--------------------------|
+    /// | 0      | mass_1_0                 |
+    /// | 4      | mass_2_5                 |
+    /// | 8      | mass_4_0                 |
+    /// | 12     | mass_10                  |
+    /// | 16     | number_pm0_5             |
+    /// | 20     | number_pm1_0             |
+    /// | 24     | number_pm2_5             |
+    /// | 28     | number_pm4_0             |
+    /// | 32     | number_pm10              |
+    /// | 36     | typical_particle_size_um |
+    ///
+    /// Needs no allocation, and [`Sps30Measurement::from_bytes`] reads it
+    /// back.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut out = [0u8; Self::SIZE];
+        for (chunk, v) in out.chunks_exact_mut(4).zip(self.as_array()) {
+            chunk.copy_from_slice(&v.to_be_bytes());
+        }
+        out
+    }
+
+    /// Parse a Read Measured Values payload in IEEE754 float format. An empty
+    /// payload means no new measurement is available. NaN or infinite values,
+    /// which the sensor never sends, are rejected as a corrupted payload.
+    pub fn from_bytes(data: &[u8]) -> Result<Option<Self>, DeviceError> {
+        if data.is_empty() {
+            debug!("No data changed");
+            return Ok(None);
+        }
+        let m = Self::try_from(data).inspect_err(|e| match e {
+            MeasurementParseError::Length(n) => warn!("Wrong received data length: {}", n),
+            MeasurementParseError::NotFinite(i) => warn!("Non-finite value at offset {}", i),
+        })?;
+
+        debug!("mass pm1.0: {} µg/m³", m.mass_1_0);
+        debug!("mass pm2.5: {} µg/m³", m.mass_2_5);
+        debug!("mass pm4.0: {} µg/m³", m.mass_4_0);
+        debug!("mass pm10: {} µg/m³", m.mass_10);
+        debug!("number concentration pm0.5: {} #/cm³", m.number_pm0_5);
+        debug!("number concentration pm1.0: {} #/cm³", m.number_pm1_0);
+        debug!("number concentration pm2.5: {} #/cm³", m.number_pm2_5);
+        debug!("number concentration pm4.0: {} #/cm³", m.number_pm4_0);
+        debug!("number concentration pm10.0: {} #/cm³", m.number_pm10);
+        debug!("Typical particle size: {} µm", m.typical_particle_size_um);
+
+        Ok(Some(m))
+    }
+
+    /// Set negative values, which some firmware versions report for mass
+    /// concentrations close to zero, to 0. Returns whether any were.
+    pub fn clamp_negative(&mut self) -> bool {
+        let mut clamped = false;
+        for v in [
+            &mut self.mass_1_0,
+            &mut self.mass_2_5,
+            &mut self.mass_4_0,
+            &mut self.mass_10,
+            &mut self.number_pm0_5,
+            &mut self.number_pm1_0,
+            &mut self.number_pm2_5,
+            &mut self.number_pm4_0,
+            &mut self.number_pm10,
+            &mut self.typical_particle_size_um,
+        ] {
+            if *v < 0.0 {
+                *v = 0.0;
+                clamped = true;
+            }
+        }
+        clamped
+    }
+
+    /// A reading with every field 0, to fold a sum from.
+    pub const fn zeros() -> Self {
+        Self::from_array([0.0; 10])
+    }
+
+    /// The fields in declaration order, as listed under
+    /// [`Sps30Measurement::to_bytes`].
+    pub fn as_array(&self) -> [f32; 10] {
+        [
+            self.mass_1_0,
+            self.mass_2_5,
+            self.mass_4_0,
+            self.mass_10,
+            self.number_pm0_5,
+            self.number_pm1_0,
+            self.number_pm2_5,
+            self.number_pm4_0,
+            self.number_pm10,
+            self.typical_particle_size_um,
+        ]
+    }
+
+    pub(crate) const fn from_array(f: [f32; 10]) -> Self {
+        Self {
+            mass_1_0: f[0],
+            mass_2_5: f[1],
+            mass_4_0: f[2],
+            mass_10: f[3],
+            number_pm0_5: f[4],
+            number_pm1_0: f[5],
+            number_pm2_5: f[6],
+            number_pm4_0: f[7],
+            number_pm10: f[8],
+            typical_particle_size_um: f[9],
+        }
+    }
+
+    /// Typical particle size, in µm.
+    #[deprecated(since = "0.2.0", note = "use the typical_particle_size_um field")]
+    pub fn particle(&self) -> f32 {
+        self.typical_particle_size_um
+    }
+
+    #[deprecated(since = "0.2.0", note = "use the number_pm0_5 field")]
+    pub fn concentration_pm005(&self) -> f32 {
+        self.number_pm0_5
+    }
+
+    #[deprecated(since = "0.2.0", note = "use the number_pm1_0 field")]
+    pub fn concentration_pm010(&self) -> f32 {
+        self.number_pm1_0
+    }
+
+    #[deprecated(since = "0.2.0", note = "use the number_pm2_5 field")]
+    pub fn concentration_pm025(&self) -> f32 {
+        self.number_pm2_5
+    }
+
+    #[deprecated(since = "0.2.0", note = "use the number_pm4_0 field")]
+    pub fn concentration_pm040(&self) -> f32 {
+        self.number_pm4_0
+    }
+
+    #[deprecated(since = "0.2.0", note = "use the number_pm10 field")]
+    pub fn concentration_pm100(&self) -> f32 {
+        self.number_pm10
+    }
+
+    /// Number concentrations of each size range on its own, from the
+    /// cumulative ones the sensor reports.
+    pub fn number_bins(&self) -> NumberBins {
+        // rounding in the sensor can leave a larger bin a hair below the
+        // smaller one it includes
+        let diff = |hi: f32, lo: f32| (hi - lo).max(0.0);
+        NumberBins {
+            pm0_5_to_1_0: diff(self.number_pm1_0, self.number_pm0_5),
+            pm1_0_to_2_5: diff(self.number_pm2_5, self.number_pm1_0),
+            pm2_5_to_4_0: diff(self.number_pm4_0, self.number_pm2_5),
+            pm4_0_to_10: diff(self.number_pm10, self.number_pm4_0),
+        }
+    }
+
+    /// Every field with its name and unit, in declaration order, for sinks
+    /// that handle fields generically rather than naming each one. The names
+    /// match the serde field names.
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, f32, Unit)> {
+        self.named_fields().into_iter()
+    }
+
+    fn named_fields(&self) -> [(&'static str, f32, Unit); 10] {
+        let mass = Unit::MicrogramsPerCubicMeter;
+        let number = Unit::PerCubicCentimeter;
+        [
+            ("mass_1_0", self.mass_1_0, mass),
+            ("mass_2_5", self.mass_2_5, mass),
+            ("mass_4_0", self.mass_4_0, mass),
+            ("mass_10", self.mass_10, mass),
+            ("number_pm0_5", self.number_pm0_5, number),
+            ("number_pm1_0", self.number_pm1_0, number),
+            ("number_pm2_5", self.number_pm2_5, number),
+            ("number_pm4_0", self.number_pm4_0, number),
+            ("number_pm10", self.number_pm10, number),
+            (
+                "typical_particle_size_um",
+                self.typical_particle_size_um,
+                Unit::Micrometers,
+            ),
+        ]
+    }
+
+    /// Check the reading is something the sensor could have produced, to
+    /// catch corruption the checksum missed:
+    ///
+    /// - every value is finite
+    /// - mass concentrations are within the 0-1000 µg/m³ output range
+    /// - number concentrations aren't negative
+    /// - the typical particle size is within 0-3 µm, the top of the range the
+    ///   datasheet gives for it
+    /// - each size bin, which includes all smaller particles, is at least as
+    ///   large as the one below it, for both mass and number
+    ///
+    /// The first failing field is returned.
+    pub fn validate(&self) -> Result<(), MeasurementAnomaly> {
+        let fields = self.named_fields();
+        let anomaly = |(field, value, _): (&'static str, f32, Unit), kind| MeasurementAnomaly {
+            field,
+            value,
+            kind,
+        };
+
+        for (i, &f) in fields.iter().enumerate() {
+            let (_, value, _) = f;
+            if !value.is_finite() {
+                return Err(anomaly(f, AnomalyKind::NotFinite));
+            }
+            let in_range = match i {
+                0..=3 => (0.0..=1000.0).contains(&value),
+                4..=8 => value >= 0.0,
+                // the datasheet gives 0.3-3 µm for the typical particle
+                // size; 0 is what the sensor reports before its first reading
+                _ => (0.0..=3.0).contains(&value),
+            };
+            if !in_range {
+                return Err(anomaly(f, AnomalyKind::OutOfRange));
+            }
+        }
+        for bins in [&fields[0..4], &fields[4..9]] {
+            for pair in bins.windows(2) {
+                if pair[1].1 < pair[0].1 {
+                    return Err(anomaly(pair[1], AnomalyKind::NotCumulative));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<[f32; 10]> for Sps30Measurement {
+    /// Fields in declaration order, as from [`Sps30Measurement::as_array`].
+    fn from(f: [f32; 10]) -> Self {
+        Self::from_array(f)
+    }
+}
+
+/// Parses exactly [`Sps30Measurement::SIZE`] bytes in the sensor's own
+/// layout, as documented under [`Sps30Measurement::to_bytes`], so captured or
+/// relayed payloads can be decoded without a driver. Unlike
+/// [`Sps30Measurement::from_bytes`], an empty payload is an error.
+impl TryFrom<&[u8]> for Sps30Measurement {
+    type Error = MeasurementParseError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        if data.len() != Self::SIZE {
+            return Err(MeasurementParseError::Length(data.len()));
+        }
+        let mut f = [0f32; 10];
+        for (i, (v, chunk)) in f.iter_mut().zip(data.chunks_exact(4)).enumerate() {
+            *v = slice_to_f32(chunk);
+            if !v.is_finite() {
+                return Err(MeasurementParseError::NotFinite(i * 4));
+            }
+        }
+        Ok(Self::from_array(f))
+    }
+}
+
+/// Why a payload isn't a valid measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeasurementParseError {
+    /// Not [`Sps30Measurement::SIZE`] bytes long; holds the actual length.
+    Length(usize),
+    /// NaN or infinite value at this byte offset.
+    NotFinite(usize),
+}
+
+impl fmt::Display for MeasurementParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length(n) => write!(
+                f,
+                "measurement is {} bytes, expected {}",
+                n,
+                Sps30Measurement::SIZE
+            ),
+            Self::NotFinite(i) => write!(f, "non-finite value at offset {}", i),
+        }
+    }
+}
+
+impl From<MeasurementParseError> for DeviceError {
+    fn from(_: MeasurementParseError) -> Self {
+        DeviceError::default()
+    }
+}
+
+/// Differential number concentrations in #/cm³, as returned by
+/// [`Sps30Measurement::number_bins`]. Each covers particle sizes from the
+/// first size to the second in µm; together with `number_pm0_5` they
+/// add up to each cumulative concentration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct NumberBins {
+    pub pm0_5_to_1_0: f32,
+    pub pm1_0_to_2_5: f32,
+    pub pm2_5_to_4_0: f32,
+    pub pm4_0_to_10: f32,
+}
+
+/// Unit of a measurement field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// Mass concentration, µg/m³.
+    MicrogramsPerCubicMeter,
+    /// Number concentration, #/cm³.
+    PerCubicCentimeter,
+    /// Particle size, µm.
+    Micrometers,
+}
+
+impl Unit {
+    /// Symbol in UTF-8, e.g. `µg/m³`. Also what Display writes.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::MicrogramsPerCubicMeter => "µg/m³",
+            Unit::PerCubicCentimeter => "#/cm³",
+            Unit::Micrometers => "µm",
+        }
+    }
+
+    /// Symbol in plain ASCII, e.g. `ug/m3`, for CSV headers, metric names and
+    /// displays without the micro sign.
+    pub fn ascii_symbol(&self) -> &'static str {
+        match self {
+            Unit::MicrogramsPerCubicMeter => "ug/m3",
+            Unit::PerCubicCentimeter => "#/cm3",
+            Unit::Micrometers => "um",
+        }
+    }
+
+    /// Case sensitive UCUM code, e.g. `ug/m3`.
+    pub fn ucum(&self) -> &'static str {
+        match self {
+            Unit::MicrogramsPerCubicMeter => "ug/m3",
+            Unit::PerCubicCentimeter => "/cm3",
+            Unit::Micrometers => "um",
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// What [`Sps30Measurement::validate`] found wrong with a field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnomalyKind {
+    /// NaN or infinite.
+    NotFinite,
+    /// Outside the sensor's output range.
+    OutOfRange,
+    /// Smaller than the next smaller size bin, which it includes.
+    NotCumulative,
+}
+
+/// An implausible value in a reading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeasurementAnomaly {
+    pub field: &'static str,
+    pub value: f32,
+    pub kind: AnomalyKind,
+}
+
+impl fmt::Display for MeasurementAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            AnomalyKind::NotFinite => "not finite",
+            AnomalyKind::OutOfRange => "out of range",
+            AnomalyKind::NotCumulative => "smaller than the bin below it",
+        };
+        write!(f, "{} {}: {}", self.field, what, self.value)
+    }
+}
+
+/// Mass concentrations on one line, e.g.
+/// `PM1.0 1.2 PM2.5 3.4 PM4.0 4.1 PM10 4.5 µg/m³`, with the precision taken
+/// from the formatter or one decimal by default. Needs no allocation, so it
+/// can be written into a fixed buffer on no_std.
+impl fmt::Display for Sps30Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = f.precision().unwrap_or(1);
+        write!(
+            f,
+            "PM1.0 {:.*} PM2.5 {:.*} PM4.0 {:.*} PM10 {:.*} {}",
+            p,
+            self.mass_1_0,
+            p,
+            self.mass_2_5,
+            p,
+            self.mass_4_0,
+            p,
+            self.mass_10,
+            Unit::MicrogramsPerCubicMeter
+        )
+    }
+}
+
+// Element-wise arithmetic, so readings can be averaged with
+// `iter.fold(Sps30Measurement::zeros(), |a, b| a + b) / n as f32`.
+macro_rules! elementwise_op {
+    ($op:ident, $f:ident, $assign:ident, $assign_f:ident) => {
+        impl core::ops::$op for Sps30Measurement {
+            type Output = Self;
+            fn $f(self, rhs: Self) -> Self {
+                let mut a = self.as_array();
+                for (a, b) in a.iter_mut().zip(rhs.as_array()) {
+                    *a = core::ops::$op::$f(*a, b);
+                }
+                Self::from_array(a)
+            }
+        }
+
+        impl core::ops::$assign for Sps30Measurement {
+            fn $assign_f(&mut self, rhs: Self) {
+                *self = core::ops::$op::$f(*self, rhs);
+            }
+        }
+    };
+}
+
+macro_rules! scalar_op {
+    ($op:ident, $f:ident, $assign:ident, $assign_f:ident) => {
+        impl core::ops::$op<f32> for Sps30Measurement {
+            type Output = Self;
+            fn $f(self, rhs: f32) -> Self {
+                Self::from_array(self.as_array().map(|v| core::ops::$op::$f(v, rhs)))
+            }
+        }
+
+        impl core::ops::$assign<f32> for Sps30Measurement {
+            fn $assign_f(&mut self, rhs: f32) {
+                *self = core::ops::$op::$f(*self, rhs);
+            }
+        }
+    };
+}
+
+elementwise_op!(Add, add, AddAssign, add_assign);
+elementwise_op!(Sub, sub, SubAssign, sub_assign);
+scalar_op!(Mul, mul, MulAssign, mul_assign);
+scalar_op!(Div, div, DivAssign, div_assign);
+
+/// A measurement with the time it was taken, as returned by
+/// [`Sps30::read_measurement_timestamped`]. With the serde feature it
+/// serializes as one flat record with a leading `timestamp` column followed
+/// by the measurement's fields, which csv and similar formats without
+/// nesting can handle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "FlatTimestamped<T>"))]
+pub struct Timestamped<T> {
+    pub timestamp: T,
+    pub measurement: Sps30Measurement,
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Timestamped<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let m = &self.measurement;
+        let mut s = serializer.serialize_struct("Timestamped", 11)?;
+        s.serialize_field("timestamp", &self.timestamp)?;
+        for (name, v, _) in m.fields() {
+            s.serialize_field(name, &v)?;
+        }
+        s.end()
+    }
+}
+
+/// The measurement's schema with a required `timestamp` property added,
+/// matching the flat serialization.
+#[cfg(feature = "schemars")]
+impl<T: schemars::JsonSchema> schemars::JsonSchema for Timestamped<T> {
+    fn schema_name() -> String {
+        alloc::format!("Timestamped_{}", T::schema_name())
+    }
+    fn json_schema(generator: &mut schemars::r#gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = Sps30Measurement::json_schema(generator).into_object();
+        let object = schema.object();
+        object
+            .properties
+            .insert("timestamp".into(), generator.subschema_for::<T>());
+        object.required.insert("timestamp".into());
+        schemars::schema::Schema::Object(schema)
+    }
+}
+
+/// Flat shape [`Timestamped`] is read back through, since csv can't
+/// deserialize `#[serde(flatten)]`.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct FlatTimestamped<T> {
+    timestamp: T,
+    mass_1_0: f32,
+    mass_2_5: f32,
+    mass_4_0: f32,
+    mass_10: f32,
+    #[serde(alias = "concentration_pm005")]
+    number_pm0_5: f32,
+    #[serde(alias = "concentration_pm010")]
+    number_pm1_0: f32,
+    #[serde(alias = "concentration_pm025")]
+    number_pm2_5: f32,
+    #[serde(alias = "concentration_pm040")]
+    number_pm4_0: f32,
+    #[serde(alias = "concentration_pm100")]
+    number_pm10: f32,
+    #[serde(alias = "particle")]
+    typical_particle_size_um: f32,
+}
+
+#[cfg(feature = "serde")]
+impl<T> From<FlatTimestamped<T>> for Timestamped<T> {
+    fn from(f: FlatTimestamped<T>) -> Self {
+        Self {
+            timestamp: f.timestamp,
+            measurement: Sps30Measurement {
+                mass_1_0: f.mass_1_0,
+                mass_2_5: f.mass_2_5,
+                mass_4_0: f.mass_4_0,
+                mass_10: f.mass_10,
+                number_pm0_5: f.number_pm0_5,
+                number_pm1_0: f.number_pm1_0,
+                number_pm2_5: f.number_pm2_5,
+                number_pm4_0: f.number_pm4_0,
+                number_pm10: f.number_pm10,
+                typical_particle_size_um: f.typical_particle_size_um,
+            },
+        }
+    }
+}
+
+/// Define a `major.minor` version type, ordered by major then minor.
+macro_rules! version_type {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+        #[cfg_attr(feature = "no_std", derive(defmt::Format))]
+        pub struct $name {
+            pub major: u8,
+            pub minor: u8,
+        }
+
+        impl $name {
+            pub const fn new(major: u8, minor: u8) -> Self {
+                Self { major, minor }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}.{}", self.major, self.minor)
+            }
+        }
+    };
+}
+
+version_type!(
+    /// Firmware version, e.g. `if v.firmware >= FirmwareVersion::new(2, 2)`.
+    FirmwareVersion
+);
+version_type!(
+    /// SHDLC protocol version.
+    ProtocolVersion
+);
+
+impl ProtocolVersion {
+    /// SHDLC major versions whose frames and payloads this crate knows how
+    /// to parse.
+    pub const SUPPORTED_MAJOR: &[u8] = &[2];
+
+    /// Whether the major version is one in [`ProtocolVersion::SUPPORTED_MAJOR`].
+    pub fn is_supported(&self) -> bool {
+        Self::SUPPORTED_MAJOR.contains(&self.major)
+    }
+}
+
+/// A command the sensor's firmware is too old for, as found by
+/// [`Sps30::check_firmware`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "no_std", derive(defmt::Format))]
+pub struct UnsupportedByFirmware {
+    pub feature: &'static str,
+    pub required: FirmwareVersion,
+    pub actual: FirmwareVersion,
+}
+
+impl fmt::Display for UnsupportedByFirmware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} needs firmware {} or later, the sensor has {}",
+            self.feature, self.required, self.actual
+        )
+    }
+}
+
+impl From<UnsupportedByFirmware> for DeviceError {
+    fn from(e: UnsupportedByFirmware) -> Self {
+        DeviceError {
+            unsupported: Some(e),
+        }
+    }
+}
+
+/// What to do when the device reports an SHDLC protocol major version this
+/// crate doesn't implement, see [`Sps30::set_protocol_check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolCheck {
+    Ignore,
+    /// Log a warning and carry on.
+    #[default]
+    Warn,
+    /// Fail the version read, and so [`Sps30::open_probed`].
+    Strict,
+}
+
+impl FirmwareVersion {
+    /// First firmware with the Sleep and Wake-up commands.
+    pub const SLEEP: FirmwareVersion = FirmwareVersion::new(2, 0);
+    /// First firmware with the Read Device Status Register command.
+    pub const STATUS_REGISTER: FirmwareVersion = FirmwareVersion::new(2, 2);
+}
+
+/// With the serde feature it serializes as numbers, e.g.
+/// `{"firmware":{"major":2,"minor":2},"hardware":7,"shdlc":{"major":2,"minor":0}}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "no_std", derive(defmt::Format))]
+pub struct Sps30Version {
+    pub firmware: FirmwareVersion,
+    /// Hardware revision.
+    pub hardware: u8,
+    pub shdlc: ProtocolVersion,
+}
+
+/// `fw 2.2, hw 7, shdlc 2.0`
+impl fmt::Display for Sps30Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fw {}, hw {}, shdlc {}",
+            self.firmware, self.hardware, self.shdlc
+        )
+    }
+}
+
+// every payload the driver parses fits in a single frame
+const _: () = assert!(Sps30Measurement::SIZE <= shdlc::MAX_DATA);
+const _: () = assert!(Sps30Version::SIZE <= shdlc::MAX_DATA);
+
+impl Sps30Version {
+    /// Length of the Read Version payload the SPS30 currently sends.
+    pub const SIZE: usize = 7;
+
+    /// Parse a Read Version payload. Bytes after the 7 the SPS30 currently
+    /// sends are ignored, so fields added by later firmware don't break it.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DeviceError> {
+        if data.len() < Self::SIZE {
+            warn!("Wrong received data length: {}", data.len());
+            return Err(DeviceError::default());
+        }
+        if data.len() > Self::SIZE {
+            debug!("Ignoring {} extra version bytes", data.len() - Self::SIZE);
+        }
+
+        let firmware = FirmwareVersion::new(data[0], data[1]);
+        let hardware = data[3];
+        let shdlc = ProtocolVersion::new(data[5], data[6]);
+
+        debug!("Firmware: {}", firmware);
+        debug!("Hardware: {}", hardware);
+        debug!("SHDLC: {}", shdlc);
+
+        Ok(Self {
+            firmware,
+            hardware,
+            shdlc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading() -> Sps30Measurement {
+        Sps30Measurement {
+            mass_1_0: 1.0,
+            mass_2_5: 2.5,
+            mass_4_0: 4.0,
+            mass_10: 10.0,
+            number_pm0_5: 0.5,
+            number_pm1_0: 1.5,
+            number_pm2_5: 2.0,
+            number_pm4_0: 3.0,
+            number_pm10: 4.5,
+            typical_particle_size_um: 0.6,
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let m = reading();
+        assert_eq!(
+            Sps30Measurement::from_bytes(&m.to_bytes()).unwrap(),
+            Some(m)
+        );
+
+        let extremes = Sps30Measurement::from_array([
+            0.0,
+            -0.0,
+            -0.3,
+            f32::MAX,
+            f32::MIN,
+            f32::MIN_POSITIVE,
+            f32::EPSILON,
+            1e-45,
+            65535.0,
+            1.0 / 3.0,
+        ]);
+        let back = Sps30Measurement::from_bytes(&extremes.to_bytes())
+            .unwrap()
+            .unwrap();
+        let bits = |m: &Sps30Measurement| m.as_array().map(f32::to_bits);
+        assert_eq!(bits(&back), bits(&extremes));
+    }
+
+    #[test]
+    fn bytes_follow_the_documented_layout() {
+        let bytes = reading().to_bytes();
+        for (offset, v) in [(0, 1.0_f32), (4, 2.5), (16, 0.5), (36, 0.6)] {
+            assert_eq!(
+                bytes[offset..offset + 4],
+                v.to_be_bytes(),
+                "offset {}",
+                offset
+            );
+        }
+        assert_eq!(bytes[4..8], [0x40, 0x20, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn bad_bytes_are_rejected() {
+        let mut bytes = reading().to_bytes();
+        assert_eq!(
+            Sps30Measurement::try_from(&bytes[..39]),
+            Err(MeasurementParseError::Length(39))
+        );
+        assert!(Sps30Measurement::from_bytes(&bytes[..39]).is_err());
+        bytes[8..12].copy_from_slice(&f32::NAN.to_be_bytes());
+        assert_eq!(
+            Sps30Measurement::try_from(&bytes[..]),
+            Err(MeasurementParseError::NotFinite(8))
+        );
+        bytes[8..12].copy_from_slice(&f32::INFINITY.to_be_bytes());
+        assert!(Sps30Measurement::from_bytes(&bytes).is_err());
+    }
+
+    /// A measurement payload with the given field values, built by hand
+    /// rather than with `to_bytes`.
+    fn payload(fields: [f32; 10]) -> [u8; Sps30Measurement::SIZE] {
+        let mut bytes = [0u8; Sps30Measurement::SIZE];
+        for (chunk, v) in bytes.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&v.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn negative_values_parse_as_sent_and_clamp_to_zero() {
+        let fields = [-0.3, 2.5, -1e-6, 10.0, -12.0, 1.5, 2.0, 3.0, 4.5, 0.6];
+        let mut m = Sps30Measurement::from_bytes(&payload(fields))
+            .unwrap()
+            .unwrap();
+        assert_eq!(m.as_array(), fields);
+
+        assert!(m.clamp_negative());
+        assert_eq!(
+            m.as_array(),
+            [0.0, 2.5, 0.0, 10.0, 0.0, 1.5, 2.0, 3.0, 4.5, 0.6]
+        );
+        // nothing left to clamp
+        assert!(!m.clamp_negative());
+    }
+
+    #[test]
+    fn a_typical_particle_size_above_the_datasheet_range_is_out_of_range() {
+        let at_bound = Sps30Measurement {
+            typical_particle_size_um: 3.0,
+            ..reading()
+        };
+        assert_eq!(at_bound.validate(), Ok(()));
+        assert_eq!(Sps30Measurement::zeros().validate(), Ok(()));
+
+        let above = Sps30Measurement {
+            typical_particle_size_um: 3.01,
+            ..reading()
+        };
+        let anomaly = above.validate().unwrap_err();
+        assert_eq!(anomaly.field, "typical_particle_size_um");
+        assert_eq!(anomaly.kind, AnomalyKind::OutOfRange);
+    }
+
+    #[test]
+    fn clamping_leaves_non_negative_readings_alone() {
+        let mut m = Sps30Measurement::from_bytes(&payload([0.0; 10]))
+            .unwrap()
+            .unwrap();
+        assert!(!m.clamp_negative());
+        assert_eq!(m, Sps30Measurement::zeros());
+
+        // -0.0 equals 0.0, so it isn't counted as negative
+        let mut m = Sps30Measurement::from_bytes(&payload([-0.0; 10]))
+            .unwrap()
+            .unwrap();
+        assert!(!m.clamp_negative());
+        assert_eq!(m, Sps30Measurement::zeros());
+
+        let mut m = reading();
+        assert!(!m.clamp_negative());
+        assert_eq!(m, reading());
+    }
+
+    #[test]
+    fn zeros_is_all_zero() {
+        assert_eq!(Sps30Measurement::zeros().as_array(), [0.0; 10]);
+        assert_eq!(reading() + Sps30Measurement::zeros(), reading());
+    }
+
+    #[test]
+    fn add_and_sub_go_field_by_field() {
+        let a = reading();
+        let b = Sps30Measurement::from_array(core::array::from_fn(|i| i as f32));
+        let sum = (a + b).as_array();
+        let diff = (a - b).as_array();
+        for i in 0..10 {
+            assert_eq!(sum[i], a.as_array()[i] + i as f32, "field {}", i);
+            assert_eq!(diff[i], a.as_array()[i] - i as f32, "field {}", i);
+        }
+        let mut c = a;
+        c += b;
+        assert_eq!(c, a + b);
+        c -= b;
+        assert_eq!(c, a + b - b);
+    }
+
+    #[test]
+    fn mul_and_div_scale_every_field() {
+        let a = reading();
+        assert_eq!((a * 2.0).as_array(), a.as_array().map(|v| v * 2.0));
+        assert_eq!((a / 4.0).as_array(), a.as_array().map(|v| v / 4.0));
+
+        let mut c = a;
+        c *= 3.0;
+        assert_eq!(c, a * 3.0);
+        c /= 3.0;
+        assert_eq!(c, a * 3.0 / 3.0);
+    }
+
+    #[test]
+    fn mean_folds_from_zeros() {
+        let readings = [reading(), reading() * 3.0];
+        let sum = readings
+            .iter()
+            .fold(Sps30Measurement::zeros(), |acc, &m| acc + m);
+        assert_eq!(sum / readings.len() as f32, reading() * 2.0);
+    }
+
+    #[test]
+    fn number_bins_add_back_up_to_the_cumulative_values() {
+        let m = reading();
+        let bins = m.number_bins();
+        assert_eq!(
+            bins,
+            NumberBins {
+                pm0_5_to_1_0: 1.0,
+                pm1_0_to_2_5: 0.5,
+                pm2_5_to_4_0: 1.0,
+                pm4_0_to_10: 1.5,
+            }
+        );
+        let mut total = m.number_pm0_5;
+        for (bin, cumulative) in [
+            (bins.pm0_5_to_1_0, m.number_pm1_0),
+            (bins.pm1_0_to_2_5, m.number_pm2_5),
+            (bins.pm2_5_to_4_0, m.number_pm4_0),
+            (bins.pm4_0_to_10, m.number_pm10),
+        ] {
+            total += bin;
+            assert_eq!(total, cumulative);
+        }
+    }
+
+    #[test]
+    fn number_bins_never_go_negative() {
+        let m = Sps30Measurement {
+            number_pm2_5: 1.49,
+            number_pm10: 2.9,
+            ..reading()
+        };
+        let bins = m.number_bins();
+        assert_eq!(bins.pm1_0_to_2_5, 0.0);
+        assert_eq!(bins.pm4_0_to_10, 0.0);
+        assert_eq!(bins.pm2_5_to_4_0, 3.0 - 1.49);
+    }
+
+    fn faults(raw: u32) -> Vec<Sps30Fault> {
+        DeviceStatus::new(raw, false).faults().collect()
+    }
+
+    #[test]
+    fn each_status_bit_decodes_to_its_fault() {
+        assert_eq!(faults(0), []);
+        assert_eq!(faults(1 << 21), [Sps30Fault::FanSpeedOutOfRange]);
+        assert_eq!(faults(1 << 4), [Sps30Fault::FanFailure]);
+        assert_eq!(faults(1 << 5), [Sps30Fault::LaserFailure]);
+        assert_eq!(faults(1 << 0), [Sps30Fault::Unknown(1)]);
+        assert_eq!(faults(1 << 31), [Sps30Fault::Unknown(1 << 31)]);
+    }
+
+    #[test]
+    fn combined_status_bits_decode_warnings_first() {
+        assert_eq!(
+            faults(1 << 21 | 1 << 5 | 1 << 4),
+            [
+                Sps30Fault::FanSpeedOutOfRange,
+                Sps30Fault::FanFailure,
+                Sps30Fault::LaserFailure
+            ]
+        );
+        // unknown bits are gathered into one fault, with the known ones
+        // masked out
+        assert_eq!(
+            faults(0x8000_0000 | 1 << 21 | 1 << 5 | 0b11),
+            [
+                Sps30Fault::FanSpeedOutOfRange,
+                Sps30Fault::LaserFailure,
+                Sps30Fault::Unknown(0x8000_0003)
+            ]
+        );
+    }
+
+    #[test]
+    fn status_summaries() {
+        let ok = DeviceStatus::new(0, true);
+        assert!(ok.is_healthy() && ok.was_cleared());
+        assert_eq!(
+            (ok.severity(), ok.has_errors(), ok.has_warnings()),
+            (None, false, false)
+        );
+        assert_eq!(ok.to_string(), "OK");
+
+        let warning = DeviceStatus::new(1 << 21 | 1 << 8, false);
+        assert_eq!(warning.severity(), Some(Severity::Warning));
+        assert!(warning.has_warnings() && !warning.has_errors());
+        assert_eq!(
+            warning.to_string(),
+            "fan speed out of range, unknown status bits 0x00000100"
+        );
+
+        let both = DeviceStatus::new(1 << 21 | 1 << 4, false);
+        assert_eq!(both.severity(), Some(Severity::Error));
+        assert!(both.has_warnings() && both.has_errors());
+        assert_eq!(both.raw(), 0x0020_0010);
+    }
+
+    #[test]
+    fn every_fault_has_the_datasheet_severity() {
+        let all = [
+            Sps30Fault::FanSpeedOutOfRange,
+            Sps30Fault::FanFailure,
+            Sps30Fault::LaserFailure,
+            Sps30Fault::Unknown(1 << 8),
+        ];
+        for fault in all {
+            // no catch-all arm, so a new variant fails to build until it's
+            // listed above and given a severity here
+            let want = match fault {
+                Sps30Fault::FanSpeedOutOfRange => Severity::Warning,
+                Sps30Fault::FanFailure => Severity::Error,
+                Sps30Fault::LaserFailure => Severity::Error,
+                Sps30Fault::Unknown(_) => Severity::Warning,
+            };
+            assert_eq!(fault.severity(), want, "{}", fault);
+        }
+        assert!(Severity::Error > Severity::Warning);
+    }
+
+    #[test]
+    fn fault_names_are_stable() {
+        let names: Vec<_> = faults(1 << 21 | 1 << 5 | 1 << 4 | 1 << 6)
+            .iter()
+            .map(Sps30Fault::name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "fan_speed_out_of_range",
+                "fan_failure",
+                "laser_failure",
+                "unknown"
+            ]
+        );
+        assert_eq!(Sps30Fault::Unknown(1).name(), Sps30Fault::Unknown(2).name());
+    }
+
+    #[test]
+    fn status_payloads() {
+        let status = DeviceStatus::from_payload(&[0x00, 0x20, 0x00, 0x20, 0xff], true).unwrap();
+        assert_eq!(status, DeviceStatus::new(1 << 21 | 1 << 5, true));
+        assert!(DeviceStatus::from_payload(&[0x00, 0x20, 0x00, 0x20], true).is_err());
+    }
+
+    #[test]
+    fn version_payloads() {
+        let seven = [2, 3, 0, 7, 0, 2, 0];
+        let v = Sps30Version::from_bytes(&seven).unwrap();
+        assert_eq!(v.firmware, FirmwareVersion::new(2, 3));
+        assert_eq!(v.hardware, 7);
+        assert_eq!(v.shdlc, ProtocolVersion::new(2, 0));
+
+        // fields added by later firmware are ignored
+        let eight = [2, 3, 0, 7, 0, 2, 0, 0xaa];
+        assert_eq!(Sps30Version::from_bytes(&eight).unwrap(), v);
+
+        for short in [&seven[..0], &seven[..5], &seven[..6]] {
+            assert!(Sps30Version::from_bytes(short).is_err(), "{:?}", short);
+        }
+    }
+
+    #[cfg(feature = "schemars")]
+    mod schema {
+        use crate::*;
+        use serde_json::Value;
+
+        fn schema<T: schemars::JsonSchema>() -> Value {
+            serde_json::to_value(schemars::schema_for!(T)).unwrap()
+        }
+
+        fn required(schema: &Value) -> Vec<&str> {
+            let mut names: Vec<&str> = schema["required"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_str().unwrap())
+                .collect();
+            names.sort_unstable();
+            names
+        }
+
+        #[test]
+        fn measurement_fields_are_required_floats() {
+            let s = schema::<Sps30Measurement>();
+            let mut fields = [
+                "mass_1_0",
+                "mass_2_5",
+                "mass_4_0",
+                "mass_10",
+                "number_pm0_5",
+                "number_pm1_0",
+                "number_pm2_5",
+                "number_pm4_0",
+                "number_pm10",
+                "typical_particle_size_um",
+            ];
+            fields.sort_unstable();
+            assert_eq!(required(&s), fields);
+            for field in fields {
+                let p = &s["properties"][field];
+                assert_eq!(p["type"], "number", "{field}");
+                assert_eq!(p["format"], "float", "{field}");
+            }
+        }
+
+        #[test]
+        fn timestamped_adds_a_required_timestamp() {
+            let s = schema::<Timestamped<u64>>();
+            assert!(required(&s).contains(&"timestamp"));
+            assert!(required(&s).contains(&"mass_2_5"));
+            assert_eq!(s["properties"]["timestamp"]["type"], "integer");
+        }
+
+        #[test]
+        fn version_and_status_fields() {
+            let s = schema::<Sps30Version>();
+            assert_eq!(required(&s), ["firmware", "hardware", "shdlc"]);
+            assert_eq!(s["properties"]["hardware"]["type"], "integer");
+            assert_eq!(s["properties"]["hardware"]["format"], "uint8");
+
+            let s = schema::<DeviceStatus>();
+            assert_eq!(required(&s), ["cleared", "raw"]);
+            assert_eq!(s["properties"]["raw"]["format"], "uint32");
+            assert_eq!(s["properties"]["cleared"]["type"], "boolean");
+        }
+
+        #[test]
+        fn severity_is_a_string_enum() {
+            let s = schema::<Severity>();
+            // one subschema per variant, to carry each variant's doc comment
+            let variants: Vec<&Value> = s["oneOf"].as_array().unwrap().iter().collect();
+            assert_eq!(variants.len(), 2);
+            for (v, name) in variants.iter().zip(["Warning", "Error"]) {
+                assert_eq!(v["type"], "string");
+                assert_eq!(v["enum"], serde_json::json!([name]));
+            }
+        }
+
+        #[test]
+        fn stats_report_nests_measurements() {
+            let s = schema::<stats::Sps30StatsReport>();
+            assert_eq!(required(&s), ["max", "mean", "min", "samples"]);
+            assert_eq!(s["properties"]["samples"]["format"], "uint32");
+            assert_eq!(
+                s["properties"]["mean"]["$ref"],
+                "#/definitions/Sps30Measurement"
+            );
+        }
+    }
 }
@@ -0,0 +1,675 @@
+//! Scripted in-memory transport for exercising the driver without hardware.
+//!
+//! A [`MockPort`] is loaded with a list of [`Exchange`]s. Each exchange holds
+//! the bytes the driver is expected to write and the bytes the "device" answers
+//! with once that write has been seen.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::shdlc::{encode_request, encode_response};
+use crate::{Transport, TransportError};
+
+use cfg_block::cfg_block;
+cfg_block! {
+    #[cfg(feature = "std")]{
+        use std::thread::sleep;
+        use std::time::Duration;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Exchange {
+    /// Raw bytes the driver must write.
+    pub request: Vec<u8>,
+    /// Raw bytes queued for reading once the request has been written.
+    pub response: Vec<u8>,
+    /// Flip every bit of the response byte at this index.
+    pub corrupt: Option<usize>,
+    /// Wait this long before the response becomes readable. Ignored on no_std.
+    pub delay_ms: u32,
+}
+
+impl Exchange {
+    pub fn new(request: &[u8], response: &[u8]) -> Self {
+        Self {
+            request: request.to_vec(),
+            response: response.to_vec(),
+            ..Default::default()
+        }
+    }
+    /// Command `cmd` with `data` sent to address 0, answered with `state`
+    /// and `reply`, the frames encoded as on the wire.
+    pub fn command(cmd: u8, data: &[u8], state: u8, reply: &[u8]) -> Self {
+        Self::new(
+            &encode_request(0, cmd, data).unwrap(),
+            &encode_response(0, cmd, state, reply).unwrap(),
+        )
+    }
+    pub fn corrupt(mut self, index: usize) -> Self {
+        self.corrupt = Some(index);
+        self
+    }
+    pub fn delay_ms(mut self, ms: u32) -> Self {
+        self.delay_ms = ms;
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MockPort {
+    script: VecDeque<Exchange>,
+    written: Vec<u8>,
+    pending: VecDeque<u8>,
+    read_chunk: Option<usize>,
+}
+
+impl MockPort {
+    pub fn new(script: Vec<Exchange>) -> Self {
+        Self {
+            script: script.into(),
+            ..Default::default()
+        }
+    }
+    /// Return at most `max` bytes from each read, like a port handing over
+    /// data as it trickles in.
+    pub fn read_chunk(mut self, max: usize) -> Self {
+        self.read_chunk = Some(max);
+        self
+    }
+    /// Queue bytes to be read without waiting for a request.
+    pub fn push_rx(&mut self, data: &[u8]) {
+        self.pending.extend(data);
+    }
+    /// True once every scripted exchange has been played and all queued
+    /// bytes have been read.
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty() && self.pending.is_empty()
+    }
+
+    fn do_write(&mut self, buf: &[u8]) {
+        self.written.extend_from_slice(buf);
+        while let Some(e) = self.script.front() {
+            if self.written.len() < e.request.len() {
+                break;
+            }
+            let e = self.script.pop_front().unwrap();
+            let request: Vec<u8> = self.written.drain(..e.request.len()).collect();
+            assert_eq!(
+                request, e.request,
+                "MockPort: unexpected write {:02x?}, expected {:02x?}",
+                request, e.request
+            );
+
+            #[cfg(feature = "std")]
+            if e.delay_ms > 0 {
+                sleep(Duration::from_millis(e.delay_ms as u64));
+            }
+
+            let mut response = e.response;
+            if let Some(i) = e.corrupt
+                && let Some(b) = response.get_mut(i)
+            {
+                *b = !*b;
+            }
+            self.pending.extend(response);
+        }
+    }
+    fn do_read(&mut self, buf: &mut [u8]) -> usize {
+        let len = self.read_chunk.map_or(buf.len(), |max| max.min(buf.len()));
+        let mut n = 0;
+        while n < len {
+            match self.pending.pop_front() {
+                Some(b) => {
+                    buf[n] = b;
+                    n += 1;
+                }
+                None => break,
+            }
+        }
+        n
+    }
+}
+
+impl Transport for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        Ok(self.do_read(buf))
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+        self.do_write(buf);
+        Ok(())
+    }
+    fn read_ready(&mut self) -> Option<bool> {
+        Some(!self.pending.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_RESPONSE_TIMEOUT, FirmwareVersion, RetryPolicy, Sps30, Sps30Measurement};
+    use alloc::vec;
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use core::time::Duration;
+    use std::string::ToString;
+    use std::time::Instant;
+
+    const START_MEASUREMENT: u8 = 0x00;
+    const STOP_MEASUREMENT: u8 = 0x01;
+    const READ_MEASURED_VALUE: u8 = 0x03;
+    const READ_VERSION: u8 = 0xd1;
+    const READ_DEVICE_STATUS: u8 = 0xd2;
+    const VERSION: [u8; 7] = [2, 2, 0, 7, 0, 2, 0];
+
+    pub(crate) fn measurement() -> Sps30Measurement {
+        Sps30Measurement {
+            mass_1_0: 1.0,
+            mass_2_5: 2.5,
+            mass_4_0: 4.0,
+            mass_10: 10.0,
+            number_pm0_5: 0.5,
+            number_pm1_0: 1.5,
+            number_pm2_5: 2.0,
+            number_pm4_0: 3.0,
+            number_pm10: 4.5,
+            typical_particle_size_um: 0.6,
+        }
+    }
+
+    fn sensor(script: Vec<Exchange>) -> Sps30<MockPort> {
+        Sps30::new(MockPort::new(script))
+    }
+
+    #[test]
+    fn reads_the_version() {
+        let mut sensor = sensor(vec![Exchange::command(READ_VERSION, &[], 0, &VERSION)]);
+        let v = sensor.read_version().unwrap();
+        assert_eq!((v.firmware.major, v.firmware.minor), (2, 2));
+        assert_eq!(v.hardware, 7);
+        assert_eq!((v.shdlc.major, v.shdlc.minor), (2, 0));
+        assert_eq!(sensor.cached_version(), Some(v));
+    }
+
+    #[test]
+    fn reads_a_full_measurement() {
+        let m = measurement();
+        let mut sensor = sensor(vec![Exchange::command(
+            READ_MEASURED_VALUE,
+            &[],
+            0,
+            &m.to_bytes(),
+        )]);
+        assert_eq!(sensor.read_measurement().unwrap(), Some(m));
+    }
+
+    #[test]
+    fn negative_readings_are_clamped_only_when_asked() {
+        let negative = Sps30Measurement {
+            mass_1_0: -0.2,
+            number_pm0_5: -3.0,
+            ..measurement()
+        };
+        let read = || Exchange::command(READ_MEASURED_VALUE, &[], 0, &negative.to_bytes());
+        let mut sensor = sensor(vec![read(), read()]);
+        assert_eq!(sensor.read_measurement().unwrap(), Some(negative));
+
+        sensor.set_clamp_negative(true);
+        let m = sensor.read_measurement().unwrap().unwrap();
+        assert_eq!((m.mass_1_0, m.number_pm0_5), (0.0, 0.0));
+        assert_eq!(m.mass_2_5, negative.mass_2_5);
+    }
+
+    #[test]
+    fn an_empty_measurement_is_nothing_new() {
+        let mut sensor = sensor(vec![Exchange::command(READ_MEASURED_VALUE, &[], 0, &[])]);
+        assert_eq!(sensor.read_measurement().unwrap(), None);
+    }
+
+    #[test]
+    fn a_short_measurement_is_an_error() {
+        let mut sensor = sensor(vec![Exchange::command(
+            READ_MEASURED_VALUE,
+            &[],
+            0,
+            &measurement().to_bytes()[..36],
+        )]);
+        assert!(sensor.read_measurement().is_err());
+    }
+
+    #[test]
+    fn reads_the_device_status() {
+        let mut sensor = sensor(vec![Exchange::command(
+            READ_DEVICE_STATUS,
+            &[0x01],
+            0,
+            &[0x00, 0x20, 0x00, 0x10, 0x00],
+        )]);
+        let status = sensor.read_and_clear_device_status().unwrap();
+        assert_eq!(status.raw(), 1 << 21 | 1 << 4);
+        assert!(status.has_errors());
+        assert!(status.has_warnings());
+    }
+
+    #[test]
+    fn peeking_leaves_the_status_register_set() {
+        // the mock checks the subcommand byte: 0x00 peeks, 0x01 clears
+        let status = [0x00, 0x20, 0x00, 0x00, 0x00];
+        let mut sensor = sensor(vec![
+            Exchange::command(READ_DEVICE_STATUS, &[0x00], 0, &status),
+            Exchange::command(READ_DEVICE_STATUS, &[0x01], 0, &status),
+            Exchange::command(READ_DEVICE_STATUS, &[0x00], 0, &[0; 5]),
+        ]);
+        let peeked = sensor.peek_device_status().unwrap();
+        assert_eq!((peeked.raw(), peeked.was_cleared()), (1 << 21, false));
+        let cleared = sensor.read_and_clear_device_status().unwrap();
+        assert_eq!((cleared.raw(), cleared.was_cleared()), (1 << 21, true));
+        assert!(sensor.peek_device_status().unwrap().is_healthy());
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn a_flagged_error_is_looked_up_and_reported() {
+        static FAULTS: AtomicU32 = AtomicU32::new(0);
+        static LAST: AtomicU32 = AtomicU32::new(0);
+        fn on_fault(status: &crate::DeviceStatus) {
+            FAULTS.fetch_add(1, Ordering::Relaxed);
+            LAST.store(status.raw(), Ordering::Relaxed);
+        }
+
+        let m = measurement().to_bytes();
+        let mut sensor = sensor(vec![
+            Exchange::command(READ_MEASURED_VALUE, &[], 0x80, &m),
+            // the driver peeks to find out what the flag is about
+            Exchange::command(READ_DEVICE_STATUS, &[0x00], 0x80, &[0, 0, 0, 0x20, 0]),
+            // still flagged, already reported
+            Exchange::command(READ_MEASURED_VALUE, &[], 0x80, &m),
+            Exchange::command(READ_MEASURED_VALUE, &[], 0, &m),
+        ]);
+        sensor.set_on_fault(Some(on_fault));
+
+        assert_eq!(sensor.read_measurement().unwrap(), Some(measurement()));
+        assert!(sensor.device_error_pending());
+        assert_eq!(FAULTS.load(Ordering::Relaxed), 1);
+        assert_eq!(LAST.load(Ordering::Relaxed), 1 << 5);
+        let pending = sensor.pending_status().unwrap();
+        assert_eq!(
+            pending.faults().next(),
+            Some(crate::Sps30Fault::LaserFailure)
+        );
+
+        sensor.read_measurement().unwrap();
+        assert_eq!(FAULTS.load(Ordering::Relaxed), 1);
+
+        sensor.read_measurement().unwrap();
+        assert!(!sensor.device_error_pending());
+        assert_eq!(sensor.pending_status(), None);
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn a_bad_checksum_fails_the_command() {
+        let e = Exchange::command(READ_VERSION, &[], 0, &VERSION);
+        let checksum = e.response.len() - 2;
+        let mut sensor = sensor(vec![e.corrupt(checksum)]);
+        assert!(sensor.read_version().is_err());
+        assert_eq!(sensor.cached_version(), None);
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    /// The Read Version exchange with one bit of the response's data flipped.
+    fn flipped_version() -> Exchange {
+        let mut e = Exchange::command(READ_VERSION, &[], 0, &VERSION);
+        e.response[6] ^= 0x01;
+        e
+    }
+
+    #[test]
+    fn a_flipped_bit_is_resent_once() {
+        let mut sensor = sensor(vec![
+            flipped_version(),
+            Exchange::command(READ_VERSION, &[], 0, &VERSION),
+        ]);
+        sensor.set_retry_policy(RetryPolicy {
+            retries: 3,
+            ..Default::default()
+        });
+        assert_eq!(sensor.read_version().unwrap().hardware, 7);
+        assert_eq!(sensor.last_attempts(), 2);
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn a_late_response_is_not_taken_for_the_next_one() {
+        let stale = measurement();
+        let fresh = Sps30Measurement {
+            mass_2_5: 30.0,
+            ..stale
+        };
+        let mut corrupted = Exchange::command(READ_MEASURED_VALUE, &[], 0, &stale.to_bytes());
+        corrupted.response[6] ^= 0x01;
+        let mut sensor = sensor(vec![
+            corrupted,
+            Exchange::command(READ_MEASURED_VALUE, &[], 0, &fresh.to_bytes()),
+        ]);
+        assert!(sensor.read_measurement().is_err());
+        // a clean copy of the answer turns up after it was given up on
+        let late = Exchange::command(READ_MEASURED_VALUE, &[], 0, &stale.to_bytes()).response;
+        sensor.link.port_mut().push_rx(&late);
+        assert_eq!(sensor.read_measurement().unwrap(), Some(fresh));
+        assert_eq!(sensor.last_attempts(), 1);
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    /// The Read Version exchange with its answer lost.
+    fn dropped_version() -> Exchange {
+        Exchange::new(&Exchange::command(READ_VERSION, &[], 0, &[]).request, &[])
+    }
+
+    #[test]
+    fn a_missing_response_times_out_after_the_default() {
+        let mut sensor = sensor(vec![dropped_version()]);
+        let start = Instant::now();
+        assert!(sensor.read_version().is_err());
+        let waited = start.elapsed();
+        assert!(sensor.timed_out());
+        assert!(waited >= DEFAULT_RESPONSE_TIMEOUT, "{:?}", waited);
+        assert!(waited < DEFAULT_RESPONSE_TIMEOUT * 5, "{:?}", waited);
+    }
+
+    #[test]
+    fn a_dropped_response_is_resent() {
+        let mut sensor = sensor(vec![
+            dropped_version(),
+            Exchange::command(READ_VERSION, &[], 0, &VERSION),
+        ]);
+        sensor.set_response_timeout(Some(Duration::from_millis(20)));
+        sensor.set_retry_policy(RetryPolicy {
+            retries: 1,
+            ..Default::default()
+        });
+        assert_eq!(sensor.read_version().unwrap().hardware, 7);
+        assert_eq!(sensor.last_attempts(), 2);
+        assert!(!sensor.timed_out());
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn resends_wait_the_retry_delay() {
+        static WAITED_MS: AtomicU32 = AtomicU32::new(0);
+        fn delay(ms: u32) {
+            WAITED_MS.fetch_add(ms, Ordering::Relaxed);
+        }
+
+        let mut sensor = sensor(vec![
+            dropped_version(),
+            dropped_version(),
+            Exchange::command(READ_VERSION, &[], 0, &VERSION),
+        ]);
+        sensor.set_response_timeout(Some(Duration::from_millis(20)));
+        sensor.set_delay(Some(delay));
+        sensor.set_retry_policy(RetryPolicy {
+            retries: 2,
+            delay: Duration::from_millis(50),
+        });
+        sensor.read_version().unwrap();
+        assert_eq!(sensor.last_attempts(), 3);
+        assert_eq!(WAITED_MS.load(Ordering::Relaxed), 100);
+    }
+
+    /// A port that, like a serial port, blocks for its read timeout when
+    /// nothing has arrived, and whose responses take `latency` to arrive.
+    struct SlowPort {
+        inner: MockPort,
+        latency: Duration,
+        port_timeout: Option<Duration>,
+        ready_at: Instant,
+        empty_reads: u32,
+    }
+
+    impl Transport for SlowPort {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+            if Instant::now() >= self.ready_at {
+                let n = self.inner.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+            }
+            std::thread::sleep(self.port_timeout.unwrap());
+            self.empty_reads += 1;
+            Ok(0)
+        }
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+            self.ready_at = Instant::now() + self.latency;
+            self.inner.write_all(buf)
+        }
+    }
+
+    impl crate::PortTimeout for SlowPort {
+        fn set_port_timeout(&mut self, timeout: Duration) -> Result<(), TransportError> {
+            self.port_timeout = Some(timeout);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_short_port_timeout_only_means_more_reads() {
+        let deadline = Duration::from_millis(200);
+        let mut sensor = Sps30::new(SlowPort {
+            inner: MockPort::new(vec![
+                Exchange::command(READ_VERSION, &[], 0, &VERSION),
+                dropped_version(),
+            ]),
+            latency: Duration::from_millis(70),
+            port_timeout: None,
+            ready_at: Instant::now(),
+            empty_reads: 0,
+        });
+        sensor.set_timeout(deadline).unwrap();
+        let port = sensor.link.port_mut();
+        assert_eq!(port.port_timeout, Some(Duration::from_millis(20)));
+
+        // several port timeouts pass before the answer, none of them fatal
+        assert_eq!(sensor.read_version().unwrap().hardware, 7);
+        assert!(!sensor.timed_out());
+        assert!(sensor.link.port_mut().empty_reads >= 3);
+
+        // the deadline still holds, overshot by at most about one port read
+        let start = Instant::now();
+        assert!(sensor.read_version().is_err());
+        let waited = start.elapsed();
+        assert!(sensor.timed_out());
+        assert!(waited >= deadline, "{:?}", waited);
+        assert!(
+            waited < deadline + Duration::from_millis(100),
+            "{:?}",
+            waited
+        );
+
+        sensor.set_timeout(Duration::from_millis(5)).unwrap();
+        assert_eq!(
+            sensor.link.port_mut().port_timeout,
+            Some(Duration::from_millis(5))
+        );
+        assert_eq!(sensor.response_timeout, Some(Duration::from_millis(5)));
+    }
+
+    /// A Read Version exchange whose answer fails its checksum.
+    fn corrupted_version() -> Exchange {
+        let mut corrupted = Exchange::command(READ_VERSION, &[], 0, &VERSION);
+        corrupted.response[6] ^= 0x01;
+        corrupted
+    }
+
+    #[test]
+    fn stale_input_is_drained_from_ports_whose_reads_time_out() {
+        let mut sensor = Sps30::new(SlowPort {
+            inner: MockPort::new(vec![
+                corrupted_version(),
+                Exchange::command(READ_VERSION, &[], 0, &[1, 0, 0, 7, 0, 2, 0]),
+            ]),
+            latency: Duration::ZERO,
+            port_timeout: None,
+            ready_at: Instant::now(),
+            empty_reads: 0,
+        });
+        sensor.set_timeout(Duration::from_millis(200)).unwrap();
+        assert!(sensor.read_version().is_err());
+        // the port can't say whether input is waiting, but its reads return
+        let late = Exchange::command(READ_VERSION, &[], 0, &VERSION).response;
+        sensor.link.port_mut().inner.push_rx(&late);
+        assert_eq!(sensor.read_version().unwrap().firmware.major, 1);
+        assert!(sensor.link.port_mut().inner.is_done());
+    }
+
+    /// A port that can't tell whether input is waiting, and whose reads,
+    /// like embedded_io ones or those of a file without a timeout, block
+    /// until a byte arrives.
+    struct BlockingPort(MockPort);
+
+    impl Transport for BlockingPort {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+            match self.0.read(buf)? {
+                0 => panic!("read blocked on an idle line"),
+                n => Ok(n),
+            }
+        }
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+            self.0.write_all(buf)
+        }
+    }
+
+    #[test]
+    fn a_blocking_port_is_not_read_on_an_idle_line() {
+        let mut sensor = Sps30::new(BlockingPort(MockPort::new(vec![
+            corrupted_version(),
+            Exchange::command(READ_VERSION, &[], 0, &VERSION),
+        ])));
+        assert!(sensor.read_version().is_err());
+        assert_eq!(sensor.read_version().unwrap().hardware, 7);
+        assert!(sensor.link.port_mut().0.is_done());
+    }
+
+    /// A port that always has input, noise when the device is silent.
+    struct NoisyPort {
+        inner: MockPort,
+        noise: usize,
+    }
+
+    impl Transport for NoisyPort {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+            match self.inner.read(buf)? {
+                0 => {
+                    buf.fill(0x00);
+                    self.noise += buf.len();
+                    Ok(buf.len())
+                }
+                n => Ok(n),
+            }
+        }
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+            self.inner.write_all(buf)
+        }
+        fn read_ready(&mut self) -> Option<bool> {
+            Some(true)
+        }
+    }
+
+    #[test]
+    fn draining_a_noisy_line_stops() {
+        let mut sensor = Sps30::new(NoisyPort {
+            inner: MockPort::new(vec![
+                corrupted_version(),
+                Exchange::command(READ_VERSION, &[], 0, &VERSION),
+            ]),
+            noise: 0,
+        });
+        assert!(sensor.read_version().is_err());
+        assert_eq!(sensor.read_version().unwrap().hardware, 7);
+        let port = sensor.link.port_mut();
+        assert!(port.inner.is_done());
+        assert!(port.noise > 0 && port.noise <= 2 * crate::shdlc::MAX_STUFFED_FRAME);
+    }
+
+    #[test]
+    fn commands_too_new_for_the_firmware_say_so() {
+        let mut sensor = sensor(vec![Exchange::command(
+            READ_VERSION,
+            &[],
+            0,
+            &[1, 0, 0, 7, 0, 2, 0],
+        )]);
+        sensor.read_version().unwrap();
+
+        let e = sensor.sleep().unwrap_err();
+        let u = e.unsupported_by_firmware().unwrap();
+        assert_eq!(u.required, FirmwareVersion::SLEEP);
+        assert_eq!(u.actual, FirmwareVersion::new(1, 0));
+
+        let e = sensor.peek_device_status().unwrap_err();
+        let u = e.unsupported_by_firmware().unwrap();
+        assert_eq!(u.required, FirmwareVersion::STATUS_REGISTER);
+        assert!(e.to_string().contains("needs firmware 2.2"), "{}", e);
+
+        // neither was sent
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    #[test]
+    fn other_failures_are_not_about_the_firmware() {
+        let mut sensor = sensor(vec![Exchange::command(READ_VERSION, &[], 0, &[2])]);
+        let e = sensor.read_version().unwrap_err();
+        assert!(e.unsupported_by_firmware().is_none());
+    }
+
+    #[test]
+    fn starting_twice_is_refused_without_asking_the_device() {
+        let mut sensor = sensor(vec![
+            Exchange::command(START_MEASUREMENT, &[0x01, 0x03], 0, &[]),
+            Exchange::command(STOP_MEASUREMENT, &[], 0, &[]),
+            Exchange::command(START_MEASUREMENT, &[0x01, 0x03], 0, &[]),
+        ]);
+        sensor.start_measurement().unwrap();
+        assert!(sensor.start_measurement().is_err());
+        sensor.stop_measurement().unwrap();
+        sensor.start_measurement().unwrap();
+        assert!(sensor.link.port_mut().is_done());
+    }
+
+    /// Three reads, the first answer arriving together with the second.
+    fn chunked_session(chunk: usize) -> [Option<Sps30Measurement>; 3] {
+        let first = measurement();
+        let second = Sps30Measurement {
+            mass_1_0: 11.0,
+            ..first
+        };
+        let read =
+            |m: &Sps30Measurement| Exchange::command(READ_MEASURED_VALUE, &[], 0, &m.to_bytes());
+        let mut both = read(&first);
+        both.response.extend(read(&second).response);
+        let mut port = MockPort::new(vec![
+            both,
+            Exchange::new(&read(&second).request, &[]),
+            Exchange::command(READ_MEASURED_VALUE, &[], 0, &[]),
+        ])
+        .read_chunk(chunk);
+        // line noise before the first frame
+        port.push_rx(&[0x00, 0xff]);
+        let mut sensor = Sps30::new(port);
+        let readings = [
+            sensor.read_measurement().unwrap(),
+            sensor.read_measurement().unwrap(),
+            sensor.read_measurement().unwrap(),
+        ];
+        assert!(sensor.link.port_mut().is_done());
+        readings
+    }
+
+    #[test]
+    fn chunk_size_makes_no_difference() {
+        let one = chunked_session(1);
+        assert_eq!(one[0], Some(measurement()));
+        assert_eq!(one[1].unwrap().mass_1_0, 11.0);
+        assert_eq!(one[2], None);
+        assert_eq!(chunked_session(200), one);
+    }
+}
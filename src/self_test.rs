@@ -0,0 +1,354 @@
+//! Commissioning check for a newly installed sensor.
+//!
+//! [`self_test`] runs the sensor through a reset, identification, a status
+//! check and one measurement cycle, and reports how each stage went. It stops
+//! at the first failing stage, leaving the results of the ones before it in
+//! the report, and stops measurement again if it got that far.
+
+use std::fmt;
+use std::format;
+use std::string::{String, ToString};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::{
+    DeviceModel, DeviceStatus, PRODUCT_TYPE, Sps30, Sps30Measurement, Sps30Version, Transport,
+    WARM_UP,
+};
+
+/// How long to wait for a plausible reading once measurement has started.
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A step of the self test, in the order they're run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stage {
+    Reset,
+    /// Checking the product type is the SPS30's.
+    Probe,
+    /// Reading the serial number and versions.
+    Identity,
+    /// Reading the device status register, without clearing it.
+    Status,
+    StartMeasurement,
+    /// Waiting for a reading that passes [`Sps30Measurement::validate`] after
+    /// warm-up.
+    FirstSample,
+    StopMeasurement,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Reset => "reset",
+            Stage::Probe => "probe",
+            Stage::Identity => "identity",
+            Stage::Status => "status",
+            Stage::StartMeasurement => "start measurement",
+            Stage::FirstSample => "first sample",
+            Stage::StopMeasurement => "stop measurement",
+        })
+    }
+}
+
+/// How one stage went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StageResult {
+    pub stage: Stage,
+    pub passed: bool,
+    pub duration: Duration,
+    /// Why the stage failed.
+    pub error: Option<String>,
+}
+
+/// Everything [`self_test`] found out, with the values collected by the
+/// stages that got that far.
+#[derive(Debug, Default)]
+pub struct Sps30SelfTestReport {
+    /// The stages run, in order. Stages after a failure are left out, except
+    /// for stopping measurement.
+    pub stages: Vec<StageResult>,
+    pub product_type: Option<String>,
+    pub serial: Option<String>,
+    pub version: Option<Sps30Version>,
+    pub status: Option<DeviceStatus>,
+    pub sample: Option<Sps30Measurement>,
+    /// How long after measurement started the sample came in.
+    pub time_to_sample: Option<Duration>,
+}
+
+impl Sps30SelfTestReport {
+    /// Whether every stage passed.
+    pub fn passed(&self) -> bool {
+        self.failed_stage().is_none()
+            && self.stages.last().map(|s| s.stage) == Some(Stage::StopMeasurement)
+    }
+
+    /// The first stage that failed.
+    pub fn failed_stage(&self) -> Option<Stage> {
+        self.stages.iter().find(|s| !s.passed).map(|s| s.stage)
+    }
+
+    /// Time each stage and record how it went, returning its value if it
+    /// passed.
+    fn run<T>(&mut self, stage: Stage, f: impl FnOnce() -> Result<T, String>) -> Option<T> {
+        let start = Instant::now();
+        let r = f();
+        self.stages.push(StageResult {
+            stage,
+            passed: r.is_ok(),
+            duration: start.elapsed(),
+            error: r.as_ref().err().cloned(),
+        });
+        r.ok()
+    }
+}
+
+impl fmt::Display for Sps30SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in &self.stages {
+            let result = if s.passed { "ok" } else { "FAILED" };
+            write!(f, "{:<18} {:<6} {:>8.2?}", s.stage, result, s.duration)?;
+            if let Some(e) = &s.error {
+                write!(f, "  {}", e)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Wait for a reading that passes validation once warm-up is over.
+fn first_sample<P: Transport>(sensor: &mut Sps30<P>) -> Result<Sps30Measurement, String> {
+    let start = Instant::now();
+    let mut last_error = String::from("no reading");
+    while start.elapsed() < SAMPLE_TIMEOUT {
+        sleep(Duration::from_secs(1));
+        match sensor.read_measurement() {
+            Ok(Some(m)) if start.elapsed() < WARM_UP => {
+                last_error = format!("warming up, last {}", m)
+            }
+            Ok(Some(m)) => match m.validate() {
+                Ok(()) => return Ok(m),
+                Err(e) => last_error = e.to_string(),
+            },
+            Ok(None) => (),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Err(format!(
+        "no plausible reading in {:?}: {}",
+        SAMPLE_TIMEOUT, last_error
+    ))
+}
+
+/// Run the commissioning checks on `sensor`, see the module documentation.
+/// Takes around half a minute, most of it waiting for the sensor to warm up.
+pub fn self_test<P: Transport>(sensor: &mut Sps30<P>) -> Sps30SelfTestReport {
+    let mut report = Sps30SelfTestReport::default();
+
+    if report
+        .run(Stage::Reset, || {
+            sensor.device_reset().map_err(|e| e.to_string())
+        })
+        .is_none()
+    {
+        return report;
+    }
+
+    let model = report.run(Stage::Probe, || match sensor.identify() {
+        Ok(DeviceModel::Sps30) => Ok(DeviceModel::Sps30),
+        Ok(m) => Err(m.to_string()),
+        Err(_) => Err("no product type".to_string()),
+    });
+    if model.is_none() {
+        return report;
+    }
+    report.product_type = Some(PRODUCT_TYPE.to_string());
+
+    let identity = report.run(Stage::Identity, || {
+        let serial = sensor.read_serial_number().ok_or("no serial number")?;
+        let version = sensor.read_version().map_err(|e| e.to_string())?;
+        Ok((serial, version))
+    });
+    let Some((serial, version)) = identity else {
+        return report;
+    };
+    report.serial = Some(serial);
+    report.version = Some(version);
+
+    // kept even when the stage fails, to say which faults are flagged
+    let mut status = None;
+    let healthy = report.run(Stage::Status, || {
+        let s = sensor.peek_device_status().map_err(|e| e.to_string())?;
+        status = Some(s);
+        if s.has_errors() {
+            return Err(format!("faults flagged: {}", s));
+        }
+        Ok(())
+    });
+    report.status = status;
+    if healthy.is_none() {
+        return report;
+    }
+
+    if report
+        .run(Stage::StartMeasurement, || {
+            sensor.start_measurement().map_err(|e| e.to_string())
+        })
+        .is_none()
+    {
+        return report;
+    }
+
+    let started = Instant::now();
+    if let Some(m) = report.run(Stage::FirstSample, || first_sample(sensor)) {
+        report.sample = Some(m);
+        report.time_to_sample = Some(started.elapsed());
+    }
+
+    // stop even when no sample came, so the fan isn't left running
+    report.run(Stage::StopMeasurement, || {
+        sensor.stop_measurement().map_err(|e| e.to_string())
+    });
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Exchange, MockPort};
+
+    const START_MEASUREMENT: u8 = 0x00;
+    const STOP_MEASUREMENT: u8 = 0x01;
+    const READ_MEASURED_VALUE: u8 = 0x03;
+    const DEVICE_INFORMATION: u8 = 0xd0;
+    const READ_VERSION: u8 = 0xd1;
+    const READ_DEVICE_STATUS: u8 = 0xd2;
+    const RESET: u8 = 0xd3;
+    const SERIAL: &str = "ABCDEF0123456789";
+
+    /// The device's side of a passing self test, up to starting measurement.
+    fn healthy() -> Vec<Exchange> {
+        std::vec![
+            Exchange::command(RESET, &[], 0, &[]),
+            Exchange::command(DEVICE_INFORMATION, &[0x00], 0, b"00080000\0"),
+            Exchange::command(DEVICE_INFORMATION, &[0x03], 0, b"ABCDEF0123456789\0"),
+            Exchange::command(READ_VERSION, &[], 0, &[2, 2, 0, 7, 0, 2, 0]),
+            Exchange::command(READ_DEVICE_STATUS, &[0x00], 0, &[0; 5]),
+            Exchange::command(START_MEASUREMENT, &[0x01, 0x03], 0, &[]),
+        ]
+    }
+
+    /// Run the self test against the first `passing` exchanges of a healthy
+    /// device followed by `rest`, checking the whole script was played.
+    fn run(passing: usize, rest: Vec<Exchange>) -> (Sps30SelfTestReport, Sps30<MockPort>) {
+        let mut script = healthy();
+        script.truncate(passing);
+        script.extend(rest);
+        let mut sensor = Sps30::new(MockPort::new(script));
+        let report = self_test(&mut sensor);
+        assert!(sensor.link.port_mut().is_done());
+        (report, sensor)
+    }
+
+    fn stages(report: &Sps30SelfTestReport) -> Vec<(Stage, bool)> {
+        report.stages.iter().map(|s| (s.stage, s.passed)).collect()
+    }
+
+    #[test]
+    fn a_missing_reset_response_stops_at_once() {
+        let reset = Exchange::command(RESET, &[], 0, &[]).request;
+        let (report, _) = run(0, std::vec![Exchange::new(&reset, &[])]);
+        assert_eq!(stages(&report), [(Stage::Reset, false)]);
+        assert!(report.stages[0].error.is_some());
+        assert!(!report.passed());
+        assert_eq!(report.product_type, None);
+    }
+
+    #[test]
+    fn another_product_fails_the_probe() {
+        let other = Exchange::command(DEVICE_INFORMATION, &[0x00], 0, b"00090000\0");
+        let (report, _) = run(1, std::vec![other]);
+        assert_eq!(
+            stages(&report),
+            [(Stage::Reset, true), (Stage::Probe, false)]
+        );
+        assert_eq!(report.failed_stage(), Some(Stage::Probe));
+        assert!(
+            report.stages[1]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("00090000")
+        );
+        assert_eq!(report.product_type, None);
+    }
+
+    #[test]
+    fn a_bad_version_fails_identity() {
+        let (report, _) = run(
+            3,
+            std::vec![Exchange::command(READ_VERSION, &[], 0, &[2, 2])],
+        );
+        assert_eq!(report.failed_stage(), Some(Stage::Identity));
+        assert_eq!(report.stages.len(), 3);
+        assert_eq!(report.product_type.as_deref(), Some(PRODUCT_TYPE));
+        assert_eq!((report.serial.as_deref(), report.version), (None, None));
+    }
+
+    #[test]
+    fn flagged_errors_fail_the_status_check() {
+        let fan = Exchange::command(READ_DEVICE_STATUS, &[0x00], 0x80, &[0, 0, 0, 0x10, 0]);
+        let (report, _) = run(4, std::vec![fan]);
+        assert_eq!(report.failed_stage(), Some(Stage::Status));
+        assert_eq!(report.stages.len(), 4);
+        assert!(
+            report.stages[3]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("fan failure")
+        );
+        // the earlier stages' findings are kept
+        assert_eq!(report.serial.as_deref(), Some(SERIAL));
+        assert_eq!(report.version.unwrap().hardware, 7);
+        assert!(report.status.unwrap().has_errors());
+    }
+
+    #[test]
+    fn a_refused_start_leaves_nothing_to_stop() {
+        let refused = Exchange::command(START_MEASUREMENT, &[0x01, 0x03], 0x43, &[]);
+        let (report, sensor) = run(5, std::vec![refused]);
+        assert_eq!(report.failed_stage(), Some(Stage::StartMeasurement));
+        assert_eq!(report.stages.last().unwrap().stage, Stage::StartMeasurement);
+        assert!(report.status.unwrap().is_healthy());
+        assert!(!sensor.running);
+    }
+
+    #[test]
+    fn a_bad_sample_still_stops_measurement() {
+        let short = Sps30Measurement::zeros().to_bytes();
+        let (report, sensor) = run(
+            6,
+            std::vec![
+                Exchange::command(READ_MEASURED_VALUE, &[], 0, &short[..20]),
+                Exchange::command(STOP_MEASUREMENT, &[], 0, &[]),
+            ],
+        );
+        assert_eq!(
+            stages(&report)[4..],
+            [
+                (Stage::StartMeasurement, true),
+                (Stage::FirstSample, false),
+                (Stage::StopMeasurement, true)
+            ]
+        );
+        assert!(!report.passed());
+        assert_eq!((report.sample, report.time_to_sample), (None, None));
+        assert_eq!(report.serial.as_deref(), Some(SERIAL));
+        assert!(!sensor.running);
+    }
+}
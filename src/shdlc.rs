@@ -0,0 +1,733 @@
+//! Sensirion SHDLC, the UART framing used by the SPS30 and other Sensirion
+//! parts such as the SVM40/SVM41.
+//!
+//! A MOSI frame is address, command, length, data and checksum; the MISO
+//! response adds a device state byte after the command. Both are delimited
+//! by 0x7e and byte stuffed. [`ShdlcTransceiver`] sends commands and reads
+//! their responses over any [`Transport`], knowing nothing about what the
+//! commands mean.
+//!
+//! The transceiver frames into fixed buffers of its own, so talking to the
+//! device doesn't allocate beyond the response data, and with the `heapless`
+//! feature not even that: [`Payload`] is then a fixed capacity vector.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use cfg_block::cfg_block;
+cfg_block! {
+    #[cfg(feature = "std")]{
+        use std::thread::sleep;
+        use std::time::Instant;
+        use log::{debug, warn};
+    }
+    #[cfg(feature = "no_std")]{
+        use defmt::{debug, warn};
+    }
+}
+
+use crate::event::{Event, EventFn};
+use crate::{DelayFn, Transport};
+
+pub const FEND: u8 = 0x7e;
+pub const FESC: u8 = 0x7d;
+
+/// Longest data field a frame can carry.
+pub const MAX_DATA: usize = 255;
+
+/// Longest MISO frame before stuffing: address, command, state, length, data
+/// and checksum.
+pub const MAX_FRAME: usize = MAX_DATA + 5;
+
+/// Longest MISO frame on the wire, with every byte stuffed, plus the two
+/// delimiters.
+pub const MAX_STUFFED_FRAME: usize = 2 * MAX_FRAME + 2;
+
+/// Bytes that are sent as [`FESC`] followed by their substitute.
+const STUFFED: [(u8, u8); 4] = [(FEND, 0x5e), (FESC, 0x5d), (0x11, 0x31), (0x13, 0x33)];
+
+/// Response data: a `Vec`, or with the `heapless` feature a vector of fixed
+/// capacity, which every response fits as the length byte caps it.
+#[cfg(not(feature = "heapless"))]
+pub type Payload = Vec<u8>;
+#[cfg(feature = "heapless")]
+pub type Payload = heapless::Vec<u8, MAX_DATA>;
+
+#[cfg(not(feature = "heapless"))]
+fn payload(data: &[u8]) -> Result<Payload, FrameError> {
+    Ok(data.to_vec())
+}
+#[cfg(feature = "heapless")]
+fn payload(data: &[u8]) -> Result<Payload, FrameError> {
+    Payload::from_slice(data).map_err(|_| FrameError {})
+}
+
+#[derive(Debug)]
+pub struct FrameError {}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FrameError")
+    }
+}
+
+/// SHDLC checksum: the inverted LSB of the sum of all bytes between the start
+/// and stop delimiters, before stuffing.
+pub fn checksum(buf: &[u8]) -> u8 {
+    let c = buf.iter().fold(0_u8, |acc, x| acc.wrapping_add(*x));
+    !c
+}
+
+/// A frame with its command as a plain byte.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawFrame {
+    pub addr: u8,
+    pub cmd: u8,
+    pub data: Payload,
+}
+
+/// Write `frame` into `out` byte stuffed and between delimiters, returning
+/// the number of bytes written.
+fn stuff(frame: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+    let mut n = 0;
+    let mut put = |b: u8| match out.get_mut(n) {
+        Some(o) => {
+            *o = b;
+            n += 1;
+            Ok(())
+        }
+        None => Err(FrameError {}),
+    };
+    put(FEND)?;
+    for &b in frame {
+        match STUFFED.iter().find(|(raw, _)| *raw == b) {
+            Some(&(_, sub)) => {
+                put(FESC)?;
+                put(sub)?;
+            }
+            None => put(b)?,
+        }
+    }
+    put(FEND)?;
+    Ok(n)
+}
+
+/// Undo [`stuff`]: write the bytes between the delimiters of `raw` into
+/// `out`, returning how many there are.
+fn unstuff(raw: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+    let inner = raw
+        .strip_prefix(&[FEND])
+        .and_then(|r| r.strip_suffix(&[FEND]))
+        .ok_or(FrameError {})?;
+    let mut n = 0;
+    let mut bytes = inner.iter();
+    while let Some(&b) = bytes.next() {
+        let b = match b {
+            FEND => return Err(FrameError {}),
+            FESC => {
+                let &sub = bytes.next().ok_or(FrameError {})?;
+                STUFFED
+                    .iter()
+                    .find(|(_, s)| *s == sub)
+                    .ok_or(FrameError {})?
+                    .0
+            }
+            b => b,
+        };
+        *out.get_mut(n).ok_or(FrameError {})? = b;
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// Build the frame for `header` and `data` into `out`, returning the number
+/// of stuffed bytes.
+fn encode_into(header: &[u8], data: &[u8], out: &mut [u8]) -> Result<usize, FrameError> {
+    if data.len() > MAX_DATA {
+        return Err(FrameError {});
+    }
+    let mut frame = [0u8; MAX_FRAME];
+    let h = header.len();
+    let end = h + 1 + data.len();
+    frame[..h].copy_from_slice(header);
+    frame[h] = data.len() as u8;
+    frame[h + 1..end].copy_from_slice(data);
+    frame[end] = checksum(&frame[..end]);
+
+    stuff(&frame[..=end], out)
+}
+
+fn encode(header: &[u8], data: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let mut out = [0u8; MAX_STUFFED_FRAME];
+    let n = encode_into(header, data, &mut out)?;
+    Ok(out[..n].to_vec())
+}
+
+/// Unstuff a frame, check its checksum and split the `header` bytes before the
+/// length byte from the data.
+fn decode(raw: &[u8], header: usize) -> Result<([u8; 3], Payload), FrameError> {
+    let mut d = [0u8; MAX_FRAME];
+    let n = unstuff(raw, &mut d).inspect_err(|_| warn!("Bad framing"))?;
+    if n < header + 2 {
+        warn!("Frame too short: {}", n);
+        return Err(FrameError {});
+    }
+
+    let (d, c) = (&d[..n - 1], d[n - 1]);
+    if c != checksum(d) {
+        warn!("Checksum error!");
+        return Err(FrameError {});
+    }
+
+    let l = d[header];
+    let data = &d[header + 1..];
+    if data.len() != l as usize {
+        warn!("Packet read: l({}) != d.len({})", l, data.len());
+        return Err(FrameError {});
+    }
+    let mut h = [0u8; 3];
+    h[..header].copy_from_slice(&d[..header]);
+    Ok((h, payload(data)?))
+}
+
+/// Build the stuffed MOSI bytes for a request, ready to be written to the
+/// port.
+pub fn encode_request(addr: u8, cmd: u8, data: &[u8]) -> Result<Vec<u8>, FrameError> {
+    encode(&[addr, cmd], data)
+}
+
+/// Parse a raw MOSI frame, as the device would receive it.
+pub fn decode_request(raw: &[u8]) -> Result<RawFrame, FrameError> {
+    let (h, data) = decode(raw, 2)?;
+    Ok(RawFrame {
+        addr: h[0],
+        cmd: h[1],
+        data,
+    })
+}
+
+/// Build the stuffed MISO bytes a device would answer with.
+pub fn encode_response(addr: u8, cmd: u8, state: u8, data: &[u8]) -> Result<Vec<u8>, FrameError> {
+    encode(&[addr, cmd, state], data)
+}
+
+/// Parse a raw MISO frame as read off the port, returning the device state
+/// byte along with the frame.
+pub fn decode_response(raw: &[u8]) -> Result<(u8, RawFrame), FrameError> {
+    let (h, data) = decode(raw, 3)?;
+    Ok((
+        h[2],
+        RawFrame {
+            addr: h[0],
+            cmd: h[1],
+            data,
+        },
+    ))
+}
+
+/// Which way a frame travelled, from the host's point of view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Tx => "TX",
+            Direction::Rx => "RX",
+        }
+    }
+}
+
+/// Called with the stuffed on-wire bytes of every frame sent or received.
+pub type TraceFn = fn(Direction, &[u8]);
+
+/// How often a command is resent when its response doesn't arrive in time or
+/// is corrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one.
+    pub retries: u8,
+    /// Pause before each resend, giving a device that is busy or recovering
+    /// from a glitch time to settle. Waited with the delay set by
+    /// [`ShdlcTransceiver::set_delay`], which on no_std must be set for
+    /// there to be any pause.
+    pub delay: Duration,
+}
+
+/// How often the port is polled while waiting for a response on no_std, in
+/// milliseconds.
+#[cfg(feature = "no_std")]
+const POLL_MS: u32 = 1;
+
+/// Size of the receive chunk buffer. Bytes are read from the port in chunks of
+/// up to this size to avoid a syscall per byte.
+const RX_CHUNK: usize = 128;
+
+/// Most bytes read from the port when dropping stale input: a late response
+/// and then some.
+const DRAIN_LIMIT: usize = 2 * MAX_STUFFED_FRAME;
+
+pub struct ShdlcTransceiver<P> {
+    port: P,
+    rx_buf: [u8; RX_CHUNK],
+    rx_pos: usize,
+    rx_len: usize,
+    /// The frame being received, from its opening delimiter.
+    rx_frame: [u8; MAX_STUFFED_FRAME],
+    rx_frame_len: usize,
+    tx_buf: [u8; MAX_STUFFED_FRAME],
+    trace: Option<TraceFn>,
+    on_event: Option<EventFn>,
+    retry: RetryPolicy,
+    attempts: u8,
+    timed_out: bool,
+    /// Set when a response went wrong, so whatever is left of it, or a late
+    /// answer to the same request, is discarded before the next request.
+    resync: bool,
+    /// Whether port reads return when nothing arrives, see
+    /// [`ShdlcTransceiver::set_reads_time_out`].
+    reads_time_out: bool,
+    delay: Option<DelayFn>,
+    /// Command of the last request sent, for the events about its response.
+    sent_cmd: u8,
+    #[cfg(feature = "std")]
+    sent_at: Option<Instant>,
+    #[cfg(feature = "std")]
+    last_latency: Option<Duration>,
+}
+
+impl<P: Transport> ShdlcTransceiver<P> {
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            rx_buf: [0; RX_CHUNK],
+            rx_pos: 0,
+            rx_len: 0,
+            rx_frame: [0; MAX_STUFFED_FRAME],
+            rx_frame_len: 0,
+            tx_buf: [0; MAX_STUFFED_FRAME],
+            trace: None,
+            on_event: None,
+            retry: RetryPolicy::default(),
+            attempts: 0,
+            timed_out: false,
+            resync: false,
+            reads_time_out: false,
+            delay: None,
+            sent_cmd: 0,
+            #[cfg(feature = "std")]
+            sent_at: None,
+            #[cfg(feature = "std")]
+            last_latency: None,
+        }
+    }
+    /// Round trip time of the last completed transaction, from the request
+    /// being written to its response being decoded.
+    #[cfg(feature = "std")]
+    pub fn last_latency(&self) -> Option<Duration> {
+        self.last_latency
+    }
+    /// Hand every frame to `trace` as it is sent or received, for debugging
+    /// protocol issues.
+    pub fn set_trace(&mut self, trace: Option<TraceFn>) {
+        self.trace = trace;
+    }
+    /// Resend commands according to `policy` when their response times out
+    /// or is corrupted. No retries are made by default.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+    /// Hand timeouts, corrupted responses and retries to `on_event`.
+    pub fn set_on_event(&mut self, on_event: Option<EventFn>) {
+        self.on_event = on_event;
+    }
+    /// Hand `event` to the event sink, if there is one.
+    pub fn emit(&self, event: Event) {
+        if let Some(on_event) = self.on_event {
+            on_event(&event);
+        }
+    }
+    /// How many times the last command was sent, including the first.
+    pub fn last_attempts(&self) -> u8 {
+        self.attempts
+    }
+    /// Whether the last response wasn't received because it timed out.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+    /// Use `delay` to pause between retries and, on no_std, to measure the
+    /// response timeout. On std it defaults to sleeping the thread.
+    pub fn set_delay(&mut self, delay: Option<DelayFn>) {
+        self.delay = delay;
+    }
+    fn wait(&self, d: Duration) {
+        if d.is_zero() {
+            return;
+        }
+        match self.delay {
+            Some(delay) => delay(d.as_millis() as u32),
+            #[cfg(feature = "std")]
+            None => sleep(d),
+            #[cfg(feature = "no_std")]
+            None => (),
+        }
+    }
+    /// Swap in a new port, returning the old one. Anything buffered from the
+    /// old port is dropped.
+    pub fn replace_port(&mut self, port: P) -> P {
+        self.rx_pos = 0;
+        self.rx_len = 0;
+        self.rx_frame_len = 0;
+        self.resync = false;
+        core::mem::replace(&mut self.port, port)
+    }
+    /// Tell the transceiver that reads from the port return, empty, when
+    /// nothing arrives within a read timeout of the port's own, so stale
+    /// input can be drained from it even though it can't report
+    /// [`Transport::read_ready`]. Set by [`crate::Sps30::set_timeout`].
+    pub fn set_reads_time_out(&mut self, reads_time_out: bool) {
+        self.reads_time_out = reads_time_out;
+    }
+    /// Drop anything received so far, and whatever the port has waiting if
+    /// it can be read without blocking, to start the next response on a
+    /// clean slate. Ports that can't tell only have their buffered bytes
+    /// dropped. At most [`DRAIN_LIMIT`] bytes are read, so a noisy line
+    /// can't keep this going.
+    fn discard_input(&mut self) -> Result<(), FrameError> {
+        let mut dropped = self.rx_len - self.rx_pos + self.rx_frame_len;
+        self.rx_pos = 0;
+        self.rx_len = 0;
+        self.rx_frame_len = 0;
+        let mut budget = DRAIN_LIMIT;
+        while budget > 0 && self.port.read_ready().unwrap_or(self.reads_time_out) {
+            let chunk = budget.min(RX_CHUNK);
+            match self
+                .port
+                .read(&mut self.rx_buf[..chunk])
+                .map_err(|_| FrameError {})?
+            {
+                0 => break,
+                n => {
+                    dropped += n;
+                    budget -= n;
+                }
+            }
+        }
+        if dropped > 0 {
+            debug!("Discarded {} stale bytes", dropped);
+        }
+        Ok(())
+    }
+    /// The port, for settings of its own such as its read timeout.
+    pub fn port_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+    /// Write bytes outside of any frame, such as a wake-up pulse.
+    pub fn write_raw(&mut self, data: &[u8]) -> Result<(), FrameError> {
+        self.port.write_all(data).map_err(|_| FrameError {})
+    }
+
+    /// Send a request frame. If the last response went wrong, anything still
+    /// arriving from it is discarded first.
+    pub fn send(&mut self, addr: u8, cmd: u8, data: &[u8]) -> Result<(), FrameError> {
+        let n = encode_into(&[addr, cmd], data, &mut self.tx_buf)?;
+        if self.resync {
+            self.discard_input()?;
+            self.resync = false;
+        }
+        self.sent_cmd = cmd;
+        let packet = &self.tx_buf[..n];
+        if let Some(trace) = self.trace {
+            trace(Direction::Tx, packet);
+        }
+
+        self.port.write_all(packet).map_err(|_| FrameError {})?;
+        #[cfg(feature = "std")]
+        {
+            self.sent_at = Some(Instant::now());
+        }
+
+        Ok(())
+    }
+    /// Next received byte, refilling the chunk buffer from the port once it has
+    /// been consumed. Returns None if the port has nothing to give us yet, and
+    /// an error if the port itself failed.
+    fn next_byte(&mut self) -> Result<Option<u8>, FrameError> {
+        if self.rx_pos == self.rx_len {
+            match self
+                .port
+                .read(&mut self.rx_buf)
+                .map_err(|_| FrameError {})?
+            {
+                0 => return Ok(None),
+                n => {
+                    self.rx_pos = 0;
+                    self.rx_len = n;
+                }
+            }
+        }
+        let b = self.rx_buf[self.rx_pos];
+        self.rx_pos += 1;
+        Ok(Some(b))
+    }
+    /// Assemble a delimited raw frame in `rx_frame`, returning its length
+    /// once the closing delimiter arrives. A partially received frame is kept,
+    /// and any bytes after the closing delimiter stay in the chunk buffer for
+    /// the next call. A frame growing past [`MAX_STUFFED_FRAME`] has lost its
+    /// delimiter and is dropped.
+    fn read_raw_frame(&mut self) -> Result<Option<usize>, FrameError> {
+        loop {
+            let Some(b) = self.next_byte()? else {
+                return Ok(None);
+            };
+            if b == FEND {
+                if self.rx_frame_len > 1 {
+                    self.rx_frame[self.rx_frame_len] = b;
+                    let n = self.rx_frame_len + 1;
+                    self.rx_frame_len = 0;
+                    return Ok(Some(n));
+                }
+                // opening delimiter, or back to back delimiters
+                self.rx_frame[0] = b;
+                self.rx_frame_len = 1;
+            } else if self.rx_frame_len > 0 {
+                // leave room for the closing delimiter
+                if self.rx_frame_len == MAX_STUFFED_FRAME - 1 {
+                    warn!("Frame longer than {} bytes, dropped", MAX_STUFFED_FRAME);
+                    self.rx_frame_len = 0;
+                    continue;
+                }
+                self.rx_frame[self.rx_frame_len] = b;
+                self.rx_frame_len += 1;
+            }
+        }
+    }
+    /// Wait for a response from `addr`, giving up `timeout` after the last
+    /// request was sent. Without a timeout this waits forever.
+    ///
+    /// no_std has no clock, so there the port is polled every millisecond
+    /// with the delay from [`ShdlcTransceiver::set_delay`], and the timeout
+    /// counts the time spent waiting. Without a delay it waits forever.
+    pub fn receive(
+        &mut self,
+        addr: u8,
+        timeout: Option<Duration>,
+    ) -> Result<(u8, RawFrame), FrameError> {
+        self.timed_out = false;
+        #[cfg(feature = "no_std")]
+        let mut waited = Duration::ZERO;
+        let n;
+        loop {
+            if let Some(len) = self.read_raw_frame()? {
+                n = len;
+                break;
+            }
+            let Some(timeout) = timeout else {
+                continue;
+            };
+            #[cfg(feature = "std")]
+            let expired = self.sent_at.is_some_and(|sent| sent.elapsed() > timeout);
+            #[cfg(feature = "no_std")]
+            let expired = match self.delay {
+                Some(delay) => {
+                    delay(POLL_MS);
+                    waited += Duration::from_millis(POLL_MS as u64);
+                    waited > timeout
+                }
+                None => false,
+            };
+            if expired {
+                warn!("No response within {}ms", timeout.as_millis() as u32);
+                self.timed_out = true;
+                self.emit(Event::Timeout { cmd: self.sent_cmd });
+                return Err(FrameError {});
+            }
+        }
+
+        #[cfg(feature = "std")]
+        {
+            self.last_latency = self.sent_at.map(|t| t.elapsed());
+        }
+
+        let frame = &self.rx_frame[..n];
+        if let Some(trace) = self.trace {
+            trace(Direction::Rx, frame);
+        }
+        let bad = Event::BadResponse { cmd: self.sent_cmd };
+        let (state, f) = decode_response(frame).inspect_err(|_| self.emit(bad))?;
+        if f.addr != addr {
+            warn!("Response from address {:x}, expected {:x}", f.addr, addr);
+            self.emit(bad);
+            return Err(FrameError {});
+        }
+        Ok((state, f))
+    }
+
+    /// Wait for the response to `cmd`, skipping late responses to earlier
+    /// commands.
+    fn receive_for(
+        &mut self,
+        addr: u8,
+        cmd: u8,
+        timeout: Option<Duration>,
+    ) -> Result<(u8, RawFrame), FrameError> {
+        loop {
+            let (state, f) = self.receive(addr, timeout)?;
+            if f.cmd == cmd {
+                return Ok((state, f));
+            }
+            warn!(
+                "Skipping response to command {:x}, expected {:x}",
+                f.cmd, cmd
+            );
+        }
+    }
+
+    /// Send a command and wait for its response, resending it as the retry
+    /// policy allows if the response times out, can't be decoded or carries
+    /// more than `expected_len` data bytes. Responses to other commands,
+    /// left over from earlier failures, are skipped. Returns the device state
+    /// byte and the response data.
+    ///
+    /// After a failed attempt whatever is left of its response is discarded
+    /// before resending, and again before the next command if the last
+    /// attempt failed too, so a late answer isn't taken for the next one.
+    pub fn transceive(
+        &mut self,
+        addr: u8,
+        cmd: u8,
+        data: &[u8],
+        expected_len: usize,
+        timeout: Option<Duration>,
+    ) -> Result<(u8, Payload), FrameError> {
+        self.attempts = 0;
+        loop {
+            self.attempts += 1;
+            self.timed_out = false;
+            let r = self
+                .send(addr, cmd, data)
+                .and_then(|_| self.receive_for(addr, cmd, timeout))
+                .and_then(|(state, f)| {
+                    if f.data.len() > expected_len {
+                        warn!("Response too long: {} > {}", f.data.len(), expected_len);
+                        self.emit(Event::BadResponse { cmd });
+                        Err(FrameError {})
+                    } else {
+                        Ok((state, f.data))
+                    }
+                });
+            match r {
+                Ok(r) => {
+                    debug!("State: {:x}, data: {:?}", r.0, &r.1[..]);
+                    if self.attempts > 1 {
+                        self.emit(Event::Recovered {
+                            cmd,
+                            attempts: self.attempts,
+                        });
+                    }
+                    return Ok(r);
+                }
+                Err(e) if self.attempts > self.retry.retries => {
+                    self.resync = true;
+                    return Err(e);
+                }
+                Err(_) => {
+                    warn!("Resending command {:x}, attempt {}", cmd, self.attempts + 1);
+                    self.emit(Event::Retry {
+                        cmd,
+                        attempt: self.attempts + 1,
+                    });
+                    self.wait(self.retry.delay);
+                    self.resync = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::{format, vec};
+
+    /// Any byte, with the ones that need stuffing turning up often.
+    fn byte() -> impl Strategy<Value = u8> {
+        prop_oneof![
+            any::<u8>(),
+            prop::sample::select(vec![FEND, FESC, 0x11, 0x13]),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn requests_round_trip(
+            addr in byte(),
+            cmd in byte(),
+            data in prop::collection::vec(byte(), 0..=MAX_DATA),
+        ) {
+            let raw = encode_request(addr, cmd, &data).unwrap();
+            prop_assert_eq!(raw.iter().filter(|&&b| b == FEND).count(), 2);
+            let f = decode_request(&raw).unwrap();
+            prop_assert_eq!(f.addr, addr);
+            prop_assert_eq!(f.cmd, cmd);
+            prop_assert_eq!(&f.data[..], &data[..]);
+        }
+
+        #[test]
+        fn responses_round_trip(
+            addr in byte(),
+            cmd in byte(),
+            state in byte(),
+            data in prop::collection::vec(byte(), 0..=MAX_DATA),
+        ) {
+            let raw = encode_response(addr, cmd, state, &data).unwrap();
+            let (s, f) = decode_response(&raw).unwrap();
+            prop_assert_eq!(s, state);
+            prop_assert_eq!(f.addr, addr);
+            prop_assert_eq!(f.cmd, cmd);
+            prop_assert_eq!(&f.data[..], &data[..]);
+        }
+
+        #[test]
+        fn decoding_arbitrary_bytes_never_panics(
+            raw in prop::collection::vec(byte(), 0..2 * MAX_STUFFED_FRAME),
+        ) {
+            let _ = decode_request(&raw);
+            let _ = decode_response(&raw);
+            let mut framed = vec![FEND];
+            framed.extend_from_slice(&raw);
+            framed.push(FEND);
+            let _ = decode_request(&framed);
+            let _ = decode_response(&framed);
+        }
+    }
+
+    #[test]
+    fn checksum_datasheet_examples() {
+        // Start Measurement request and its response
+        assert_eq!(checksum(&[0x00, 0x00, 0x02, 0x01, 0x03]), 0xf9);
+        assert_eq!(checksum(&[0x00, 0x00, 0x00, 0x00]), 0xff);
+        // Read Measured Values and Wake-up requests
+        assert_eq!(checksum(&[0x00, 0x03, 0x00]), 0xfc);
+        assert_eq!(checksum(&[0x00, 0x11, 0x00]), 0xee);
+    }
+
+    #[test]
+    fn checksum_keeps_the_low_byte_of_the_sum() {
+        assert_eq!(checksum(&[]), 0xff);
+        assert_eq!(checksum(&[0xff, 0x01]), 0xff);
+        assert_eq!(checksum(&[0x80, 0x80, 0x01]), 0xfe);
+        // 260 bytes of 0xff sum to 0x102fc, whose low byte 0xfc inverts to 0x03
+        assert_eq!(checksum(&[0xff; MAX_FRAME]), 0x03);
+    }
+
+    #[test]
+    fn too_much_data_is_refused() {
+        assert!(encode_request(0, 0, &[0; MAX_DATA + 1]).is_err());
+    }
+}
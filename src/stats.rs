@@ -0,0 +1,558 @@
+//! Smoothing and aggregating readings, without allocating, so the same
+//! helpers work on std and no_std.
+
+use core::fmt;
+use core::time::Duration;
+
+use crate::clock::Elapsed;
+use crate::{Sps30Measurement, Timestamped};
+
+#[cfg(feature = "schemars")]
+use std::prelude::rust_2024::*;
+
+const FIELDS: usize = 10;
+
+/// The `p`th percentile (0 to 100) of `values`, interpolating linearly
+/// between the closest ranks, or None if there are none. Sorts `values` in
+/// place.
+pub fn percentile(values: &mut [f32], p: f32) -> Option<f32> {
+    let last = values.len().checked_sub(1)?;
+    values.sort_unstable_by(f32::total_cmp);
+    let rank = p.clamp(0.0, 100.0) / 100.0 * last as f32;
+    // rank isn't negative, so truncating is floor, which isn't in core
+    let lo = rank as usize;
+    let hi = (lo + 1).min(last);
+    Some(values[lo] + (values[hi] - values[lo]) * (rank - lo as f32))
+}
+
+/// The last `N` readings, for a rolling mean. Gaps where the sensor had
+/// nothing new are simply not pushed.
+#[derive(Debug, Clone)]
+pub struct MeasurementWindow<const N: usize> {
+    samples: [[f32; FIELDS]; N],
+    /// Where the next sample goes, overwriting the oldest once full.
+    next: usize,
+    len: usize,
+}
+
+impl<const N: usize> MeasurementWindow<N> {
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "window must hold at least one reading") };
+        Self {
+            samples: [[0.0; FIELDS]; N],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Add a reading, dropping the oldest if the window is full.
+    pub fn push(&mut self, m: &Sps30Measurement) {
+        self.samples[self.next] = m.as_array();
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Number of readings in the window.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Whether the window holds `N` readings, so the mean covers its whole
+    /// span.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+
+    /// Per-field mean of the readings in the window, or None if it's empty.
+    pub fn mean(&self) -> Option<Sps30Measurement> {
+        if self.is_empty() {
+            return None;
+        }
+        // the oldest samples aren't overwritten until the window is full, so
+        // the first len slots are always the ones in use
+        let mut sum = [0f64; FIELDS];
+        for s in &self.samples[..self.len] {
+            for (acc, &v) in sum.iter_mut().zip(s) {
+                *acc += v as f64;
+            }
+        }
+        Some(Sps30Measurement::from_array(
+            sum.map(|s| (s / self.len as f64) as f32),
+        ))
+    }
+
+    /// Per-field `p`th percentile (0 to 100) of the readings in the window,
+    /// or None if it's empty. Exact, see [`percentile`].
+    pub fn percentile(&self, p: f32) -> Option<Sps30Measurement> {
+        let samples = &self.samples[..self.len];
+        let mut column = [0f32; N];
+        let mut out = [0f32; FIELDS];
+        for (i, o) in out.iter_mut().enumerate() {
+            for (c, s) in column.iter_mut().zip(samples) {
+                *c = s[i];
+            }
+            *o = percentile(&mut column[..samples.len()], p)?;
+        }
+        Some(Sps30Measurement::from_array(out))
+    }
+}
+
+impl<const N: usize> Default for MeasurementWindow<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exponential moving average of each field. Each update moves the filtered
+/// value `alpha` of the way towards the new reading, so smaller values
+/// smooth more.
+#[derive(Debug, Clone)]
+pub struct EmaFilter {
+    alpha: f32,
+    value: Option<[f32; FIELDS]>,
+}
+
+impl EmaFilter {
+    /// `alpha` is clamped to (0, 1]; 1 passes readings through unfiltered.
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(f32::MIN_POSITIVE, 1.0),
+            value: None,
+        }
+    }
+
+    /// Filter a new reading. The first one is taken as is, so the output
+    /// doesn't have to ramp up from zero.
+    pub fn update(&mut self, m: &Sps30Measurement) -> Sps30Measurement {
+        let new = m.as_array();
+        let value = match self.value {
+            Some(mut v) => {
+                for (v, n) in v.iter_mut().zip(new) {
+                    *v += self.alpha * (n - *v);
+                }
+                v
+            }
+            None => new,
+        };
+        self.value = Some(value);
+        Sps30Measurement::from_array(value)
+    }
+
+    /// The current filtered value, or None before the first update.
+    pub fn value(&self) -> Option<Sps30Measurement> {
+        self.value.map(Sps30Measurement::from_array)
+    }
+
+    /// Forget the filtered value, so the next reading starts it afresh.
+    pub fn reset(&mut self) {
+        self.value = None;
+    }
+}
+
+/// Per-field mean, minimum and maximum of readings since the last reset, as
+/// returned by [`Sps30Stats::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Sps30StatsReport {
+    pub mean: Sps30Measurement,
+    pub min: Sps30Measurement,
+    pub max: Sps30Measurement,
+    pub samples: u32,
+}
+
+impl fmt::Display for Sps30StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = f.precision().unwrap_or(1);
+        write!(
+            f,
+            "mean {:.*} min {:.*} max {:.*} ({} samples)",
+            p, self.mean, p, self.min, p, self.max, self.samples
+        )
+    }
+}
+
+/// Running min, max and mean of every reading pushed since the last reset,
+/// for reporting aggregates over a period rather than each reading.
+#[derive(Debug, Clone)]
+pub struct Sps30Stats {
+    sum: [f64; FIELDS],
+    min: [f32; FIELDS],
+    max: [f32; FIELDS],
+    count: u32,
+}
+
+impl Sps30Stats {
+    pub const fn new() -> Self {
+        Self {
+            sum: [0.0; FIELDS],
+            min: [f32::INFINITY; FIELDS],
+            max: [f32::NEG_INFINITY; FIELDS],
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, m: &Sps30Measurement) {
+        for (i, v) in m.as_array().into_iter().enumerate() {
+            self.sum[i] += v as f64;
+            self.min[i] = self.min[i].min(v);
+            self.max[i] = self.max[i].max(v);
+        }
+        self.count = self.count.saturating_add(1);
+    }
+
+    /// Number of readings pushed since the last reset.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// The aggregates so far, or None if nothing has been pushed. Doesn't
+    /// reset, so a report can be taken partway through a period.
+    pub fn snapshot(&self) -> Option<Sps30StatsReport> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as f64;
+        Some(Sps30StatsReport {
+            mean: Sps30Measurement::from_array(self.sum.map(|s| (s / n) as f32)),
+            min: Sps30Measurement::from_array(self.min),
+            max: Sps30Measurement::from_array(self.max),
+            samples: self.count,
+        })
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for Sps30Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One field's P² markers: heights `q` at actual positions `n`, which are
+/// nudged towards the desired positions `np` as readings arrive.
+#[derive(Debug, Clone, Copy)]
+struct P2 {
+    q: [f32; 5],
+    n: [f32; 5],
+    np: [f32; 5],
+}
+
+impl P2 {
+    const EMPTY: P2 = P2 {
+        q: [0.0; 5],
+        n: [0.0; 5],
+        np: [0.0; 5],
+    };
+
+    /// Add the `count`th reading, counting from 0, for quantile `p` (0 to 1).
+    fn push(&mut self, x: f32, count: u32, p: f32) {
+        if count < 5 {
+            self.q[count as usize] = x;
+            if count == 4 {
+                self.q.sort_unstable_by(f32::total_cmp);
+                self.n = [1.0, 2.0, 3.0, 4.0, 5.0];
+                self.np = [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0];
+            }
+            return;
+        }
+
+        if x < self.q[0] {
+            self.q[0] = x;
+        }
+        if x > self.q[4] {
+            self.q[4] = x;
+        }
+        // cell the reading falls in, 0 to 3
+        let k = self.q[1..4].iter().take_while(|&&q| q <= x).count();
+        for n in &mut self.n[k + 1..] {
+            *n += 1.0;
+        }
+        let dn = [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0];
+        for (np, dn) in self.np.iter_mut().zip(dn) {
+            *np += dn;
+        }
+
+        for i in 1..4 {
+            let (q, n) = (self.q, self.n);
+            let d = self.np[i] - n[i];
+            let room = if d > 0.0 {
+                n[i + 1] - n[i]
+            } else {
+                n[i] - n[i - 1]
+            };
+            if (d > -1.0 && d < 1.0) || room <= 1.0 {
+                continue;
+            }
+            let s = if d > 0.0 { 1.0 } else { -1.0 };
+            let parabolic = q[i]
+                + s / (n[i + 1] - n[i - 1])
+                    * ((n[i] - n[i - 1] + s) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                        + (n[i + 1] - n[i] - s) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]));
+            self.q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                parabolic
+            } else {
+                let j = if s > 0.0 { i + 1 } else { i - 1 };
+                q[i] + s * (q[j] - q[i]) / (n[j] - n[i])
+            };
+            self.n[i] += s;
+        }
+    }
+}
+
+/// Streaming estimate of one percentile of each field, using the P²
+/// algorithm (Jain and Chlamtac, 1985). Memory stays fixed however many
+/// readings are pushed, which suits long periods on no_std; use
+/// [`MeasurementWindow::percentile`] where the readings fit in memory and
+/// the exact value is wanted.
+#[derive(Debug, Clone)]
+pub struct PercentileEstimator {
+    /// Quantile, 0 to 1.
+    p: f32,
+    fields: [P2; FIELDS],
+    count: u32,
+}
+
+impl PercentileEstimator {
+    /// Estimate the `p`th percentile, clamped to 0 to 100.
+    pub fn new(p: f32) -> Self {
+        Self {
+            p: p.clamp(0.0, 100.0) / 100.0,
+            fields: [P2::EMPTY; FIELDS],
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, m: &Sps30Measurement) {
+        for (f, x) in self.fields.iter_mut().zip(m.as_array()) {
+            f.push(x, self.count, self.p);
+        }
+        self.count = self.count.saturating_add(1);
+    }
+
+    /// Number of readings pushed since the last reset.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// The current estimate, or None before the first reading. Until five
+    /// readings have arrived it's exact.
+    pub fn estimate(&self) -> Option<Sps30Measurement> {
+        if self.count == 0 {
+            return None;
+        }
+        let mut out = [0f32; FIELDS];
+        for (o, f) in out.iter_mut().zip(&self.fields) {
+            *o = if self.count < 5 {
+                let mut q = f.q;
+                percentile(&mut q[..self.count as usize], self.p * 100.0)?
+            } else {
+                f.q[2]
+            };
+        }
+        Some(Sps30Measurement::from_array(out))
+    }
+
+    pub fn reset(&mut self) {
+        self.fields = [P2::EMPTY; FIELDS];
+        self.count = 0;
+    }
+}
+
+/// Change between two consecutive readings, from [`MeasurementDelta`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Change {
+    /// Each field of the later reading less the earlier one.
+    pub delta: Sps30Measurement,
+    /// `delta` scaled to a minute, e.g. µg/m³ per minute for mass.
+    pub per_minute: Sps30Measurement,
+    /// Time between the two readings.
+    pub interval: Duration,
+}
+
+/// Per-field change and rate of change between consecutive readings, for
+/// spotting sudden rises. Readings may arrive at any interval; one that
+/// comes more than `max_gap` after the last, or with an earlier timestamp,
+/// starts afresh rather than being compared across the gap.
+#[derive(Debug, Clone)]
+pub struct MeasurementDelta<T> {
+    max_gap: Duration,
+    last: Option<Timestamped<T>>,
+}
+
+impl<T: Elapsed> MeasurementDelta<T> {
+    pub fn new(max_gap: Duration) -> Self {
+        Self {
+            max_gap,
+            last: None,
+        }
+    }
+
+    /// Take the next reading, returning its change from the last one, or
+    /// None for the first reading after a new, reset or gap.
+    pub fn update(&mut self, r: Timestamped<T>) -> Option<Change> {
+        let last = self.last.replace(r)?;
+        let r = self.last.as_ref()?;
+        let interval = r.timestamp.since(&last.timestamp)?;
+        if interval.is_zero() || interval > self.max_gap {
+            return None;
+        }
+        let delta = r.measurement - last.measurement;
+        Some(Change {
+            delta,
+            per_minute: delta * (60.0 / interval.as_secs_f32()),
+            interval,
+        })
+    }
+
+    /// Forget the last reading, so the next one starts afresh.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all(v: f32) -> Sps30Measurement {
+        Sps30Measurement::from_array([v; FIELDS])
+    }
+
+    #[test]
+    fn ema_starts_from_the_first_reading() {
+        let mut f = EmaFilter::new(0.1);
+        assert_eq!(f.value(), None);
+        assert_eq!(f.update(&all(42.0)), all(42.0));
+        assert_eq!(f.value(), Some(all(42.0)));
+    }
+
+    #[test]
+    fn ema_follows_hand_computed_sequences() {
+        let mut f = EmaFilter::new(0.5);
+        for (x, want) in [(10.0, 10.0), (20.0, 15.0), (20.0, 17.5), (0.0, 8.75)] {
+            assert_eq!(f.update(&all(x)), all(want), "after {}", x);
+        }
+
+        let mut f = EmaFilter::new(0.25);
+        for (x, want) in [(8.0, 8.0), (0.0, 6.0), (16.0, 8.5)] {
+            assert_eq!(f.update(&all(x)), all(want), "after {}", x);
+        }
+    }
+
+    #[test]
+    fn ema_filters_each_field_on_its_own() {
+        let mut f = EmaFilter::new(0.5);
+        let first: [f32; FIELDS] = core::array::from_fn(|i| i as f32);
+        f.update(&Sps30Measurement::from_array(first));
+        let out = f.update(&all(0.0)).as_array();
+        assert_eq!(out, first.map(|v| v / 2.0));
+    }
+
+    #[test]
+    fn ema_alpha_is_clamped() {
+        let mut f = EmaFilter::new(2.0);
+        f.update(&all(1.0));
+        assert_eq!(f.update(&all(7.0)), all(7.0));
+
+        let mut f = EmaFilter::new(0.0);
+        f.update(&all(1.0));
+        assert_eq!(f.update(&all(7.0)), all(1.0));
+    }
+
+    #[test]
+    fn ema_reset_restarts_from_the_next_reading() {
+        let mut f = EmaFilter::new(0.5);
+        f.update(&all(10.0));
+        f.reset();
+        assert_eq!(f.value(), None);
+        assert_eq!(f.update(&all(3.0)), all(3.0));
+    }
+
+    #[test]
+    fn percentile_interpolates_between_ranks() {
+        let mut values = [5.0, 1.0, 4.0, 2.0, 3.0];
+        for (p, want) in [
+            (0.0, 1.0),
+            (25.0, 2.0),
+            (50.0, 3.0),
+            (90.0, 4.6),
+            (100.0, 5.0),
+            (-10.0, 1.0),
+            (150.0, 5.0),
+        ] {
+            let got = percentile(&mut values, p).unwrap();
+            assert!((got - want).abs() < 1e-6, "p{}: {}", p, got);
+        }
+        assert_eq!(values, [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(percentile(&mut [7.0], 30.0), Some(7.0));
+        assert_eq!(percentile(&mut [], 50.0), None);
+    }
+
+    #[test]
+    fn window_percentile_covers_only_the_last_n() {
+        let mut w = MeasurementWindow::<3>::new();
+        assert_eq!(w.percentile(50.0), None);
+        for v in [100.0, 1.0, 3.0, 2.0] {
+            w.push(&all(v));
+        }
+        assert_eq!(w.percentile(50.0), Some(all(2.0)));
+        assert_eq!(w.percentile(100.0), Some(all(3.0)));
+    }
+
+    /// 0 to 999, each once, in a scrambled order.
+    fn scrambled() -> impl Iterator<Item = f32> {
+        (0..1000u32).map(|i| (i * 389 % 1000) as f32)
+    }
+
+    #[test]
+    fn estimator_is_exact_for_the_first_readings() {
+        let mut e = PercentileEstimator::new(50.0);
+        assert_eq!(e.estimate(), None);
+        for v in [3.0, 1.0, 2.0] {
+            e.push(&all(v));
+        }
+        assert_eq!(e.estimate(), Some(all(2.0)));
+        e.push(&all(10.0));
+        assert_eq!(e.estimate(), Some(all(2.5)));
+    }
+
+    #[test]
+    fn estimator_tracks_a_known_distribution() {
+        for (p, exact) in [(50.0, 499.5), (90.0, 899.1), (10.0, 99.9)] {
+            let mut e = PercentileEstimator::new(p);
+            for v in scrambled() {
+                e.push(&all(v));
+            }
+            assert_eq!(e.count(), 1000);
+            // P² is an estimate; within 1% of the range is plenty for readings
+            let got = e.estimate().unwrap().mass_2_5;
+            assert!((got - exact).abs() < 10.0, "p{}: {}", p, got);
+
+            let mut values: std::vec::Vec<f32> = scrambled().collect();
+            let reference = percentile(&mut values, p).unwrap();
+            assert!((reference - exact).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn estimator_reset_starts_over() {
+        let mut e = PercentileEstimator::new(50.0);
+        for v in scrambled() {
+            e.push(&all(v));
+        }
+        e.reset();
+        assert_eq!((e.count(), e.estimate()), (0, None));
+        e.push(&all(4.0));
+        assert_eq!(e.estimate(), Some(all(4.0)));
+    }
+}
@@ -0,0 +1,234 @@
+//! The byte transport the driver talks to the sensor through.
+//!
+//! [`Transport`] is implemented for every `std::io` port on std and every
+//! `embedded_io` port on no_std, with the two worlds' ways of saying "nothing
+//! arrived yet" mapped to the same thing. Wrappers such as
+//! [`crate::mock::MockPort`] and [`crate::faulty::FaultyPort`] implement it
+//! directly and work in either build.
+
+use core::fmt;
+
+use cfg_block::cfg_block;
+cfg_block! {
+    #[cfg(feature = "std")]{
+        use core::time::Duration;
+        use log::warn;
+    }
+    #[cfg(feature = "no_std")]{
+        use defmt::warn;
+    }
+}
+
+#[derive(Debug)]
+pub struct TransportError {}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TransportError")
+    }
+}
+
+pub trait Transport {
+    /// Read whatever has arrived into `buf`. Returns 0 if nothing arrived
+    /// before the port's own timeout, which isn't an error: the driver keeps
+    /// polling until its response timeout. Errors mean the port itself
+    /// failed.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError>;
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError>;
+    /// Whether a read would return data straight away, or None if the port
+    /// can't tell. The driver only reads speculatively, to drop stale input,
+    /// from ports that say so or whose reads are known to time out, since a
+    /// blocking read on an idle line never returns.
+    fn read_ready(&mut self) -> Option<bool> {
+        None
+    }
+}
+
+/// A port with a read timeout of its own, such as a serial port. Implemented
+/// so [`crate::Sps30::set_timeout`] can keep it in step with the driver's
+/// response timeout.
+#[cfg(feature = "std")]
+pub trait PortTimeout {
+    /// Make reads give up after `timeout` when nothing arrives.
+    fn set_port_timeout(&mut self, timeout: Duration) -> Result<(), TransportError>;
+}
+
+#[cfg(feature = "serialport")]
+impl PortTimeout for std::boxed::Box<dyn serialport::SerialPort> {
+    fn set_port_timeout(&mut self, timeout: Duration) -> Result<(), TransportError> {
+        serialport::SerialPort::set_timeout(self.as_mut(), timeout).map_err(|e| {
+            warn!("Can't set port timeout: {}", e);
+            TransportError {}
+        })
+    }
+}
+
+/// Ports that time out say so with an error, which is mapped to an empty
+/// read. `Ok(0)` is how `std::io` reports the end of a file, pipe or socket,
+/// which no more data will ever arrive from, so it's an error here rather
+/// than something to keep polling, which without a response timeout would
+/// spin forever.
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write> Transport for T {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        match std::io::Read::read(self, buf) {
+            Ok(0) if !buf.is_empty() => {
+                warn!("Port closed");
+                Err(TransportError {})
+            }
+            Ok(n) => Ok(n),
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::TimedOut
+                | std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::Interrupted => Ok(0),
+                _ => {
+                    warn!("Port read failed: {}", e);
+                    Err(TransportError {})
+                }
+            },
+        }
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+        std::io::Write::write_all(self, buf).map_err(|e| {
+            warn!("Port write failed: {}", e);
+            TransportError {}
+        })
+    }
+}
+
+#[cfg(feature = "no_std")]
+impl<T: embedded_io::Read + embedded_io::Write> Transport for T {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TransportError> {
+        use embedded_io::{Error, ErrorKind};
+        match embedded_io::Read::read(self, buf) {
+            Ok(n) => Ok(n),
+            Err(e) => match e.kind() {
+                ErrorKind::TimedOut | ErrorKind::Interrupted => Ok(0),
+                _ => {
+                    warn!("Port read failed");
+                    Err(TransportError {})
+                }
+            },
+        }
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), TransportError> {
+        embedded_io::Write::write_all(self, buf).map_err(|_| {
+            warn!("Port write failed");
+            TransportError {}
+        })
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::capture::{Record, RecordingPort, ReplayPort};
+    use crate::faulty::{FaultConfig, FaultyPort};
+    use crate::mock::{Exchange, MockPort};
+    use crate::{Direction, Sps30};
+    use alloc::collections::VecDeque;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use std::io;
+    use std::string::String;
+    use std::time::Instant;
+
+    const READ_VERSION: u8 = 0xd1;
+
+    fn version_exchange() -> Exchange {
+        Exchange::command(READ_VERSION, &[], 0, &[2, 2, 0, 7, 0, 2, 0])
+    }
+
+    /// Hardware version read through `port`, whatever it wraps.
+    fn hardware<P: Transport>(port: P) -> u8 {
+        Sps30::new(port).read_version().unwrap().hardware
+    }
+
+    #[test]
+    fn wrappers_work_through_transport() {
+        assert_eq!(hardware(MockPort::new(vec![version_exchange()])), 7);
+
+        let faulty = FaultyPort::new(
+            MockPort::new(vec![version_exchange()]),
+            FaultConfig::default(),
+        );
+        assert_eq!(hardware(faulty), 7);
+
+        // a std::io port, reaching Transport through the blanket impl
+        let e = version_exchange();
+        let replay = ReplayPort::new(vec![
+            Record {
+                micros: 0,
+                direction: Direction::Tx,
+                data: e.request,
+            },
+            Record {
+                micros: 10,
+                direction: Direction::Rx,
+                data: e.response,
+            },
+        ]);
+        let mut log = Vec::new();
+        assert_eq!(hardware(RecordingPort::new(replay, &mut log)), 7);
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.contains(" TX ") && log.contains(" RX "), "{}", log);
+    }
+
+    /// A std::io port whose reads give the results queued in `reads`, a
+    /// count filling that many bytes.
+    struct IoPort {
+        reads: VecDeque<io::Result<usize>>,
+    }
+
+    impl io::Read for IoPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.reads.pop_front().unwrap_or(Ok(0))?;
+            buf[..n].fill(0xaa);
+            Ok(n)
+        }
+    }
+
+    impl io::Write for IoPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn io_timeouts_are_empty_reads_and_eof_is_an_error() {
+        use io::ErrorKind::*;
+        let mut port = IoPort {
+            reads: VecDeque::from([
+                Err(TimedOut.into()),
+                Err(WouldBlock.into()),
+                Err(Interrupted.into()),
+                Ok(3),
+                Err(BrokenPipe.into()),
+                Ok(0),
+            ]),
+        };
+        let mut buf = [0u8; 8];
+        let mut read = || Transport::read(&mut port, &mut buf).ok();
+        assert_eq!(
+            [read(), read(), read(), read(), read(), read()],
+            [Some(0), Some(0), Some(0), Some(3), None, None]
+        );
+        assert_eq!(Transport::read(&mut port, &mut []).ok(), Some(0));
+    }
+
+    #[test]
+    fn a_closed_port_fails_instead_of_spinning() {
+        let mut sensor = Sps30::new(IoPort {
+            reads: VecDeque::new(),
+        });
+        // without a response timeout, only the error ends the wait
+        sensor.set_response_timeout(None);
+        let start = Instant::now();
+        assert!(sensor.read_version().is_err());
+        let waited = start.elapsed();
+        assert!(waited < crate::DEFAULT_RESPONSE_TIMEOUT, "{:?}", waited);
+    }
+}
@@ -0,0 +1,66 @@
+//! Readings as [uom] quantities, so units are carried through calculations
+//! rather than implied by field names.
+//!
+//! uom has no dedicated number concentration quantity, so those are
+//! [`VolumetricNumberDensity`], particles per volume.
+
+use uom::si::f32::{Length, MassConcentration, VolumetricNumberDensity};
+use uom::si::length::micrometer;
+use uom::si::mass_concentration::kilogram_per_cubic_meter;
+use uom::si::volumetric_number_density::per_cubic_meter;
+
+use crate::Sps30Measurement;
+
+/// A mass concentration in µg/m³, as the sensor reports them.
+pub fn mass_concentration(ug_per_m3: f32) -> MassConcentration {
+    MassConcentration::new::<kilogram_per_cubic_meter>(ug_per_m3 * 1e-9)
+}
+
+/// A number concentration in #/cm³, as the sensor reports them.
+pub fn number_concentration(per_cm3: f32) -> VolumetricNumberDensity {
+    VolumetricNumberDensity::new::<per_cubic_meter>(per_cm3 * 1e6)
+}
+
+/// A particle size in µm, as the sensor reports them.
+pub fn particle_size(um: f32) -> Length {
+    Length::new::<micrometer>(um)
+}
+
+/// [`Sps30Measurement`] with each field as a uom quantity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sps30Quantities {
+    pub mass_1_0: MassConcentration,
+    pub mass_2_5: MassConcentration,
+    pub mass_4_0: MassConcentration,
+    pub mass_10: MassConcentration,
+    pub number_pm0_5: VolumetricNumberDensity,
+    pub number_pm1_0: VolumetricNumberDensity,
+    pub number_pm2_5: VolumetricNumberDensity,
+    pub number_pm4_0: VolumetricNumberDensity,
+    pub number_pm10: VolumetricNumberDensity,
+    pub typical_particle_size: Length,
+}
+
+impl From<Sps30Measurement> for Sps30Quantities {
+    fn from(m: Sps30Measurement) -> Self {
+        Self {
+            mass_1_0: mass_concentration(m.mass_1_0),
+            mass_2_5: mass_concentration(m.mass_2_5),
+            mass_4_0: mass_concentration(m.mass_4_0),
+            mass_10: mass_concentration(m.mass_10),
+            number_pm0_5: number_concentration(m.number_pm0_5),
+            number_pm1_0: number_concentration(m.number_pm1_0),
+            number_pm2_5: number_concentration(m.number_pm2_5),
+            number_pm4_0: number_concentration(m.number_pm4_0),
+            number_pm10: number_concentration(m.number_pm10),
+            typical_particle_size: particle_size(m.typical_particle_size_um),
+        }
+    }
+}
+
+impl Sps30Measurement {
+    /// The reading as uom quantities.
+    pub fn quantities(&self) -> Sps30Quantities {
+        (*self).into()
+    }
+}
@@ -0,0 +1,85 @@
+//! The csv column names are what downstream spreadsheets and scripts key on,
+//! so they are pinned here exactly.
+#![cfg(feature = "serde")]
+
+use sps30_hdlc::{Sps30Measurement, Timestamped};
+
+const COLUMNS: [&str; 10] = [
+    "mass_1_0",
+    "mass_2_5",
+    "mass_4_0",
+    "mass_10",
+    "number_pm0_5",
+    "number_pm1_0",
+    "number_pm2_5",
+    "number_pm4_0",
+    "number_pm10",
+    "typical_particle_size_um",
+];
+
+fn readings() -> Vec<Sps30Measurement> {
+    vec![
+        Sps30Measurement {
+            mass_1_0: 1.0,
+            mass_2_5: 2.5,
+            mass_4_0: 4.0,
+            mass_10: 10.0,
+            number_pm0_5: 0.5,
+            number_pm1_0: 1.5,
+            number_pm2_5: 2.0,
+            number_pm4_0: 3.0,
+            number_pm10: 4.5,
+            typical_particle_size_um: 0.6,
+        },
+        Sps30Measurement {
+            mass_1_0: 0.0,
+            mass_2_5: 12.25,
+            mass_4_0: 13.0,
+            mass_10: 999.5,
+            number_pm0_5: 80.75,
+            number_pm1_0: 95.0,
+            number_pm2_5: 97.125,
+            number_pm4_0: 97.5,
+            number_pm10: 97.5,
+            typical_particle_size_um: 0.4375,
+        },
+    ]
+}
+
+fn write<T: serde::Serialize>(rows: &[T]) -> String {
+    let mut w = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        w.serialize(row).unwrap();
+    }
+    String::from_utf8(w.into_inner().unwrap()).unwrap()
+}
+
+#[test]
+fn measurements_round_trip_with_fixed_columns() {
+    let text = write(&readings());
+    assert_eq!(text.lines().next().unwrap(), COLUMNS.join(","));
+
+    let mut r = csv::Reader::from_reader(text.as_bytes());
+    assert_eq!(r.headers().unwrap(), &COLUMNS[..]);
+    let back: Vec<Sps30Measurement> = r.deserialize().map(Result::unwrap).collect();
+    assert_eq!(back, readings());
+}
+
+#[test]
+fn timestamped_rows_lead_with_the_timestamp() {
+    let rows: Vec<_> = readings()
+        .into_iter()
+        .zip([1_714_557_600_u64, 1_714_557_601])
+        .map(|(measurement, timestamp)| Timestamped {
+            timestamp,
+            measurement,
+        })
+        .collect();
+    let text = write(&rows);
+    let header = text.lines().next().unwrap();
+    assert_eq!(header, format!("timestamp,{}", COLUMNS.join(",")));
+
+    let mut r = csv::Reader::from_reader(text.as_bytes());
+    let back: Vec<Timestamped<u64>> = r.deserialize().map(Result::unwrap).collect();
+    assert_eq!(back, rows);
+}
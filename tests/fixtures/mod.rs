@@ -0,0 +1,75 @@
+//! Golden SHDLC frames, checked by the golden_frames tests and shared with
+//! the benchmarks.
+
+use sps30_hdlc::Command;
+
+/// MOSI frame for `ReadMeasuredValue` on address 0.
+pub const READ_MEASURED_VALUE_REQUEST: &[u8] = &[0x7e, 0x00, 0x03, 0x00, 0xfc, 0x7e];
+
+/// Worked MOSI examples from the SPS30 datasheet, as (command, data, frame).
+///
+/// `WakeUp` and the auto cleaning interval read cover byte stuffing of the
+/// command and the checksum respectively.
+pub const DATASHEET_REQUESTS: &[(Command, &[u8], &[u8])] = &[
+    (
+        Command::StartMeasurement,
+        &[0x01, 0x03],
+        &[0x7e, 0x00, 0x00, 0x02, 0x01, 0x03, 0xf9, 0x7e],
+    ),
+    (
+        Command::StopMeasurement,
+        &[],
+        &[0x7e, 0x00, 0x01, 0x00, 0xfe, 0x7e],
+    ),
+    (Command::ReadMeasuredValue, &[], READ_MEASURED_VALUE_REQUEST),
+    (Command::Sleep, &[], &[0x7e, 0x00, 0x10, 0x00, 0xef, 0x7e]),
+    (
+        Command::WakeUp,
+        &[],
+        &[0x7e, 0x00, 0x7d, 0x31, 0x00, 0xee, 0x7e],
+    ),
+    (
+        Command::StartFanCleaning,
+        &[],
+        &[0x7e, 0x00, 0x56, 0x00, 0xa9, 0x7e],
+    ),
+    (
+        Command::RWAutoCleaningInterval,
+        &[0x00],
+        &[0x7e, 0x00, 0x80, 0x01, 0x00, 0x7d, 0x5e, 0x7e],
+    ),
+    (
+        Command::DeviceInformation,
+        &[0x00],
+        &[0x7e, 0x00, 0xd0, 0x01, 0x00, 0x2e, 0x7e],
+    ),
+    (
+        Command::ReadVersion,
+        &[],
+        &[0x7e, 0x00, 0xd1, 0x00, 0x2e, 0x7e],
+    ),
+    (
+        Command::ReadDeviceStatusRegister,
+        &[0x00],
+        &[0x7e, 0x00, 0xd2, 0x01, 0x00, 0x2c, 0x7e],
+    ),
+    (Command::Reset, &[], &[0x7e, 0x00, 0xd3, 0x00, 0x2c, 0x7e]),
+];
+
+/// MISO answer to Start Measurement from the datasheet.
+pub const START_MEASUREMENT_RESPONSE: &[u8] = &[0x7e, 0x00, 0x00, 0x00, 0x00, 0xff, 0x7e];
+
+/// Decoded values of [`READ_MEASURED_VALUE_RESPONSE`], in wire order.
+pub const READ_MEASURED_VALUE_VALUES: [f32; 10] = [
+    12.5, 15.25, 16.0, 16.5, 70.125, 82.5, 84.0, 84.25, 84.5, 0.56640625,
+];
+
+/// MISO response to `ReadMeasuredValue` carrying a full 40 byte IEEE754 payload.
+///
+/// The typical particle size (0.566) encodes as `3f 11 00 00`, so the frame
+/// contains a stuffed `0x11` to exercise unstuffing.
+pub const READ_MEASURED_VALUE_RESPONSE: &[u8] = &[
+    0x7e, 0x00, 0x03, 0x00, 0x28, 0x41, 0x48, 0x00, 0x00, 0x41, 0x74, 0x00, 0x00, 0x41, 0x80, 0x00,
+    0x00, 0x41, 0x84, 0x00, 0x00, 0x42, 0x8c, 0x40, 0x00, 0x42, 0xa5, 0x00, 0x00, 0x42, 0xa8, 0x00,
+    0x00, 0x42, 0xa8, 0x80, 0x00, 0x42, 0xa9, 0x00, 0x00, 0x3f, 0x7d, 0x31, 0x00, 0x00, 0x8c, 0x7e,
+];
@@ -0,0 +1,65 @@
+//! The datasheet's example frames, encoded and decoded.
+
+use sps30_hdlc::{Command, Frame, Sps30Measurement, checksum};
+
+mod fixtures;
+
+#[test]
+fn requests_encode_as_in_the_datasheet() {
+    let f = Frame {
+        addr: 0,
+        cmd: Command::ReadMeasuredValue,
+        data: Vec::new(),
+    };
+    assert_eq!(f.encode().unwrap(), fixtures::READ_MEASURED_VALUE_REQUEST);
+    for (cmd, data, frame) in fixtures::DATASHEET_REQUESTS {
+        let f = Frame {
+            addr: 0,
+            cmd: *cmd,
+            data: data.to_vec(),
+        };
+        assert_eq!(f.encode().unwrap(), *frame, "{:?}", cmd);
+    }
+}
+
+#[test]
+fn requests_decode_as_in_the_datasheet() {
+    for (cmd, data, frame) in fixtures::DATASHEET_REQUESTS {
+        let f = Frame::decode_request(frame).unwrap();
+        assert_eq!(f.addr, 0);
+        assert_eq!(u8::from(f.cmd), u8::from(*cmd));
+        assert_eq!(f.data, *data, "{:?}", cmd);
+    }
+}
+
+#[test]
+fn start_measurement_response_decodes() {
+    let (state, f) = Frame::decode(fixtures::START_MEASUREMENT_RESPONSE).unwrap();
+    assert_eq!(state, 0);
+    assert_eq!(u8::from(f.cmd), u8::from(Command::StartMeasurement));
+    assert!(f.data.is_empty());
+}
+
+#[test]
+fn measurement_response_decodes() {
+    let (state, f) = Frame::decode(fixtures::READ_MEASURED_VALUE_RESPONSE).unwrap();
+    assert_eq!(state, 0);
+    let values: Vec<f32> = f
+        .data
+        .chunks(4)
+        .map(|b| f32::from_be_bytes(b.try_into().unwrap()))
+        .collect();
+    assert_eq!(values, fixtures::READ_MEASURED_VALUE_VALUES);
+    let m = Sps30Measurement::from_bytes(&f.data).unwrap().unwrap();
+    assert_eq!(m.as_array(), fixtures::READ_MEASURED_VALUE_VALUES);
+    assert_eq!(m.to_bytes().as_slice(), f.data.as_slice());
+}
+
+#[test]
+fn measurement_response_checksum() {
+    // unstuffed frame content, without delimiters or the checksum byte
+    let mut content = fixtures::READ_MEASURED_VALUE_RESPONSE[1..42].to_vec();
+    content.push(0x11);
+    content.extend_from_slice(&fixtures::READ_MEASURED_VALUE_RESPONSE[44..46]);
+    assert_eq!(checksum(&content), 0x8c);
+}
@@ -0,0 +1,42 @@
+//! `linux_test replay` exits 0 only when the whole capture played back.
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::Command;
+
+const SESSION: &str = include_str!("fixtures/sim_session.cap");
+
+fn replay(capture: &Path) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_linux_test"))
+        .arg("replay")
+        .arg(capture)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn a_complete_session_replays_cleanly() {
+    let capture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sim_session.cap");
+    assert_eq!(replay(&capture), 0);
+}
+
+#[test]
+fn a_session_that_stops_early_fails() {
+    // the capture expects a different Read Measured Value request than the
+    // driver sends, so replay stops at the first measurement
+    let edited = SESSION.replace("TX 7e 00 03 00 fc 7e", "TX 7e 00 03 00 fd 7e");
+    assert_ne!(edited, SESSION);
+    let capture = std::env::temp_dir().join(format!("sps30-replay-{}.cap", std::process::id()));
+    std::fs::write(&capture, edited).unwrap();
+    let code = replay(&capture);
+    std::fs::remove_file(&capture).unwrap();
+    assert_eq!(code, 1);
+}
+
+#[test]
+fn a_missing_capture_is_a_port_error() {
+    assert_eq!(replay(Path::new("tests/fixtures/no_such.cap")), 2);
+}
@@ -0,0 +1,44 @@
+//! A recorded session, replayed through the driver in the order linux_test
+//! sent it.
+#![cfg(feature = "std")]
+
+use sps30_hdlc::Sps30;
+use sps30_hdlc::capture::ReplayPort;
+
+const SESSION: &str = include_str!("fixtures/sim_session.cap");
+
+#[test]
+fn the_session_decodes() {
+    let mut sensor = Sps30::new(ReplayPort::from_capture(SESSION).unwrap());
+    sensor.device_reset().unwrap();
+    assert_eq!(sensor.get_device_info().unwrap(), "00080000");
+
+    let v = sensor.read_version().unwrap();
+    assert_eq!((v.firmware.major, v.firmware.minor), (2, 2));
+    assert_eq!(v.hardware, 7);
+    assert_eq!((v.shdlc.major, v.shdlc.minor), (2, 0));
+
+    sensor.start_measurement().unwrap();
+    assert!(sensor.read_and_clear_device_status().unwrap().is_healthy());
+    let m = sensor.read_measurement().unwrap().unwrap();
+    assert_eq!(
+        [m.mass_1_0, m.mass_2_5, m.mass_4_0, m.mass_10],
+        [10.0, 11.0, 11.5, 12.0]
+    );
+    assert_eq!(
+        [
+            m.number_pm0_5,
+            m.number_pm1_0,
+            m.number_pm2_5,
+            m.number_pm4_0,
+            m.number_pm10
+        ],
+        [60.0, 70.0, 71.0, 71.5, 72.0]
+    );
+    assert_eq!(m.typical_particle_size_um, 0.55);
+
+    assert!(sensor.read_and_clear_device_status().unwrap().is_healthy());
+    assert_eq!(sensor.read_measurement().unwrap(), None);
+    // nothing follows in the capture
+    assert!(sensor.stop_measurement().is_err());
+}